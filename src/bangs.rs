@@ -0,0 +1,167 @@
+//! DuckDuckGo style bang shortcuts (e.g. `!g rust traits` or `tokio !gh`).
+
+/// Where a bang sends a query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bang {
+    /// Search with only one of this crate's engines, identified by its id.
+    Engine(&'static str),
+    /// Redirect to another site, where `{}` in the url is replaced with the query.
+    Redirect(&'static str),
+}
+
+/// What a query's bang resolved to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BangResolution {
+    /// The query doesn't contain a known bang.
+    None,
+    /// The query should only be searched with the engine with this id (see [`crate::by_id`]).
+    Engine {
+        /// The id of the engine to search with.
+        engine_id: &'static str,
+        /// The query with the bang removed.
+        query: String,
+    },
+    /// The user should be redirected to this url instead of searching.
+    Redirect(String),
+}
+
+/// Looks up a bang by its name, without the leading `!` (e.g. `gh`).
+pub fn lookup(name: &str) -> Option<Bang> {
+    BANGS.get(name.to_lowercase().as_str()).copied()
+}
+
+/// Resolves the first known bang in a query. Unknown bangs are left in the query.
+pub fn resolve(query: &str) -> BangResolution {
+    let tokens = query.split_whitespace().collect::<Vec<_>>();
+
+    let Some((bang_idx, bang)) = tokens.iter().enumerate().find_map(|(idx, token)| {
+        token
+            .strip_prefix('!')
+            .and_then(lookup)
+            .map(|bang| (idx, bang))
+    }) else {
+        return BangResolution::None;
+    };
+
+    let query = tokens
+        .iter()
+        .enumerate()
+        .filter(|(idx, _)| *idx != bang_idx)
+        .map(|(_, token)| *token)
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    match bang {
+        Bang::Engine(engine_id) => BangResolution::Engine { engine_id, query },
+        Bang::Redirect(url) => BangResolution::Redirect(url.replace("{}", &encode(&query))),
+    }
+}
+
+/// Percent-encodes a query so it can be placed in a url.
+fn encode(query: &str) -> String {
+    let mut encoded = String::with_capacity(query.len());
+
+    for byte in query.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+
+    encoded
+}
+
+static BANGS: phf::Map<&'static str, Bang> = phf::phf_map! {
+    // Engines from this crate.
+    "b" => Bang::Engine("bing"),
+    "bing" => Bang::Engine("bing"),
+    "br" => Bang::Engine("brave"),
+    "brave" => Bang::Engine("brave"),
+    "g" => Bang::Engine("google"),
+    "google" => Bang::Engine("google"),
+    "mj" => Bang::Engine("mojeek"),
+    "mojeek" => Bang::Engine("mojeek"),
+    "y" => Bang::Engine("yahoo"),
+    "yahoo" => Bang::Engine("yahoo"),
+    "ya" => Bang::Engine("yandex"),
+    "yandex" => Bang::Engine("yandex"),
+
+    // Sites we don't scrape.
+    "a" => Bang::Redirect("https://www.amazon.com/s?k={}"),
+    "amazon" => Bang::Redirect("https://www.amazon.com/s?k={}"),
+    "crates" => Bang::Redirect("https://crates.io/search?q={}"),
+    "ddg" => Bang::Redirect("https://duckduckgo.com/?q={}"),
+    "docs" => Bang::Redirect("https://docs.rs/releases/search?query={}"),
+    "gh" => Bang::Redirect("https://github.com/search?q={}"),
+    "github" => Bang::Redirect("https://github.com/search?q={}"),
+    "maps" => Bang::Redirect("https://www.openstreetmap.org/search?query={}"),
+    "mdn" => Bang::Redirect("https://developer.mozilla.org/en-US/search?q={}"),
+    "r" => Bang::Redirect("https://www.reddit.com/search/?q={}"),
+    "reddit" => Bang::Redirect("https://www.reddit.com/search/?q={}"),
+    "so" => Bang::Redirect("https://stackoverflow.com/search?q={}"),
+    "w" => Bang::Redirect("https://en.wikipedia.org/wiki/Special:Search?search={}"),
+    "wiki" => Bang::Redirect("https://en.wikipedia.org/wiki/Special:Search?search={}"),
+    "yt" => Bang::Redirect("https://www.youtube.com/results?search_query={}"),
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_engine_bangs_anywhere_in_the_query() {
+        assert_eq!(
+            resolve("!g rust traits"),
+            BangResolution::Engine {
+                engine_id: "google",
+                query: String::from("rust traits"),
+            }
+        );
+        assert_eq!(
+            resolve("tokio  !BR"),
+            BangResolution::Engine {
+                engine_id: "brave",
+                query: String::from("tokio"),
+            }
+        );
+    }
+
+    #[test]
+    fn encodes_the_query_in_redirect_urls() {
+        assert_eq!(
+            resolve("!gh serde json&yaml"),
+            BangResolution::Redirect(String::from(
+                "https://github.com/search?q=serde%20json%26yaml"
+            ))
+        );
+        assert_eq!(
+            resolve("!w café"),
+            BangResolution::Redirect(String::from(
+                "https://en.wikipedia.org/wiki/Special:Search?search=caf%C3%A9"
+            ))
+        );
+    }
+
+    #[test]
+    fn leaves_unknown_bangs_in_the_query() {
+        assert_eq!(resolve("rust !nope"), BangResolution::None);
+        assert_eq!(
+            resolve("!nope rust !mj"),
+            BangResolution::Engine {
+                engine_id: "mojeek",
+                query: String::from("!nope rust"),
+            }
+        );
+    }
+
+    #[test]
+    fn only_sends_engine_bangs_to_engines_which_exist() {
+        for bang in BANGS.values() {
+            if let Bang::Engine(engine_id) = bang {
+                assert!(crate::by_id(engine_id).is_some(), "{engine_id}");
+            }
+        }
+    }
+}
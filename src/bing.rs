@@ -1,26 +1,50 @@
 use std::borrow::Cow;
 
+use anyhttp::Response;
 use chrono::{TimeZone, Utc};
-use html_hybrid_parser::{ClassName, ClassNames, Node, Query, class_names_any, class_names_exact};
-use http::{
-    HeaderMap, HeaderValue,
-    header::{ACCEPT, CONTENT_TYPE, COOKIE, REFERER, USER_AGENT},
+use html_hybrid_parser::{
+    ClassName, ClassNames, Node, Query, QueryClassNames, class_names_any, class_names_exact,
 };
+use http::HeaderMap;
 
 use quaero_shared::models::{
     engine::{Engine, TaggedEngine},
     search::{SearchError, SearchOptions, SearchResult},
-    user_agent::UserAgent,
 };
 use query_parameters::query_params;
 
+use crate::{
+    config::EngineConfig,
+    counts::parse_total,
+    dates::parse_date,
+    engine::{RichEngine, parse_for_quaero},
+    error::ParseError,
+    health::{Field, ParseHealth, SelectorHits, Selectors, first_match},
+    overrides::AnyOf,
+    pacing::RequestBudget,
+    profiles::BrowserProfile,
+    results::{
+        ParsedPage, RichSearchResult, Sitelink, ad_positions, filter_tracking_params_in_url,
+    },
+    spans::{align_spans, collect_spans},
+    warmup::input_value,
+};
+
 /// An engine which parses search results from Bing.
-pub struct BingEngine;
+#[derive(Default)]
+pub struct BingEngine {
+    config: EngineConfig,
+}
 
 impl BingEngine {
     /// Creates a new Bing engine.
     pub fn new() -> TaggedEngine {
-        TaggedEngine::new(Self {})
+        Self::with_config(EngineConfig::default())
+    }
+
+    /// Creates a new Bing engine with a custom config.
+    pub fn with_config(config: EngineConfig) -> TaggedEngine {
+        TaggedEngine::new(Self { config })
     }
 }
 
@@ -39,9 +63,11 @@ impl Engine for BingEngine {
             date_time_range,
         }: &SearchOptions,
     ) -> Result<String, SearchError> {
+        let query = self.render_query(query);
+
         // Turns the page number into the index of the first result.
         // Page 0 is `1`, Page 1 is `11`, Page 2 is `21`, etc...
-        let results_per_page = 10;
+        let results_per_page = self.results_per_page();
         let page_start_idx = results_per_page * page_num + 1;
 
         let date_time_range_param = if let Some(range) = date_time_range {
@@ -56,106 +82,649 @@ impl Engine for BingEngine {
             Cow::Borrowed("")
         };
 
+        let form = self
+            .config
+            .warm_up
+            .as_ref()
+            .and_then(|this| this.token("form"))
+            .unwrap_or_else(|| DEFAULT_FORM.to_string());
+
+        let results_per_page_param = self.config.results_per_page_param(
+            "count",
+            DEFAULT_RESULTS_PER_PAGE,
+            MAX_RESULTS_PER_PAGE,
+        );
+
         let query_params = query_params! {
             "q" => query,
             "first" => page_start_idx,
-            "form" => "QBLH",
+            "form" => form,
             "safeSearch" => safe_search.as_lowercase_string()
         };
 
         Ok(format!(
-            "https://www.bing.com/search?{query_params}{date_time_range_param}"
+            "https://www.bing.com/search?{query_params}{results_per_page_param}{date_time_range_param}"
         ))
     }
 
     fn headers(&self, headers: &mut HeaderMap, _options: &SearchOptions) {
-        headers.insert(USER_AGENT, UserAgent::random_no_js().into());
-        headers.insert(
-            ACCEPT,
-            HeaderValue::from_static(
-                "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8",
-            ),
+        self.config
+            .browser_profile
+            .unwrap_or_else(BrowserProfile::random_no_js)
+            .apply(headers, self.homepage(), Some("https://google.com/"));
+        self.config.apply_cookies(
+            headers,
+            self.homepage(),
+            "_EDGE_V=1; SRCHD=AF=NOFORM; _Rwho=u=d; bngps=s=0; _UR=QS=0&TQS=0",
         );
-        headers.append(
-            CONTENT_TYPE,
-            HeaderValue::from_static("application/x-www-form-urlencoded"),
-        );
-        headers.append(
-            COOKIE,
-            HeaderValue::from_static(
-                "_EDGE_V=1; SRCHD=AF=NOFORM; _Rwho=u=d; bngps=s=0; _UR=QS=0&TQS=0; ",
-            ),
-        );
-        headers.append(REFERER, HeaderValue::from_static("https://google.com/"));
+    }
+
+    fn validate_response(&self, response: &Response) -> Result<(), SearchError> {
+        self.config.store_cookies(response);
+        Ok(())
     }
 
     fn parse<'a>(&self, response_text: String) -> Result<Vec<(String, SearchResult)>, SearchError> {
+        parse_for_quaero(self, response_text)
+    }
+}
+
+impl RichEngine for BingEngine {
+    fn id(&self) -> &str {
+        "bing"
+    }
+
+    fn config(&self) -> &EngineConfig {
+        &self.config
+    }
+
+    fn request_budget(&self) -> RequestBudget {
+        REQUEST_BUDGET
+    }
+
+    fn results_per_page(&self) -> usize {
+        self.config
+            .results_per_page(DEFAULT_RESULTS_PER_PAGE, MAX_RESULTS_PER_PAGE)
+    }
+
+    // The search form on Bing's homepage says which page the search came from.
+    fn warm_up_tokens(&self, homepage: &str) -> Vec<(String, String)> {
+        input_value(homepage, "form")
+            .map(|form| ("form".to_string(), form))
+            .into_iter()
+            .collect()
+    }
+
+    fn parse_rich(&self, response_text: String) -> Result<ParsedPage, ParseError> {
         let dom = html_hybrid_parser::Parser::fast_but_constrained(&response_text);
         let parser = dom.parser();
 
-        let nodes = dom.get_nodes_with_classes(&SEARCH_RESULT_CLASSES, parser);
+        let overrides = self.config.selectors(self.id());
+        let search_result_classes =
+            overrides.classes("SEARCH_RESULT_CLASSES", &SEARCH_RESULT_CLASSES);
+        let title_classes = overrides.classes("TITLE_CLASSES", &TITLE_CLASSES);
+        let text_summary_wrapper_classes = overrides.classes(
+            "TEXT_SUMMARY_WRAPPER_CLASSES",
+            &TEXT_SUMMARY_WRAPPER_CLASSES,
+        );
+        let text_summary_classes = overrides.classes("TEXT_SUMMARY_CLASSES", &TEXT_SUMMARY_CLASSES);
+
+        let nodes = dom
+            .get_nodes_with_classes(&search_result_classes, parser)
+            .collect::<Vec<_>>();
+        let containers = nodes.len();
+        let mut selector_hits = SelectorHits::default();
+        let now = Utc::now();
 
-        Ok(nodes
-            .filter_map(|this| {
-                let Some(title_node) = this.get_first_node_with_classes(&TITLE_CLASSES, parser)
+        let results = nodes
+            .into_iter()
+            .map(|this| {
+                let Some(title_node) = this.get_first_node_with_classes(&title_classes, parser)
                 else {
                     return None;
                 };
 
-                let title = title_node
-                    .text(parser)
-                    .map(|this| this.to_string())
-                    .unwrap_or_default();
+                let title = first_match!(selector_hits, Field::Title, {
+                    "TITLE_CLASSES" => title_node.text(parser).map(|this| this.to_string()),
+                })
+                .unwrap_or_default();
 
-                let url = title_node
-                    .get_first_node_with_tag("a", parser)
-                    .and_then(|this| this.get_href().map(|this| this.into_owned()))
-                    .unwrap_or_default();
+                let url = first_match!(selector_hits, Field::Url, {
+                    "{TITLE_CLASSES} a" => title_node
+                        .get_first_node_with_tag("a", parser)
+                        .and_then(|this| this.get_href().map(|this| this.into_owned())),
+                })
+                .unwrap_or_default();
 
-                let summary = this
-                    .get_first_node_with_classes(&TEXT_SUMMARY_WRAPPER_CLASSES, parser)
+                let summary_spans = this
+                    .get_first_node_with_classes(&text_summary_wrapper_classes, parser)
                     .and_then(|this| {
-                        this.get_first_node_with_classes(&TEXT_SUMMARY_CLASSES, parser)
-                            .and_then(|this| {
-                                let Some(text) = this.children_raw_text(parser) else {
-                                    return None;
-                                };
-                                match text {
-                                    Cow::Owned(this) => Some(
-                                        this.strip_prefix("\u{a0}· ").unwrap_or(&this).to_string(),
-                                    ),
-                                    Cow::Borrowed(this) => Some(
-                                        this.strip_prefix("\u{a0}· ").unwrap_or(&this).to_string(),
-                                    ),
-                                }
-                            })
+                        this.get_first_node_with_classes(&text_summary_classes, parser)
+                    })
+                    .map(|this| {
+                        collect_spans(
+                            this.get_child_nodes(parser)
+                                .map(|this| (this.tag_name(), this.text(parser))),
+                        )
                     })
-                    // If we can't find a summary then the result may have cards instead of basic text.
-                    .unwrap_or_else(|| {
-                        this.get_first_node_with_classes(&CARD_SUMMARY_CLASSES, parser)
-                            .and_then(|this| {
-                                this.get_child_nodes_with_classes(
-                                    &CARD_SUMMARY_CONTENT_CLASSES,
-                                    parser,
-                                )
-                                .nth(1)
-                                .and_then(|this| this.text(parser).map(|this| this.to_string()))
+                    .unwrap_or_default();
+
+                let summary = first_match!(selector_hits, Field::Summary, {
+                    "TEXT_SUMMARY_CLASSES" => this
+                        .get_first_node_with_classes(&text_summary_wrapper_classes, parser)
+                        .and_then(|this| {
+                            this.get_first_node_with_classes(&text_summary_classes, parser)
+                        })
+                        .and_then(|this| this.children_raw_text(parser))
+                        .map(|this| this.strip_prefix("\u{a0}· ").unwrap_or(&this).to_string()),
+                    // The result may have cards instead of basic text.
+                    "CARD_SUMMARY_CLASSES" => this
+                        .get_first_node_with_classes(&CARD_SUMMARY_CLASSES, parser)
+                        .and_then(|this| {
+                            this.get_child_nodes_with_classes(
+                                &CARD_SUMMARY_CONTENT_CLASSES,
+                                parser,
+                            )
+                            .nth(1)
+                        })
+                        .and_then(|this| this.text(parser).map(|this| this.to_string())),
+                })
+                .unwrap_or_default();
+
+                // News and dated results show their date in a span before the summary.
+                let date = first_match!(selector_hits, Field::Date, {
+                    "DATE_CLASSES" => this
+                        .get_first_node_with_classes(&DATE_CLASSES, parser)
+                        .and_then(|this| this.text(parser).and_then(|this| parse_date(&this, now))),
+                });
+
+                // Deep links are shown as a list underneath the main result.
+                let sitelinks = this
+                    .get_first_node_with_classes(&SITELINKS_WRAPPER_CLASSES, parser)
+                    .and_then(|this| this.get_first_node_with_tag("ul", parser))
+                    .map(|this| {
+                        this.get_child_nodes(parser)
+                            .filter_map(|this| {
+                                let link = this.get_first_node_with_tag("a", parser)?;
+
+                                let title = link.text(parser)?.trim().to_string();
+                                let url = link.get_href()?;
+
+                                let description =
+                                    this.get_first_node_with_tag("p", parser).and_then(|this| {
+                                        this.text(parser).map(|this| this.to_string())
+                                    });
+
+                                Some(Sitelink::new(
+                                    title,
+                                    &url,
+                                    description,
+                                    filter_tracking_params_in_url,
+                                ))
                             })
-                            .unwrap_or_default()
-                    });
+                            .collect()
+                    })
+                    .unwrap_or_default();
+
+                let summary_spans = align_spans(summary_spans, &summary);
+
+                Some(RichSearchResult {
+                    summary_spans,
+                    sitelinks,
+                    date,
+                    ..RichSearchResult::new(title, url, summary)
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let ad_positions = ad_positions(
+            &results,
+            dom.get_nodes_with_classes(&AnyOf(&search_result_classes, &AD_CLASSES), parser)
+                .map(|this| AD_CLASSES.matches(this.class())),
+        );
+
+        let mut page = ParsedPage::new(results.into_iter().flatten().collect());
+        page.health = ParseHealth::new(&SELECTORS, &overrides, containers, selector_hits);
 
-                Some(SearchResult::new(title, url, summary))
+        // Ads are grouped into blocks at the top and bottom of the page.
+        let ads = dom
+            .get_nodes_with_classes(&AD_CLASSES, parser)
+            .zip(ad_positions)
+            .flat_map(|(this, position)| {
+                this.get_nodes_with_classes(&AD_ITEM_CLASSES, parser)
+                    .map(move |this| (position, this))
             })
-            .collect())
+            .filter_map(|(position, this)| {
+                let link = this
+                    .get_first_node_with_tag("h2", parser)?
+                    .get_first_node_with_tag("a", parser)?;
+
+                let title = link.text(parser)?.to_string();
+                let url = link.get_href()?.into_owned();
+
+                let summary = this
+                    .get_first_node_with_classes(&text_summary_wrapper_classes, parser)
+                    .and_then(|this| this.text(parser).map(|this| this.to_string()))
+                    .unwrap_or_default();
+
+                Some((position, RichSearchResult::new(title, url, summary)))
+            });
+        page.add_ads(ads, self.config.ads);
+
+        page.estimated_total = dom
+            .get_first_node_with_classes(&RESULT_COUNT_CLASSES, parser)
+            .and_then(|this| this.text(parser).and_then(|this| parse_total(&this)));
+
+        page.has_next_page = Some(
+            dom.get_first_node_with_classes(&NEXT_PAGE_CLASSES, parser)
+                .is_some(),
+        );
+
+        Ok(page)
     }
 }
 
+const DEFAULT_RESULTS_PER_PAGE: usize = 10;
+const MAX_RESULTS_PER_PAGE: usize = 50;
+/// The `form` code of searches from Bing's homepage, used until a warm-up finds the current one.
+const DEFAULT_FORM: &str = "QBLH";
+
+const REQUEST_BUDGET: RequestBudget = RequestBudget::per_minute(20);
+
+pub(crate) const SELECTORS: Selectors = Selectors {
+    container: "{SEARCH_RESULT_CLASSES}",
+    title: "{TITLE_CLASSES}",
+    url: "{TITLE_CLASSES} a",
+    summary: "{TEXT_SUMMARY_WRAPPER_CLASSES} {TEXT_SUMMARY_CLASSES}",
+    classes: &[
+        ("SEARCH_RESULT_CLASSES", ".b_algo"),
+        ("TITLE_CLASSES", ".b_algoheader"),
+        ("TEXT_SUMMARY_WRAPPER_CLASSES", ".b_caption.b_capmedia"),
+        ("TEXT_SUMMARY_CLASSES", ".b_lineclamp3"),
+    ],
+};
+
 const SEARCH_RESULT_CLASSES: ClassName = class_names_any! { "b_algo" };
 
+const AD_CLASSES: ClassName = class_names_any! { "b_ad" };
+const AD_ITEM_CLASSES: ClassName = class_names_any! { "sb_add" };
+
 const TITLE_CLASSES: ClassName = class_names_any! { "b_algoheader" };
 
 const TEXT_SUMMARY_WRAPPER_CLASSES: ClassNames = class_names_exact! { "b_caption", "b_capmedia" };
 const TEXT_SUMMARY_CLASSES: ClassName = class_names_exact! { "b_lineclamp3" };
 
+const DATE_CLASSES: ClassName = class_names_exact! { "news_dt" };
+
 const CARD_SUMMARY_CLASSES: ClassNames = class_names_exact! { "b_cards2", "slide" };
 const CARD_SUMMARY_CONTENT_CLASSES: ClassName = class_names_exact! { "exsni" };
+
+const RESULT_COUNT_CLASSES: ClassName = class_names_any! { "sb_count" };
+const NEXT_PAGE_CLASSES: ClassName = class_names_any! { "sb_pagN" };
+
+const SITELINKS_WRAPPER_CLASSES: ClassName = class_names_any! { "b_deep" };
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use quaero_shared::models::search::SafeSearch;
+
+    use std::time::Duration;
+
+    use crate::{
+        config::AdHandling,
+        cookies::CookieJar,
+        fetch::fetch_page,
+        testing::{FakeFetcher, options, response, selector_overrides},
+        warmup::WarmUp,
+    };
+
+    fn parse(html: &str) -> ParsedPage {
+        BingEngine::default()
+            .parse_rich(html.to_string())
+            .expect("page should parse")
+    }
+
+    #[test]
+    fn parses_the_estimated_total_on_the_first_page() {
+        let page = parse(
+            r#"<html><body><span class="sb_count">About 1,234 results</span>
+            <a class="sb_pagN" href="/search?q=rust&first=11">Next</a></body></html>"#,
+        );
+
+        assert_eq!(page.estimated_total, Some(1_234));
+        assert_eq!(page.has_next_page, Some(true));
+    }
+
+    #[test]
+    fn parses_the_estimated_total_on_later_pages() {
+        let page = parse(
+            r#"<html><body><span class="sb_count">11-20 of 1,234 results</span></body></html>"#,
+        );
+
+        assert_eq!(page.estimated_total, Some(1_234));
+        assert_eq!(page.has_next_page, Some(false));
+    }
+
+    #[test]
+    fn parses_deep_links_under_a_result() {
+        let page = parse(
+            r#"<html><body><ol id="b_results">
+            <li class="b_algo"><div class="b_algoheader"><a href="https://www.rust-lang.org/"><h2>Rust</h2></a></div>
+                <div class="b_deep"><ul>
+                    <li><a href="https://www.rust-lang.org/learn?utm_source=bing">Learn</a><p>Get started with Rust.</p></li>
+                    <li><a href="https://www.rust-lang.org/tools/install">Install</a></li>
+                </ul></div></li>
+            <li class="b_algo"><div class="b_algoheader"><a href="https://doc.rust-lang.org/"><h2>Docs</h2></a></div></li>
+            </ol></body></html>"#,
+        );
+
+        let sitelinks = page.results[0]
+            .sitelinks
+            .iter()
+            .map(|this| (this.title.as_str(), this.description.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sitelinks,
+            [("Learn", Some("Get started with Rust.")), ("Install", None)]
+        );
+        assert!(page.results[1].sitelinks.is_empty());
+    }
+
+    #[test]
+    fn keeps_labelled_ads_where_they_were_shown() {
+        let engine = BingEngine {
+            config: EngineConfig {
+                ads: AdHandling::Label,
+                ..EngineConfig::default()
+            },
+        };
+
+        let page = engine
+            .parse_rich(
+                r#"<html><body><ol id="b_results">
+                <li class="b_ad"><ul>
+                    <li class="sb_add"><h2><a href="https://training.example/rust">Rust Training</a></h2></li>
+                    <li class="sb_add"><h2><a href="https://jobs.example/rust">Rust Jobs</a></h2></li>
+                </ul></li>
+                <li class="b_algo"><div class="b_algoheader"><a href="https://www.rust-lang.org/"><h2>Rust</h2></a></div></li>
+                <li class="b_ad"><ul>
+                    <li class="sb_add"><h2><a href="https://books.example/rust">Rust Books</a></h2></li>
+                </ul></li>
+                <li class="b_algo"><div class="b_algoheader"><a href="https://doc.rust-lang.org/"><h2>Docs</h2></a></div></li>
+                </ol></body></html>"#
+                    .to_string(),
+            )
+            .unwrap();
+
+        let titles = page
+            .results
+            .iter()
+            .map(|this| (this.title.as_str(), this.sponsored))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            titles,
+            [
+                ("Rust Training", true),
+                ("Rust Jobs", true),
+                ("Rust", false),
+                ("Rust Books", true),
+                ("Docs", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn treats_a_page_without_results_as_healthy() {
+        let page = parse(r#"<html><body><ol id="b_results"></ol></body></html>"#);
+
+        assert_eq!(page.health.containers, 0);
+        assert!(page.health.is_healthy());
+    }
+
+    #[test]
+    fn reports_a_title_selector_which_no_longer_matches() {
+        let page = parse(
+            r#"<html><body><ol id="b_results">
+            <li class="b_algo"><div class="b_title"><a href="https://www.rust-lang.org/"><h2>Rust</h2></a></div></li>
+            <li class="b_algo"><div class="b_title"><a href="https://doc.rust-lang.org/"><h2>Docs</h2></a></div></li>
+            </ol></body></html>"#,
+        );
+
+        assert_eq!(page.health.containers, 2);
+        assert_eq!(page.health.missing(Field::Title), 2);
+        assert_eq!(page.health.missing(Field::Url), 2);
+        assert!(!page.health.is_healthy());
+    }
+
+    #[test]
+    fn reports_the_overridden_selectors_in_the_parse_health() {
+        let engine = BingEngine {
+            config: EngineConfig {
+                selector_overrides: Some(selector_overrides(
+                    "bing.TITLE_CLASSES = any b_algoheader b_title",
+                )),
+                ..EngineConfig::default()
+            },
+        };
+
+        let page = engine
+            .parse_rich(
+                r#"<html><body><ol id="b_results">
+                <li class="b_algo"><div class="b_title"><a href="https://www.rust-lang.org/"><h2>Rust</h2></a></div></li>
+                </ol></body></html>"#
+                    .to_string(),
+            )
+            .unwrap();
+
+        let selectors = page
+            .health
+            .fields
+            .iter()
+            .map(|this| (this.field, this.selector.as_str(), this.matched))
+            .collect::<Vec<_>>();
+        assert_eq!(page.health.container_selector, ".b_algo");
+        assert_eq!(
+            selectors,
+            [
+                (Field::Title, ":is(.b_algoheader, .b_title)", 1),
+                (Field::Url, ":is(.b_algoheader, .b_title) a", 1),
+                (Field::Summary, ".b_caption.b_capmedia .b_lineclamp3", 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn records_which_summary_selector_matched() {
+        let page = parse(
+            r#"<html><body><ol id="b_results">
+            <li class="b_algo"><div class="b_algoheader"><a href="https://www.rust-lang.org/"><h2>Rust</h2></a></div>
+            <div class="b_cards2 slide"><div class="exsni">Install</div><div class="exsni">A language empowering everyone</div></div></li>
+            </ol></body></html>"#,
+        );
+
+        let summary_hits = page
+            .health
+            .selector_hits
+            .iter()
+            .filter(|this| this.field == Field::Summary)
+            .map(|this| (this.selector, this.matched))
+            .collect::<Vec<_>>();
+        assert_eq!(summary_hits, [("CARD_SUMMARY_CLASSES", 1)]);
+        assert_eq!(page.health.missing(Field::Summary), 0);
+
+        // The url is on the link inside the title, not the title itself.
+        let url_hits = page
+            .health
+            .selector_hits
+            .iter()
+            .filter(|this| this.field == Field::Url)
+            .map(|this| (this.selector, this.matched))
+            .collect::<Vec<_>>();
+        assert_eq!(url_hits, [("{TITLE_CLASSES} a", 1)]);
+    }
+
+    #[test]
+    fn keeps_the_cookies_bing_sets_when_validating_a_response() {
+        let engine = BingEngine {
+            config: EngineConfig {
+                cookie_jar: Some(CookieJar::new()),
+                ..EngineConfig::default()
+            },
+        };
+
+        Engine::validate_response(
+            &engine,
+            &response(
+                "https://www.bing.com/search?q=rust",
+                &[("set-cookie", "MUID=abc; Domain=.bing.com; Path=/")],
+            ),
+        )
+        .unwrap();
+
+        let mut headers = HeaderMap::new();
+        engine.headers(&mut headers, &options());
+        let cookies = headers[http::header::COOKIE].to_str().unwrap();
+        assert!(cookies.starts_with("MUID=abc; "));
+        assert!(cookies.contains("_EDGE_V=1"));
+    }
+
+    fn safe_search_url(safe_search: SafeSearch) -> String {
+        let options = SearchOptions {
+            safe_search,
+            ..options()
+        };
+
+        BingEngine::default().url("rust", &options).unwrap()
+    }
+
+    #[test]
+    fn requests_the_configured_number_of_results_per_page() {
+        let url = |results_per_page| {
+            let engine = BingEngine {
+                config: EngineConfig {
+                    results_per_page,
+                    ..EngineConfig::default()
+                },
+            };
+            let options = SearchOptions {
+                page_num: 1,
+                ..options()
+            };
+
+            engine.url("rust", &options).unwrap()
+        };
+
+        assert!(url(Some(25)).contains("&first=26&"));
+        assert!(url(Some(25)).contains("&count=25"));
+        assert!(url(Some(10)).contains("&first=11&"));
+        assert!(!url(Some(10)).contains("count="));
+        assert!(!url(None).contains("count="));
+    }
+
+    #[tokio::test]
+    async fn searches_with_the_form_from_its_homepage() {
+        let engine = BingEngine {
+            config: EngineConfig {
+                warm_up: Some(WarmUp::new(Duration::from_secs(60))),
+                ..EngineConfig::default()
+            },
+        };
+        let fetcher = FakeFetcher::default()
+            .with_body(r#"<form><input type="hidden" name="form" value="QBRE"></form>"#)
+            .with_body("<html><body></body></html>");
+
+        let _ = fetch_page(&engine, &fetcher, "rust", &options()).await;
+
+        let requests = fetcher.requests();
+        assert_eq!(requests[0].url, "https://www.bing.com");
+        assert!(requests[1].url.contains("&form=QBRE&"));
+    }
+
+    #[test]
+    fn sends_the_safe_search_level() {
+        assert!(safe_search_url(SafeSearch::Off).contains("&safeSearch=off"));
+        assert!(safe_search_url(SafeSearch::Moderate).contains("&safeSearch=moderate"));
+        assert!(safe_search_url(SafeSearch::Strict).contains("&safeSearch=strict"));
+    }
+
+    #[test]
+    fn supports_every_safe_search_level() {
+        for safe_search in [SafeSearch::Off, SafeSearch::Moderate, SafeSearch::Strict] {
+            assert!(BingEngine::default().supports_safe_search(&safe_search));
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_a_request_which_differs_from_its_cassette() {
+        use crate::testing::cassettes::{cassette_config, load_cassette, replay};
+
+        let engine = BingEngine {
+            config: cassette_config(),
+        };
+        // As if the engine used to request the second page.
+        let mut cassette = load_cassette("bing");
+        let interaction = &mut cassette.interactions[0];
+        interaction.request.url = interaction.request.url.replace("&first=1&", "&first=11&");
+        interaction.response.url = interaction.request.url.clone();
+
+        let mismatches = replay(&engine, &cassette).await.mismatches;
+
+        assert_eq!(mismatches.len(), 2, "{mismatches:#?}");
+        assert!(mismatches[0].starts_with("unexpected request for /search?q=rust&first=1&"));
+        assert_eq!(mismatches[1], "expected 2 results but parsed 0");
+    }
+
+    #[tokio::test]
+    async fn reports_a_host_or_header_which_differs_from_its_cassette() {
+        use crate::testing::cassettes::{cassette_config, load_cassette, replay};
+
+        let engine = BingEngine {
+            config: cassette_config(),
+        };
+        // As if the engine used to search another Bing domain with another browser.
+        let mut cassette = load_cassette("bing");
+        let interaction = &mut cassette.interactions[0];
+        interaction.request.url = interaction
+            .request
+            .url
+            .replace("www.bing.com", "cn.bing.com");
+        interaction.request.headers[0].1 = String::from("Mozilla/5.0");
+
+        let mismatches = replay(&engine, &cassette).await.mismatches;
+
+        assert_eq!(mismatches.len(), 2, "{mismatches:#?}");
+        assert!(mismatches[0].ends_with("was sent to www.bing.com but cn.bing.com was recorded"));
+        assert!(mismatches[1].contains(r#"sent user-agent: "Lynx/"#));
+        assert!(mismatches[1].ends_with(r#"but "Mozilla/5.0" was recorded"#));
+    }
+
+    #[tokio::test]
+    async fn reports_a_result_which_differs_from_its_cassette() {
+        use crate::testing::cassettes::{cassette_config, load_cassette, replay};
+
+        let engine = BingEngine {
+            config: cassette_config(),
+        };
+        let mut cassette = load_cassette("bing");
+        cassette.results[1].title = String::from("The Book");
+
+        let mismatches = replay(&engine, &cassette).await.mismatches;
+
+        assert_eq!(mismatches.len(), 1, "{mismatches:#?}");
+        assert!(mismatches[0].starts_with("result 1 differs"));
+    }
+
+    #[tokio::test]
+    async fn replays_its_cassette() {
+        use crate::testing::cassettes::{cassette_config, replay_cassette};
+
+        let engine = BingEngine {
+            config: cassette_config(),
+        };
+
+        let page = replay_cassette(&engine, "bing").await;
+        assert_eq!(page.results[0].title, "Rust Programming Language");
+    }
+}
@@ -1,26 +1,44 @@
 use std::borrow::Cow;
 
-use chrono::Datelike;
-use html_hybrid_parser::{ClassName, ClassNames, Node, Query, QueryClassNames, class_names_any};
-use http::{
-    HeaderMap, HeaderValue,
-    header::{ACCEPT, CONTENT_TYPE, COOKIE, REFERER, USER_AGENT},
-};
+use anyhttp::Response;
+use chrono::{Datelike, Utc};
+use html_hybrid_parser::{ClassName, Node, Query, QueryClassNames, class_names_any};
+use http::HeaderMap;
 
 use quaero_shared::models::{
     engine::{Engine, TaggedEngine},
     search::{DateTimeRange, SearchError, SearchOptions, SearchResult},
-    user_agent::UserAgent,
 };
 use query_parameters::query_params;
 
+use crate::{
+    config::EngineConfig,
+    counts::parse_count,
+    dates::split_date_prefix,
+    engine::{RichEngine, parse_for_quaero},
+    error::{ParseDiagnostics, ParseError},
+    health::{Field, ParseHealth, SelectorHits, Selectors, first_match},
+    pacing::RequestBudget,
+    profiles::BrowserProfile,
+    results::{Discussion, ParsedPage, Product, Recipe, ResultKind, RichSearchResult},
+    spans::{align_spans, collect_spans},
+};
+
 /// An engine which parses search results from Brave.
-pub struct BraveEngine;
+#[derive(Default)]
+pub struct BraveEngine {
+    config: EngineConfig,
+}
 
 impl BraveEngine {
     /// Creates a new Brave engine.
     pub fn new() -> TaggedEngine {
-        TaggedEngine::new(Self {})
+        Self::with_config(EngineConfig::default())
+    }
+
+    /// Creates a new Brave engine with a custom config.
+    pub fn with_config(config: EngineConfig) -> TaggedEngine {
+        TaggedEngine::new(Self { config })
     }
 }
 
@@ -39,6 +57,8 @@ impl Engine for BraveEngine {
             ..
         }: &SearchOptions,
     ) -> Result<String, SearchError> {
+        let query = self.render_query(query);
+
         let date_time_range_param = if let Some(DateTimeRange {
             start: start_range,
             end: end_range,
@@ -74,58 +94,94 @@ impl Engine for BraveEngine {
     fn headers(&self, headers: &mut HeaderMap, SearchOptions { safe_search, .. }: &SearchOptions) {
         let safe_search = safe_search.as_lowercase_string();
 
-        headers.insert(USER_AGENT, UserAgent::random_no_js().into());
-        headers.insert(ACCEPT, HeaderValue::from_static("*/*"));
-        headers.append(
-            CONTENT_TYPE,
-            HeaderValue::from_static("application/x-www-form-urlencoded"),
-        );
-        headers.append(
-            COOKIE,
-            HeaderValue::from_str(&format!("safe_search={safe_search}")).unwrap(),
+        self.config
+            .browser_profile
+            .unwrap_or_else(BrowserProfile::random_no_js)
+            .apply(headers, self.homepage(), Some("https://google.com/"));
+
+        // The safe search level is only sent as a cookie, so it has to replace whatever the jar
+        // has kept from earlier requests.
+        if let Some(cookie_jar) = &self.config.cookie_jar {
+            cookie_jar.set(self.homepage(), "safe_search", &safe_search);
+        }
+        self.config.apply_cookies(
+            headers,
+            self.homepage(),
+            &format!("safe_search={safe_search}"),
         );
-        headers.append(REFERER, HeaderValue::from_static("https://google.com/"));
+    }
+
+    fn validate_response(&self, response: &Response) -> Result<(), SearchError> {
+        self.config.store_cookies(response);
+        Ok(())
     }
 
     fn parse<'a>(&self, response_text: String) -> Result<Vec<(String, SearchResult)>, SearchError> {
+        parse_for_quaero(self, response_text)
+    }
+}
+
+impl RichEngine for BraveEngine {
+    fn id(&self) -> &str {
+        "brave"
+    }
+
+    fn config(&self) -> &EngineConfig {
+        &self.config
+    }
+
+    fn request_budget(&self) -> RequestBudget {
+        REQUEST_BUDGET
+    }
+
+    // Brave's `offset` param is a page number rather than a result index,
+    // and each page has 20 results.
+    fn results_per_page(&self) -> usize {
+        20
+    }
+
+    /// Parses a results page into rich results, including those from Brave's standalone snippets
+    /// (discussions, faqs, products and recipes).
+    fn parse_rich(&self, response_text: String) -> Result<ParsedPage, ParseError> {
         let decoded_data = html_escape::decode_html_entities(&response_text);
 
         let dom = html_hybrid_parser::Parser::comprehensive_but_slow(decoded_data.as_ref());
         let parser = dom.parser();
 
+        let overrides = self.config.selectors(self.id());
+        let search_result_classes =
+            overrides.classes("SEARCH_RESULT_CLASSES", &SEARCH_RESULT_CLASSES);
+        let title_classes = overrides.classes("TITLE_CLASSES", &TITLE_CLASSES);
+        let summary_classes = overrides.classes("SUMMARY_CLASSES", &SUMMARY_CLASSES);
+
         let Some(results) = dom.get_first_node_with_id("results", parser) else {
-            return Err(SearchError::NoResultsFound);
+            return Err(ParseError::SelectorMissing(ParseDiagnostics::new(
+                self.id(),
+                "#results",
+                &response_text,
+            )));
         };
 
         if results
             .get_first_node_with_id("bad-results-info-banner", parser)
             .is_some()
         {
-            return Err(SearchError::NoResultsFound);
+            return Err(SearchError::NoResultsFound.into());
         }
 
         let nodes = results
-            .get_child_nodes_with_classes(&SEARCH_RESULT_CLASSES, parser)
+            .get_child_nodes_with_classes(&search_result_classes, parser)
             // Removes any nodes which:
-            // - Don't have the `[data-type="web"]` attributes (non-web results).
-            // TODO: look into extracting data from `standalone` snippets as they do contain useful data.
-            // - Have the `.noscript-hide` (hidden and empty data) or `standalone` (non standard web result) classes.
+            // - Have the `.noscript-hide` class (hidden and empty data).
             // - Have the `#search-elsewhere` id (search suggestions).
-            // - Have the `#search-ad` id (advertisement).
             .filter(|this| {
-                if let Some(data_type_attribute) = this.get_attribute("data-type") {
-                    if data_type_attribute.as_ref() != "web" {
-                        return false;
-                    }
-                }
-
                 if SEARCH_RESULT_BLOCKLISTED_CLASSES.matches(this.class()) {
                     return false;
                 }
 
                 if let Some(id) = this.id() {
                     let id = id.as_ref();
-                    if id == "search_anywhere" || id == "search-ad" {
+                    if id == "search_anywhere" {
                         return false;
                     }
                 }
@@ -133,46 +189,677 @@ impl Engine for BraveEngine {
                 true
             });
 
-        Ok(nodes
-            .filter_map(|this| {
-                let (title, url) = this
-                    .get_first_node_with_tag("a", parser)
-                    .map(|this| {
-                        let title = this
-                            .get_first_node_with_classes(&TITLE_CLASSES, parser)
-                            .and_then(|this| this.text(parser).map(|this| this.to_string()))
-                            .unwrap_or_default();
+        let mut rich_results = Vec::new();
+        // Ads outside of the results are shown above them.
+        let mut ad_position = 0;
+        let mut containers = 0;
+        let mut selector_hits = SelectorHits::default();
+        let now = Utc::now();
+
+        for this in nodes {
+            // The ad (`#search-ad`) is handled separately below, as it isn't always amongst the
+            // results.
+            if this.id().is_some_and(|id| id == "search-ad") {
+                ad_position = rich_results.len();
+                continue;
+            }
+
+            let data_type = this
+                .get_attribute("data-type")
+                .map(|this| this.to_string())
+                .unwrap_or_else(|| String::from("web"));
+
+            // Standard web results.
+            if data_type == "web" && !STANDALONE_CLASSES.matches(this.class()) {
+                containers += 1;
+
+                let link = this.get_first_node_with_tag("a", parser);
+
+                let title = first_match!(selector_hits, Field::Title, {
+                    "TITLE_CLASSES" => link
+                        .as_ref()
+                        .and_then(|this| this.get_first_node_with_classes(&title_classes, parser))
+                        .and_then(|this| this.text(parser).map(|this| this.to_string())),
+                })
+                .unwrap_or_default();
 
-                        let url = this
-                            .get_href()
-                            .map(|this| this.to_string())
-                            .unwrap_or_default();
+                let url = first_match!(selector_hits, Field::Url, {
+                    "a[href]" => link
+                        .as_ref()
+                        .and_then(|this| this.get_href().map(|this| this.to_string())),
+                })
+                .unwrap_or_default();
 
-                        (title, url)
+                let summary_spans = this
+                    .get_first_node_with_classes(&summary_classes, parser)
+                    .map(|this| {
+                        collect_spans(
+                            this.get_child_nodes(parser)
+                                .map(|this| (this.tag_name(), this.text(parser))),
+                        )
+                    })
+                    .unwrap_or_default();
+
+                let (summary, summary_selector) =
+                    first_match!(@selector selector_hits, Field::Summary, {
+                        "SUMMARY_CLASSES" => this
+                            .get_first_node_with_classes(&summary_classes, parser)
+                            .and_then(|this| {
+                                this.text(parser).map(|this| this.trim_start().to_string())
+                            }),
+                        // Sometimes summaries may be in a q&a format.
+                        "SUMMARY_QNA_CLASSES" => this
+                            .get_first_node_with_classes(&SUMMARY_QNA_CLASSES, parser)
+                            .and_then(|this| this.text(parser).map(|this| this.to_string())),
                     })
                     .unwrap_or_default();
 
+                // Summaries may start with the date the result was published (e.g. `March 5, 2024 - `).
+                let (date, summary) = match first_match!(selector_hits, Field::Date, {
+                    summary_selector => split_date_prefix(&summary, now),
+                }) {
+                    Some((date, summary)) => (Some(date), summary.to_string()),
+                    None => (None, summary),
+                };
+
+                let summary_spans = align_spans(summary_spans, &summary);
+
+                rich_results.push(RichSearchResult {
+                    summary_spans,
+                    date,
+                    ..RichSearchResult::new(title, url, summary)
+                });
+                continue;
+            }
+
+            // Standalone snippets (and other non-web results) each have their own layout.
+            match data_type.as_str() {
+                "discussions" => rich_results.extend(
+                    this.get_nodes_with_classes(&DISCUSSION_CLASSES, parser)
+                        .filter_map(|this| {
+                            let link = this.get_first_node_with_tag("a", parser)?;
+
+                            let title = link
+                                .get_first_node_with_classes(&title_classes, parser)
+                                .and_then(|this| this.text(parser).map(|this| this.to_string()))
+                                .unwrap_or_default();
+
+                            let url = link.get_href()?.to_string();
+
+                            // The summary of a discussion is a snippet of its top answer.
+                            let summary = this
+                                .get_first_node_with_classes(&DISCUSSION_ANSWER_CLASSES, parser)
+                                .and_then(|this| this.text(parser).map(|this| this.to_string()))
+                                .unwrap_or_default();
+
+                            let forum = this
+                                .get_first_node_with_classes(&DISCUSSION_FORUM_CLASSES, parser)
+                                .and_then(|this| this.text(parser).map(|this| this.to_string()));
+
+                            let replies = this
+                                .get_first_node_with_classes(&DISCUSSION_REPLIES_CLASSES, parser)
+                                .and_then(|this| {
+                                    this.text(parser).and_then(|this| parse_count(&this))
+                                });
+
+                            let votes = this
+                                .get_first_node_with_classes(&DISCUSSION_VOTES_CLASSES, parser)
+                                .and_then(|this| {
+                                    this.text(parser).and_then(|this| parse_count(&this))
+                                });
+
+                            Some(RichSearchResult {
+                                kind: ResultKind::Discussion(Discussion {
+                                    forum,
+                                    replies,
+                                    votes,
+                                }),
+                                ..RichSearchResult::new(title, url, summary)
+                            })
+                        }),
+                ),
+
+                "faq" => rich_results.extend(
+                    this.get_nodes_with_classes(&FAQ_ITEM_CLASSES, parser)
+                        .filter_map(|this| {
+                            let title = this
+                                .get_first_node_with_classes(&FAQ_QUESTION_CLASSES, parser)
+                                .and_then(|this| {
+                                    this.text(parser).map(|this| this.trim().to_string())
+                                })?;
+
+                            let answer_node =
+                                this.get_first_node_with_classes(&FAQ_ANSWER_CLASSES, parser)?;
+
+                            let summary = answer_node
+                                .get_first_node_with_classes(&summary_classes, parser)
+                                .and_then(|this| {
+                                    this.text(parser).map(|this| this.trim().to_string())
+                                })
+                                .unwrap_or_default();
+
+                            // The answer links to the page it was taken from.
+                            let url = answer_node
+                                .get_first_node_with_tag("a", parser)
+                                .and_then(|this| this.get_href().map(|this| this.to_string()))?;
+
+                            Some(RichSearchResult {
+                                kind: ResultKind::Faq,
+                                ..RichSearchResult::new(title, url, summary)
+                            })
+                        }),
+                ),
+
+                "product" | "products" => rich_results.extend(
+                    this.get_nodes_with_classes(&PRODUCT_CLASSES, parser)
+                        .filter_map(|this| {
+                            let link = this.get_first_node_with_tag("a", parser)?;
+
+                            let title = this
+                                .get_first_node_with_classes(&title_classes, parser)
+                                .and_then(|this| {
+                                    this.text(parser).map(|this| this.trim().to_string())
+                                })
+                                .unwrap_or_default();
+
+                            let url = link.get_href()?.to_string();
+
+                            let summary = this
+                                .get_first_node_with_classes(&summary_classes, parser)
+                                .and_then(|this| {
+                                    this.text(parser).map(|this| this.trim().to_string())
+                                })
+                                .unwrap_or_default();
+
+                            let price = this
+                                .get_first_node_with_classes(&PRODUCT_PRICE_CLASSES, parser)
+                                .and_then(|this| {
+                                    this.text(parser).map(|this| this.trim().to_string())
+                                });
+
+                            let merchant = this
+                                .get_first_node_with_classes(&PRODUCT_MERCHANT_CLASSES, parser)
+                                .and_then(|this| {
+                                    this.text(parser).map(|this| this.trim().to_string())
+                                });
+
+                            let rating = this
+                                .get_first_node_with_classes(&RATING_CLASSES, parser)
+                                .and_then(|this| {
+                                    this.text(parser).and_then(|this| parse_rating(&this))
+                                });
+
+                            let review_count = this
+                                .get_first_node_with_classes(&REVIEW_COUNT_CLASSES, parser)
+                                .and_then(|this| {
+                                    this.text(parser).and_then(|this| parse_count(&this))
+                                });
+
+                            Some(RichSearchResult {
+                                kind: ResultKind::Product(Product {
+                                    price,
+                                    rating,
+                                    review_count,
+                                    merchant,
+                                }),
+                                ..RichSearchResult::new(title, url, summary)
+                            })
+                        }),
+                ),
+
+                "recipe" | "recipes" => rich_results.extend(
+                    this.get_nodes_with_classes(&RECIPE_CLASSES, parser)
+                        .filter_map(|this| {
+                            let link = this.get_first_node_with_tag("a", parser)?;
+
+                            let title = this
+                                .get_first_node_with_classes(&title_classes, parser)
+                                .and_then(|this| {
+                                    this.text(parser).map(|this| this.trim().to_string())
+                                })
+                                .unwrap_or_default();
+
+                            let url = link.get_href()?.to_string();
+
+                            let summary = this
+                                .get_first_node_with_classes(&summary_classes, parser)
+                                .and_then(|this| {
+                                    this.text(parser).map(|this| this.trim().to_string())
+                                })
+                                .unwrap_or_default();
+
+                            let rating = this
+                                .get_first_node_with_classes(&RATING_CLASSES, parser)
+                                .and_then(|this| {
+                                    this.text(parser).and_then(|this| parse_rating(&this))
+                                });
+
+                            let review_count = this
+                                .get_first_node_with_classes(&REVIEW_COUNT_CLASSES, parser)
+                                .and_then(|this| {
+                                    this.text(parser).and_then(|this| parse_count(&this))
+                                });
+
+                            let total_time = this
+                                .get_first_node_with_classes(&RECIPE_TIME_CLASSES, parser)
+                                .and_then(|this| {
+                                    this.text(parser).map(|this| this.trim().to_string())
+                                });
+
+                            Some(RichSearchResult {
+                                kind: ResultKind::Recipe(Recipe {
+                                    rating,
+                                    review_count,
+                                    total_time,
+                                }),
+                                ..RichSearchResult::new(title, url, summary)
+                            })
+                        }),
+                ),
+
+                // Anything else (videos, news clusters, infoboxes, etc...) isn't supported yet.
+                _ => {}
+            }
+        }
+
+        let mut page = ParsedPage::new(rich_results);
+        // Only web results are checked, as the standalone results have their own layouts.
+        page.health = ParseHealth::new(&SELECTORS, &overrides, containers, selector_hits);
+
+        // Ads have the same layout as web results. They may be outside of the results.
+        let ad = dom
+            .get_first_node_with_id("search-ad", parser)
+            .and_then(|this| {
+                let link = this.get_first_node_with_tag("a", parser)?;
+
+                let title = link
+                    .get_first_node_with_classes(&title_classes, parser)
+                    .and_then(|this| this.text(parser).map(|this| this.to_string()))
+                    .unwrap_or_default();
+
+                let url = link.get_href()?.to_string();
+
                 let summary = this
-                    .get_first_node_with_classes(&SUMMARY_CLASSES, parser)
+                    .get_first_node_with_classes(&summary_classes, parser)
                     .and_then(|this| this.text(parser).map(|this| this.trim_start().to_string()))
-                    // Sometimes summaries may be in a q&a format.
-                    .unwrap_or_else(|| {
-                        this.get_first_node_with_classes(&SUMMARY_QNA_CLASSES, parser)
-                            .and_then(|this| this.text(parser).map(|this| this.to_string()))
-                            .unwrap_or_default()
-                    });
-
-                Some(SearchResult::new(title, url, summary))
-            })
-            .collect())
+                    .unwrap_or_default();
+
+                Some((ad_position, RichSearchResult::new(title, url, summary)))
+            });
+        page.add_ads(ad, self.config.ads);
+
+        Ok(page)
     }
 }
 
+/// Parses a rating out of some text (e.g. `4.7 (1.2k)` -> `4.7`).
+fn parse_rating(text: &str) -> Option<f32> {
+    let rating = text
+        .trim_start()
+        .split(|this: char| !(this.is_ascii_digit() || this == '.'))
+        .next()?;
+
+    rating.parse().ok()
+}
+
+const REQUEST_BUDGET: RequestBudget = RequestBudget::per_minute(15);
+
+pub(crate) const SELECTORS: Selectors = Selectors {
+    container: "#results > {SEARCH_RESULT_CLASSES}",
+    title: "a {TITLE_CLASSES}",
+    url: "a[href]",
+    summary: "{SUMMARY_CLASSES}",
+    classes: &[
+        ("SEARCH_RESULT_CLASSES", ".snippet"),
+        ("TITLE_CLASSES", ".title"),
+        ("SUMMARY_CLASSES", ".content"),
+    ],
+};
+
 const SEARCH_RESULT_CLASSES: ClassName = class_names_any! { "snippet" };
-const SEARCH_RESULT_BLOCKLISTED_CLASSES: ClassNames =
-    class_names_any! { "noscript-hide", "standalone" };
+const SEARCH_RESULT_BLOCKLISTED_CLASSES: ClassName = class_names_any! { "noscript-hide" };
+const STANDALONE_CLASSES: ClassName = class_names_any! { "standalone" };
 
 const TITLE_CLASSES: ClassName = class_names_any! { "title" };
 
 const SUMMARY_CLASSES: ClassName = class_names_any! { "content" };
 const SUMMARY_QNA_CLASSES: ClassName = class_names_any! { "inline-qa-answer" };
+
+const RATING_CLASSES: ClassName = class_names_any! { "rating" };
+const REVIEW_COUNT_CLASSES: ClassName = class_names_any! { "review-count" };
+
+const DISCUSSION_CLASSES: ClassName = class_names_any! { "discussion-item" };
+const DISCUSSION_ANSWER_CLASSES: ClassName = class_names_any! { "discussion-answer" };
+const DISCUSSION_FORUM_CLASSES: ClassName = class_names_any! { "forum-name" };
+const DISCUSSION_REPLIES_CLASSES: ClassName = class_names_any! { "discussion-replies" };
+const DISCUSSION_VOTES_CLASSES: ClassName = class_names_any! { "discussion-votes" };
+
+const FAQ_ITEM_CLASSES: ClassName = class_names_any! { "faq-item" };
+const FAQ_QUESTION_CLASSES: ClassName = class_names_any! { "faq-question" };
+const FAQ_ANSWER_CLASSES: ClassName = class_names_any! { "faq-answer" };
+
+const PRODUCT_CLASSES: ClassName = class_names_any! { "product" };
+const PRODUCT_PRICE_CLASSES: ClassName = class_names_any! { "price" };
+const PRODUCT_MERCHANT_CLASSES: ClassName = class_names_any! { "merchant" };
+
+const RECIPE_CLASSES: ClassName = class_names_any! { "recipe" };
+const RECIPE_TIME_CLASSES: ClassName = class_names_any! { "recipe-time" };
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use quaero_shared::models::search::SafeSearch;
+
+    use crate::{config::AdHandling, cookies::CookieJar, testing::options};
+
+    fn parse_fixture(html: &str) -> ParsedPage {
+        BraveEngine::default()
+            .parse_rich(html.to_string())
+            .expect("fixture should parse")
+    }
+
+    #[test]
+    fn parses_discussions() {
+        let page = parse_fixture(include_str!("../tests/fixtures/brave/discussions.html"));
+        assert_eq!(page.results.len(), 3);
+
+        let web = &page.results[0];
+        assert_eq!(web.kind, ResultKind::Organic);
+        assert_eq!(web.title, "Async Rust in practice");
+
+        let reddit = &page.results[1];
+        assert_eq!(reddit.title, "Is async Rust worth it?");
+        assert_eq!(
+            reddit.url,
+            "https://www.reddit.com/r/rust/comments/abc123/is_async_rust_worth_it/"
+        );
+        assert_eq!(reddit.summary, "For IO-bound services it's worth it.");
+        assert_eq!(
+            reddit.kind,
+            ResultKind::Discussion(Discussion {
+                forum: Some(String::from("r/rust")),
+                replies: Some(1_200),
+                votes: Some(348),
+            })
+        );
+
+        let forum = &page.results[2];
+        assert_eq!(forum.title, "Async or threads?");
+        assert_eq!(
+            forum.url,
+            "https://users.rust-lang.org/t/async-or-threads/4242"
+        );
+        assert_eq!(
+            forum.summary,
+            "Threads are simpler until you need thousands of them."
+        );
+        assert_eq!(
+            forum.kind,
+            ResultKind::Discussion(Discussion {
+                forum: Some(String::from("users.rust-lang.org")),
+                replies: Some(42),
+                votes: None,
+            })
+        );
+    }
+
+    #[test]
+    fn parses_faqs() {
+        let page = parse_fixture(include_str!("../tests/fixtures/brave/faq.html"));
+        // The last question has no link to its source, so it's skipped.
+        assert_eq!(page.results.len(), 2);
+
+        let lifetimes = &page.results[0];
+        assert_eq!(lifetimes.kind, ResultKind::Faq);
+        assert_eq!(lifetimes.title, "What is a lifetime in Rust?");
+        assert_eq!(
+            lifetimes.url,
+            "https://doc.rust-lang.org/book/ch10-03-lifetime-syntax.html"
+        );
+        assert_eq!(
+            lifetimes.summary,
+            "A lifetime is the scope for which a reference is valid."
+        );
+
+        let borrowing = &page.results[1];
+        assert_eq!(borrowing.kind, ResultKind::Faq);
+        assert_eq!(borrowing.title, "Why does Rust need lifetimes & borrowing?");
+        assert_eq!(
+            borrowing.url,
+            "https://rust-lang.github.io/rfcs/2094-nll.html"
+        );
+        assert_eq!(
+            borrowing.summary,
+            "They let the compiler prove references never outlive their data."
+        );
+    }
+
+    #[test]
+    fn parses_products() {
+        let page = parse_fixture(include_str!("../tests/fixtures/brave/product.html"));
+        assert_eq!(page.results.len(), 2);
+
+        let book = &page.results[0];
+        assert_eq!(book.title, "The Rust Programming Language, 2nd Edition");
+        assert_eq!(
+            book.url,
+            "https://store.example.com/p/the-rust-programming-language"
+        );
+        assert_eq!(book.summary, "Paperback by Steve Klabnik & Carol Nichols.");
+        assert_eq!(
+            book.kind,
+            ResultKind::Product(Product {
+                price: Some(String::from("$39.95")),
+                rating: Some(4.8),
+                review_count: Some(2_300),
+                merchant: Some(String::from("Example Store")),
+            })
+        );
+
+        let other_book = &page.results[1];
+        assert_eq!(other_book.title, "Rust in Action");
+        assert_eq!(other_book.url, "https://books.example.org/rust-in-action");
+        assert_eq!(
+            other_book.summary,
+            "Systems programming concepts and techniques."
+        );
+        assert_eq!(
+            other_book.kind,
+            ResultKind::Product(Product {
+                price: Some(String::from("£32.99")),
+                ..Product::default()
+            })
+        );
+    }
+
+    #[test]
+    fn parses_recipes() {
+        let page = parse_fixture(include_str!("../tests/fixtures/brave/recipe.html"));
+        assert_eq!(page.results.len(), 2);
+
+        let bread = &page.results[0];
+        assert_eq!(bread.title, "Classic Sourdough Bread");
+        assert_eq!(bread.url, "https://recipes.example.com/classic-sourdough");
+        assert_eq!(bread.summary, "A crusty loaf with a chewy, open crumb.");
+        assert_eq!(
+            bread.kind,
+            ResultKind::Recipe(Recipe {
+                rating: Some(4.7),
+                review_count: Some(1_204),
+                total_time: Some(String::from("24 hr 30 min")),
+            })
+        );
+
+        let crackers = &page.results[1];
+        assert_eq!(crackers.title, "Sourdough Discard Crackers");
+        assert_eq!(
+            crackers.url,
+            "https://recipes.example.com/quick-sourdough-discard-crackers"
+        );
+        assert_eq!(crackers.summary, "Use up your starter discard.");
+        assert_eq!(
+            crackers.kind,
+            ResultKind::Recipe(Recipe {
+                total_time: Some(String::from("45 min")),
+                ..Recipe::default()
+            })
+        );
+    }
+
+    #[test]
+    fn parses_ratings() {
+        assert_eq!(parse_rating("4.7 (1.2k)"), Some(4.7));
+        assert_eq!(parse_rating(" 5"), Some(5.0));
+        assert_eq!(parse_rating("unrated"), None);
+    }
+
+    fn safe_search_cookie(engine: &BraveEngine, safe_search: SafeSearch) -> String {
+        let options = SearchOptions {
+            safe_search,
+            ..options()
+        };
+
+        let mut headers = HeaderMap::new();
+        engine.headers(&mut headers, &options);
+        headers[http::header::COOKIE].to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn sends_the_safe_search_level_as_a_cookie() {
+        let engine = BraveEngine::default();
+
+        assert_eq!(
+            safe_search_cookie(&engine, SafeSearch::Off),
+            "safe_search=off"
+        );
+        assert_eq!(
+            safe_search_cookie(&engine, SafeSearch::Moderate),
+            "safe_search=moderate"
+        );
+        assert_eq!(
+            safe_search_cookie(&engine, SafeSearch::Strict),
+            "safe_search=strict"
+        );
+    }
+
+    #[test]
+    fn replaces_the_safe_search_cookie_in_the_jar() {
+        let engine = BraveEngine {
+            config: EngineConfig {
+                cookie_jar: Some(CookieJar::new()),
+                ..EngineConfig::default()
+            },
+        };
+
+        safe_search_cookie(&engine, SafeSearch::Strict);
+        assert_eq!(
+            safe_search_cookie(&engine, SafeSearch::Off),
+            "safe_search=off"
+        );
+    }
+
+    #[test]
+    fn supports_every_safe_search_level() {
+        for safe_search in [SafeSearch::Off, SafeSearch::Moderate, SafeSearch::Strict] {
+            assert!(BraveEngine::default().supports_safe_search(&safe_search));
+        }
+    }
+
+    #[test]
+    fn keeps_a_labelled_ad_where_it_was_shown() {
+        let engine = BraveEngine {
+            config: EngineConfig {
+                ads: AdHandling::Label,
+                ..EngineConfig::default()
+            },
+        };
+
+        let page = engine
+            .parse_rich(
+                r#"<html><body><div id="results">
+                <div class="snippet" data-type="web"><a href="https://www.rust-lang.org/"><div class="title">Rust</div></a><div class="content">A language.</div></div>
+                <div class="snippet" id="search-ad"><a href="https://training.example/rust"><div class="title">Rust Training</div></a><div class="content">Learn Rust.</div></div>
+                <div class="snippet" data-type="web"><a href="https://doc.rust-lang.org/"><div class="title">Docs</div></a><div class="content">The docs.</div></div>
+                </div></body></html>"#
+                    .to_string(),
+            )
+            .unwrap();
+
+        let titles = page
+            .results
+            .iter()
+            .map(|this| (this.title.as_str(), this.sponsored))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            titles,
+            [("Rust", false), ("Rust Training", true), ("Docs", false)]
+        );
+    }
+
+    #[test]
+    fn keeps_a_labelled_ad_shown_above_the_results() {
+        let engine = BraveEngine {
+            config: EngineConfig {
+                ads: AdHandling::Label,
+                ..EngineConfig::default()
+            },
+        };
+
+        let page = engine
+            .parse_rich(
+                r#"<html><body>
+                <div class="snippet" id="search-ad"><a href="https://training.example/rust"><div class="title">Rust Training</div></a><div class="content">Learn Rust.</div></div>
+                <div id="results">
+                <div class="snippet" data-type="web"><a href="https://www.rust-lang.org/"><div class="title">Rust</div></a><div class="content">A language.</div></div>
+                </div></body></html>"#
+                    .to_string(),
+            )
+            .unwrap();
+
+        let titles = page
+            .results
+            .iter()
+            .map(|this| (this.title.as_str(), this.sponsored))
+            .collect::<Vec<_>>();
+        assert_eq!(titles, [("Rust Training", true), ("Rust", false)]);
+    }
+
+    #[test]
+    fn records_the_date_under_the_summary_selector_which_matched() {
+        let page = parse_fixture(
+            r#"<html><body><div id="results">
+            <div class="snippet" data-type="web"><a href="https://www.rust-lang.org/"><div class="title">Rust</div></a><div class="content">March 5, 2024 - A language.</div></div>
+            <div class="snippet" data-type="web"><a href="https://doc.rust-lang.org/book/"><div class="title">The Book</div></a><div class="inline-qa-answer">March 6, 2024 - Learn Rust.</div></div>
+            </div></body></html>"#,
+        );
+
+        let hits = page
+            .health
+            .selector_hits
+            .iter()
+            .filter(|this| matches!(this.field, Field::Url | Field::Date))
+            .map(|this| (this.field, this.selector, this.matched))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            hits,
+            [
+                (Field::Url, "a[href]", 2),
+                (Field::Date, "SUMMARY_CLASSES", 1),
+                (Field::Date, "SUMMARY_QNA_CLASSES", 1),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn replays_its_cassette() {
+        use crate::testing::cassettes::{cassette_config, replay_cassette};
+
+        let engine = BraveEngine {
+            config: cassette_config(),
+        };
+
+        let page = replay_cassette(&engine, "brave").await;
+        assert_eq!(page.results[0].title, "Rust Programming Language");
+    }
+}
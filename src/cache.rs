@@ -0,0 +1,528 @@
+//! Caching results pages, so repeated queries and paging back and forth don't hit providers again.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs, io,
+    path::PathBuf,
+    sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use quaero_shared::models::search::SearchOptions;
+
+use crate::{
+    engine::RichEngine,
+    error::EngineError,
+    fetch::{Fetcher, fetch_body},
+    results::ParsedPage,
+};
+
+/// What identifies a cached results page.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    /// The id of the engine the page came from.
+    pub engine: String,
+    /// The query, with its whitespace normalized.
+    pub query: String,
+    /// The page number.
+    pub page: usize,
+    /// The number of results on each page.
+    pub results_per_page: usize,
+    /// The safe search level (see `SafeSearch::as_incrementing_usize`).
+    pub safe_search: usize,
+    /// The start and end of the date range as unix timestamps.
+    pub date_time_range: Option<(i64, i64)>,
+}
+
+impl CacheKey {
+    /// Creates the key for the results page for `query` from `engine`.
+    pub fn new<E: RichEngine + ?Sized>(engine: &E, query: &str, options: &SearchOptions) -> Self {
+        Self {
+            engine: engine.id().to_string(),
+            query: query.split_whitespace().collect::<Vec<_>>().join(" "),
+            page: options.page_num,
+            results_per_page: engine.results_per_page(),
+            safe_search: options.safe_search.as_incrementing_usize(),
+            date_time_range: options
+                .date_time_range
+                .as_ref()
+                .map(|this| (this.start.timestamp(), this.end.timestamp())),
+        }
+    }
+
+    /// Writes the key on a single line, in a format which stays the same between releases.
+    fn to_line(&self) -> String {
+        let date_time_range = self
+            .date_time_range
+            .map(|(start, end)| format!("{start}..{end}"))
+            .unwrap_or_default();
+
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            self.engine,
+            self.query,
+            self.page,
+            self.results_per_page,
+            self.safe_search,
+            date_time_range
+        )
+    }
+}
+
+/// A cache of results pages. Pages are kept in memory as parsed pages, and on disk as the
+/// response bodies they were parsed from, so pages on disk survive changes to the parsers.
+pub struct ResultCache {
+    ttl: Duration,
+    memory: Option<MemoryCache>,
+    disk: Option<DiskCache>,
+}
+
+impl ResultCache {
+    /// Creates a cache whose pages expire after `ttl`.
+    /// The cache doesn't store anything until a backend is added.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            memory: None,
+            disk: None,
+        }
+    }
+
+    /// Keeps up to `capacity` parsed pages in memory, evicting the least recently used first.
+    pub fn with_memory(mut self, capacity: usize) -> Self {
+        self.memory = Some(MemoryCache {
+            capacity,
+            entries: Mutex::new(MemoryEntries::default()),
+        });
+        self
+    }
+
+    /// Keeps response bodies in files in `dir`, creating it if needed.
+    pub fn with_disk(mut self, dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+
+        self.disk = Some(DiskCache { dir });
+        Ok(self)
+    }
+
+    /// Gets the results page for `query` from the cache, or fetches it from `engine` and caches
+    /// it. Errors aren't cached, and failing to write a page to disk is only logged, as the page
+    /// was still fetched.
+    pub async fn fetch_page<E, F>(
+        &self,
+        engine: &E,
+        fetcher: &F,
+        query: &str,
+        options: &SearchOptions,
+    ) -> Result<ParsedPage, EngineError>
+    where
+        E: RichEngine + ?Sized,
+        F: Fetcher + ?Sized,
+    {
+        let key = CacheKey::new(engine, query, options);
+
+        if let Some(page) = self
+            .memory
+            .as_ref()
+            .and_then(|this| this.get(&key, self.ttl))
+        {
+            return Ok(page);
+        }
+
+        let (body, is_cached) = match self.disk.as_ref().and_then(|this| this.get(&key, self.ttl)) {
+            Some(body) => (body, true),
+            None => (fetch_body(engine, fetcher, query, options).await?, false),
+        };
+
+        let page = engine.parse_page(body.clone(), options)?;
+
+        if let Some(disk) = self.disk.as_ref().filter(|_| !is_cached)
+            && let Err(error) = disk.insert(&key, &body)
+        {
+            log::warn!("failed to cache a page from {}: {error}", key.engine);
+        }
+        if let Some(memory) = &self.memory {
+            memory.insert(key, page.clone());
+        }
+
+        Ok(page)
+    }
+
+    /// Removes the page for `key` from the cache.
+    pub fn remove(&self, key: &CacheKey) -> io::Result<()> {
+        if let Some(memory) = &self.memory {
+            memory.remove(key);
+        }
+        if let Some(disk) = &self.disk {
+            disk.remove(key)?;
+        }
+
+        Ok(())
+    }
+}
+
+struct MemoryCache {
+    capacity: usize,
+    entries: Mutex<MemoryEntries>,
+}
+
+#[derive(Default)]
+struct MemoryEntries {
+    pages: HashMap<CacheKey, MemoryEntry>,
+    /// The keys ordered by when they were last used, to find the least recently used page.
+    by_last_use: BTreeMap<u64, CacheKey>,
+    /// Incremented on every access.
+    clock: u64,
+}
+
+struct MemoryEntry {
+    page: ParsedPage,
+    stored_at: Instant,
+    last_used: u64,
+}
+
+impl MemoryEntries {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn remove(&mut self, key: &CacheKey) -> Option<MemoryEntry> {
+        let entry = self.pages.remove(key)?;
+        self.by_last_use.remove(&entry.last_used);
+
+        Some(entry)
+    }
+}
+
+impl MemoryCache {
+    fn get(&self, key: &CacheKey, ttl: Duration) -> Option<ParsedPage> {
+        let mut entries = self.entries.lock().unwrap_or_else(|this| this.into_inner());
+        let clock = entries.tick();
+
+        let entry = entries.pages.get_mut(key)?;
+        if entry.stored_at.elapsed() > ttl {
+            entries.remove(key);
+            return None;
+        }
+
+        let last_used = std::mem::replace(&mut entry.last_used, clock);
+        let page = entry.page.clone();
+
+        entries.by_last_use.remove(&last_used);
+        entries.by_last_use.insert(clock, key.clone());
+
+        Some(page)
+    }
+
+    fn insert(&self, key: CacheKey, page: ParsedPage) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap_or_else(|this| this.into_inner());
+        let clock = entries.tick();
+
+        if entries.remove(&key).is_none()
+            && entries.pages.len() >= self.capacity
+            && let Some((_, least_recently_used)) = entries.by_last_use.pop_first()
+        {
+            entries.pages.remove(&least_recently_used);
+        }
+
+        entries.by_last_use.insert(clock, key.clone());
+        entries.pages.insert(
+            key,
+            MemoryEntry {
+                page,
+                stored_at: Instant::now(),
+                last_used: clock,
+            },
+        );
+    }
+
+    fn remove(&self, key: &CacheKey) {
+        let mut entries = self.entries.lock().unwrap_or_else(|this| this.into_inner());
+        entries.remove(key);
+    }
+}
+
+/// Stores each body in its own file, as `<unix timestamp>\n<key>\n<body>`. The key is stored so
+/// that two keys with the same hash can't return each other's pages.
+struct DiskCache {
+    dir: PathBuf,
+}
+
+impl DiskCache {
+    /// The file for `key`, named after a hash which is stable between releases, so pages
+    /// cached by an earlier build are still found.
+    fn path(&self, key: &CacheKey) -> PathBuf {
+        self.dir
+            .join(format!("{:016x}.cache", fnv1a(key.to_line().as_bytes())))
+    }
+
+    fn get(&self, key: &CacheKey, ttl: Duration) -> Option<String> {
+        let path = self.path(key);
+        let contents = fs::read_to_string(&path).ok()?;
+
+        let mut parts = contents.splitn(3, '\n');
+        let stored_at = parts.next()?.parse().ok()?;
+        let stored_key = parts.next()?;
+        let body = parts.next()?;
+
+        if stored_key != key.to_line() {
+            return None;
+        }
+
+        let is_fresh = (UNIX_EPOCH + Duration::from_secs(stored_at))
+            .elapsed()
+            .is_ok_and(|age| age <= ttl);
+        if !is_fresh {
+            let _ = fs::remove_file(&path);
+            return None;
+        }
+
+        Some(body.to_string())
+    }
+
+    /// Writes the page to a temporary file first and then moves it into place, so other
+    /// processes sharing the directory never read a partly written page. Each write has its own
+    /// temporary file, as threads writing the same page would otherwise overwrite each other's.
+    fn insert(&self, key: &CacheKey, body: &str) -> io::Result<()> {
+        let stored_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |this| this.as_secs());
+
+        let path = self.path(key);
+        let temp_path = path.with_extension(format!(
+            "tmp{}-{}",
+            std::process::id(),
+            TEMP_FILES.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        fs::write(
+            &temp_path,
+            format!("{stored_at}\n{}\n{body}", key.to_line()),
+        )
+        .and_then(|_| fs::rename(&temp_path, &path))
+        .inspect_err(|_| {
+            let _ = fs::remove_file(&temp_path);
+        })
+    }
+
+    fn remove(&self, key: &CacheKey) -> io::Result<()> {
+        match fs::remove_file(self.path(key)) {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The 64-bit FNV-1a hash of `bytes`.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(FNV_PRIME)
+    })
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+static TEMP_FILES: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    use crate::testing::{FakeFetcher, TestEngine, logs, options};
+
+    fn temp_dir() -> PathBuf {
+        static DIRS: AtomicUsize = AtomicUsize::new(0);
+
+        std::env::temp_dir().join(format!(
+            "quaero_engines_cache_{}_{}",
+            std::process::id(),
+            DIRS.fetch_add(1, Ordering::Relaxed)
+        ))
+    }
+
+    async fn fetch(cache: &ResultCache, fetcher: &FakeFetcher, query: &str) -> ParsedPage {
+        cache
+            .fetch_page(&TestEngine::default(), fetcher, query, &options())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn serves_repeated_queries_from_memory() {
+        let cache = ResultCache::new(Duration::from_secs(60)).with_memory(2);
+        let fetcher = FakeFetcher::default().with_body("https://a.test A");
+
+        fetch(&cache, &fetcher, "rust").await;
+        let page = fetch(&cache, &fetcher, "  rust ").await;
+
+        assert_eq!(page.results[0].url, "https://a.test");
+        assert_eq!(fetcher.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn evicts_the_least_recently_used_page() {
+        let cache = ResultCache::new(Duration::from_secs(60)).with_memory(2);
+        let fetcher = FakeFetcher::default()
+            .with_body("https://a.test A")
+            .with_body("https://b.test B")
+            .with_body("https://c.test C")
+            .with_body("https://b.test B");
+
+        fetch(&cache, &fetcher, "a").await;
+        fetch(&cache, &fetcher, "b").await;
+        fetch(&cache, &fetcher, "a").await;
+        fetch(&cache, &fetcher, "c").await;
+        fetch(&cache, &fetcher, "a").await;
+        fetch(&cache, &fetcher, "b").await;
+
+        let queries = fetcher
+            .requests()
+            .into_iter()
+            .map(|this| this.url)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            queries,
+            [
+                "http://search.test/search?q=a&page=0",
+                "http://search.test/search?q=b&page=0",
+                "http://search.test/search?q=c&page=0",
+                "http://search.test/search?q=b&page=0",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn fetches_expired_pages_again() {
+        let cache = ResultCache::new(Duration::ZERO).with_memory(2);
+        let fetcher = FakeFetcher::default()
+            .with_body("https://a.test A")
+            .with_body("https://b.test B");
+
+        fetch(&cache, &fetcher, "rust").await;
+        std::thread::sleep(Duration::from_millis(2));
+        let page = fetch(&cache, &fetcher, "rust").await;
+
+        assert_eq!(page.results[0].url, "https://b.test");
+    }
+
+    #[tokio::test]
+    async fn serves_pages_from_disk_to_other_caches() {
+        let dir = temp_dir();
+        let fetcher = FakeFetcher::default().with_body("https://a.test A");
+
+        let cache = ResultCache::new(Duration::from_secs(60))
+            .with_disk(&dir)
+            .unwrap();
+        fetch(&cache, &fetcher, "rust").await;
+
+        let cache = ResultCache::new(Duration::from_secs(60))
+            .with_disk(&dir)
+            .unwrap();
+        let page = fetch(&cache, &fetcher, "rust").await;
+
+        assert_eq!(page.results[0].url, "https://a.test");
+        assert_eq!(fetcher.requests().len(), 1);
+
+        // Only the page is left behind, under a name derived from its key.
+        let key = CacheKey::new(&TestEngine::default(), "rust", &options());
+        let files = fs::read_dir(&dir)
+            .unwrap()
+            .map(|this| this.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            files,
+            [format!("{:016x}.cache", fnv1a(key.to_line().as_bytes()))]
+        );
+
+        cache.remove(&key).unwrap();
+        cache.remove(&key).unwrap();
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+
+        fs::remove_dir(dir).unwrap();
+    }
+
+    #[test]
+    fn writes_the_same_page_from_many_threads_at_once() {
+        let dir = temp_dir();
+        let cache = Arc::new(
+            ResultCache::new(Duration::from_secs(60))
+                .with_disk(&dir)
+                .unwrap(),
+        );
+        let key = CacheKey::new(&TestEngine::default(), "rust", &options());
+
+        let writers = (0..8)
+            .map(|_| {
+                let cache = cache.clone();
+                let key = key.clone();
+
+                std::thread::spawn(move || {
+                    let disk = cache.disk.as_ref().unwrap();
+                    (0..20).all(|_| disk.insert(&key, "https://a.test A").is_ok())
+                })
+            })
+            .collect::<Vec<_>>();
+
+        assert!(writers.into_iter().all(|this| this.join().unwrap()));
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn logs_pages_which_could_not_be_written_to_disk() {
+        logs();
+
+        let dir = temp_dir();
+        let cache = ResultCache::new(Duration::from_secs(60))
+            .with_disk(&dir)
+            .unwrap();
+        fs::remove_dir(&dir).unwrap();
+
+        let fetcher = FakeFetcher::default().with_body("https://a.test A");
+        let page = fetch(&cache, &fetcher, "rust").await;
+
+        assert_eq!(page.results.len(), 1);
+        assert!(
+            logs()
+                .iter()
+                .any(|this| this.starts_with("WARN failed to cache a page from test: "))
+        );
+    }
+
+    #[test]
+    fn writes_keys_in_a_stable_format() {
+        let key = CacheKey {
+            engine: "google".to_string(),
+            query: "rust lang".to_string(),
+            page: 2,
+            results_per_page: 10,
+            safe_search: 1,
+            date_time_range: Some((1_700_000_000, 1_700_086_400)),
+        };
+
+        assert_eq!(
+            key.to_line(),
+            "google\trust lang\t2\t10\t1\t1700000000..1700086400"
+        );
+    }
+
+    #[test]
+    fn hashes_with_fnv1a() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+}
@@ -0,0 +1,491 @@
+//! Recording the full request path of an engine (its url, headers, response and parsed results)
+//! into cassettes, and replaying them through a local stub server to test engines offline.
+//!
+//! ```ignore
+//! let (_, cassette) = record_page(&engine, &fetcher, "rust", &options).await?;
+//! cassette.save("cassettes/google_rust.json")?;
+//!
+//! let cassette = Cassette::load("cassettes/google_rust.json")?;
+//! let report = replay_page(&engine, &fetcher, "rust", &options, &cassette).await?;
+//! assert!(report.mismatches.is_empty(), "{:#?}", report.mismatches);
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::Path,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+
+use http::{HeaderMap, StatusCode};
+use quaero_shared::models::search::SearchOptions;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    egress::Egress,
+    engine::RichEngine,
+    error::EngineError,
+    fetch::{FetchRequest, FetchedResponse, Fetcher, fetch_page},
+    results::ParsedPage,
+};
+
+/// A recording of the requests an engine made for a results page and what it parsed from them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cassette {
+    /// The requests which were sent and their responses, in order.
+    pub interactions: Vec<Interaction>,
+    /// The results which were parsed from the responses.
+    pub results: Vec<RecordedResult>,
+}
+
+/// A request and its response.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Interaction {
+    /// The request which was sent.
+    pub request: RecordedRequest,
+    /// The response which was received.
+    pub response: RecordedResponse,
+}
+
+/// A recorded request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    /// The url the engine built.
+    pub url: String,
+    /// The headers the engine set, as names and values.
+    pub headers: Vec<(String, String)>,
+}
+
+/// A recorded response.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    /// The status code of the response.
+    pub status: u16,
+    /// The final url of the response, after any redirects.
+    pub url: String,
+    /// The body of the response.
+    pub body: String,
+}
+
+/// A recorded result.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedResult {
+    /// The title of the result.
+    pub title: String,
+    /// The url of the result.
+    pub url: String,
+    /// The summary of the result.
+    pub summary: String,
+    /// Whether the result is sponsored.
+    pub sponsored: bool,
+}
+
+impl Cassette {
+    /// Reads a cassette from a json file.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    /// Writes the cassette to a json file.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+
+    fn record_results(&mut self, page: &ParsedPage) {
+        self.results = page
+            .results
+            .iter()
+            .map(|this| RecordedResult {
+                title: this.title.clone(),
+                url: this.url.clone(),
+                summary: this.summary.clone(),
+                sponsored: this.sponsored,
+            })
+            .collect();
+    }
+}
+
+/// Fetches and parses the results page for `query` from `engine`, recording everything into a
+/// [`Cassette`].
+pub async fn record_page<E, F>(
+    engine: &E,
+    fetcher: &F,
+    query: &str,
+    options: &SearchOptions,
+) -> Result<(ParsedPage, Cassette), EngineError>
+where
+    E: RichEngine + ?Sized,
+    F: Fetcher + ?Sized,
+{
+    let recording_fetcher = RecordingFetcher {
+        fetcher,
+        interactions: Mutex::new(Vec::new()),
+    };
+
+    let page = fetch_page(engine, &recording_fetcher, query, options).await?;
+
+    let mut cassette = Cassette {
+        interactions: recording_fetcher
+            .interactions
+            .into_inner()
+            .unwrap_or_else(|this| this.into_inner()),
+        results: Vec::new(),
+    };
+    cassette.record_results(&page);
+
+    Ok((page, cassette))
+}
+
+/// The outcome of replaying a [`Cassette`].
+#[derive(Debug)]
+pub struct ReplayReport {
+    /// The page parsed from the replayed responses.
+    pub page: ParsedPage,
+    /// Every way the replay differed from the recording. This is empty if the engine built the
+    /// same urls, sent the same headers and parsed the same results.
+    pub mismatches: Vec<String>,
+}
+
+/// Replays `cassette` through a local [`StubServer`], using `fetcher` to send the requests.
+///
+/// Header values are compared too, so replay engines with a fixed
+/// [`browser_profile`](crate::config::EngineConfig::browser_profile), as they pick a random one
+/// for each request by default.
+pub async fn replay_page<E, F>(
+    engine: &E,
+    fetcher: &F,
+    query: &str,
+    options: &SearchOptions,
+    cassette: &Cassette,
+) -> Result<ReplayReport, EngineError>
+where
+    E: RichEngine + ?Sized,
+    F: Fetcher + ?Sized,
+{
+    let server =
+        StubServer::start(cassette).map_err(|error| EngineError::Transport(error.into()))?;
+
+    let replaying_fetcher = ReplayingFetcher {
+        fetcher,
+        server: &server,
+    };
+    let page = fetch_page(engine, &replaying_fetcher, query, options).await?;
+
+    let mut mismatches = server.mismatches();
+
+    let mut replayed = Cassette::default();
+    replayed.record_results(&page);
+
+    if replayed.results.len() != cassette.results.len() {
+        mismatches.push(format!(
+            "expected {} results but parsed {}",
+            cassette.results.len(),
+            replayed.results.len()
+        ));
+    }
+    for (idx, (expected, actual)) in cassette.results.iter().zip(&replayed.results).enumerate() {
+        if expected != actual {
+            mismatches.push(format!(
+                "result {idx} differs: expected {expected:?} but parsed {actual:?}"
+            ));
+        }
+    }
+
+    Ok(ReplayReport { page, mismatches })
+}
+
+struct RecordingFetcher<'a, F: ?Sized> {
+    fetcher: &'a F,
+    interactions: Mutex<Vec<Interaction>>,
+}
+
+#[async_trait::async_trait]
+impl<F: Fetcher + ?Sized> Fetcher for RecordingFetcher<'_, F> {
+    async fn fetch(&self, request: FetchRequest) -> Result<FetchedResponse, EngineError> {
+        let recorded_request = RecordedRequest {
+            url: request.url.clone(),
+            headers: header_pairs(&request.headers),
+        };
+
+        let fetched_response = self.fetcher.fetch(request).await?;
+
+        let recorded_response = RecordedResponse {
+            status: fetched_response.response.status().as_u16(),
+            url: fetched_response.response.url().to_string(),
+            body: fetched_response.body.clone(),
+        };
+
+        self.interactions
+            .lock()
+            .unwrap_or_else(|this| this.into_inner())
+            .push(Interaction {
+                request: recorded_request,
+                response: recorded_response,
+            });
+
+        Ok(fetched_response)
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        self.fetcher.sleep(duration).await
+    }
+}
+
+struct ReplayingFetcher<'a, F: ?Sized> {
+    fetcher: &'a F,
+    server: &'a StubServer,
+}
+
+#[async_trait::async_trait]
+impl<F: Fetcher + ?Sized> Fetcher for ReplayingFetcher<'_, F> {
+    async fn fetch(&self, request: FetchRequest) -> Result<FetchedResponse, EngineError> {
+        self.fetcher
+            .fetch(FetchRequest {
+                url: self.server.url_for(&request.url),
+                // The stub server is local, so it's never reached through a proxy.
+                egress: Egress::default(),
+                ..request
+            })
+            .await
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        self.fetcher.sleep(duration).await
+    }
+}
+
+/// A local http server which answers the requests in a [`Cassette`] with their recorded
+/// responses, noting any requests which don't match the recording (their host, path, query or
+/// headers).
+///
+/// Responses whose final url differs from their request's url are served as a redirect to the
+/// final url's path, so engines which detect captchas by their path still see them.
+pub struct StubServer {
+    address: SocketAddr,
+    interactions: Vec<Interaction>,
+    mismatches: Arc<Mutex<Vec<String>>>,
+    is_stopped: Arc<AtomicBool>,
+}
+
+impl StubServer {
+    /// Starts serving `cassette` on a random local port.
+    pub fn start(cassette: &Cassette) -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let address = listener.local_addr()?;
+
+        let mismatches = Arc::new(Mutex::new(Vec::new()));
+        let is_stopped = Arc::new(AtomicBool::new(false));
+
+        let interactions = cassette.interactions.clone();
+        let thread_mismatches = mismatches.clone();
+        let thread_is_stopped = is_stopped.clone();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if thread_is_stopped.load(Ordering::Relaxed) {
+                    break;
+                }
+
+                if let Ok(stream) = stream {
+                    let _ = serve(stream, &interactions, &thread_mismatches);
+                }
+            }
+        });
+
+        Ok(Self {
+            address,
+            interactions: cassette.interactions.clone(),
+            mismatches,
+            is_stopped,
+        })
+    }
+
+    /// The address the server is listening on.
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Rewrites `url` to point at the server, keeping its path and query. As the server only
+    /// sees the path and query, a host which differs from the recorded request's is noted here.
+    pub fn url_for(&self, url: &str) -> String {
+        let target = path_and_query(url);
+
+        if let Some(interaction) = self
+            .interactions
+            .iter()
+            .find(|this| path_and_query(&this.request.url) == target)
+            && host(&interaction.request.url) != host(url)
+        {
+            self.mismatches
+                .lock()
+                .unwrap_or_else(|this| this.into_inner())
+                .push(format!(
+                    "request for {target} was sent to {} but {} was recorded",
+                    host(url),
+                    host(&interaction.request.url)
+                ));
+        }
+
+        format!("http://{}{target}", self.address)
+    }
+
+    /// Every way the requests the server received differed from the recording so far.
+    pub fn mismatches(&self) -> Vec<String> {
+        self.mismatches
+            .lock()
+            .unwrap_or_else(|this| this.into_inner())
+            .clone()
+    }
+}
+
+impl Drop for StubServer {
+    fn drop(&mut self) {
+        self.is_stopped.store(true, Ordering::Relaxed);
+        // Wakes the server thread up so it sees it has been stopped.
+        let _ = TcpStream::connect(self.address);
+    }
+}
+
+fn serve(
+    mut stream: TcpStream,
+    interactions: &[Interaction],
+    mismatches: &Mutex<Vec<String>>,
+) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let target = request_line
+        .split_whitespace()
+        .nth(1)
+        .unwrap_or_default()
+        .to_string();
+
+    let mut headers = BTreeMap::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let push_mismatch = |mismatch: String| {
+        mismatches
+            .lock()
+            .unwrap_or_else(|this| this.into_inner())
+            .push(mismatch)
+    };
+
+    // Requests for the final url of a redirected response.
+    if let Some(interaction) = interactions
+        .iter()
+        .find(|this| path_and_query(&this.response.url) == target)
+        .filter(|this| path_and_query(&this.request.url) != target)
+    {
+        return write_response(&mut stream, &interaction.response, None);
+    }
+
+    let Some(interaction) = interactions
+        .iter()
+        .find(|this| path_and_query(&this.request.url) == target)
+    else {
+        push_mismatch(format!("unexpected request for {target}"));
+        return write_response(
+            &mut stream,
+            &RecordedResponse {
+                status: StatusCode::NOT_FOUND.as_u16(),
+                url: target,
+                body: String::new(),
+            },
+            None,
+        );
+    };
+
+    let mut missing_header_names = Vec::new();
+    for (name, expected_value) in &interaction.request.headers {
+        let name = name.to_lowercase();
+
+        match headers.get(&name) {
+            None => missing_header_names.push(name),
+            Some(value) if value != expected_value.trim() => push_mismatch(format!(
+                "request for {target} sent {name}: {value:?} but {expected_value:?} was recorded"
+            )),
+            Some(_) => {}
+        }
+    }
+    if !missing_header_names.is_empty() {
+        push_mismatch(format!(
+            "request for {target} is missing headers {missing_header_names:?}"
+        ));
+    }
+
+    let redirect = path_and_query(&interaction.response.url);
+    if redirect != target {
+        return write_response(&mut stream, &interaction.response, Some(redirect));
+    }
+
+    write_response(&mut stream, &interaction.response, None)
+}
+
+fn write_response(
+    stream: &mut TcpStream,
+    response: &RecordedResponse,
+    redirect: Option<&str>,
+) -> io::Result<()> {
+    let (status, location, body) = match redirect {
+        Some(redirect) => (StatusCode::FOUND, format!("Location: {redirect}\r\n"), ""),
+        None => (
+            StatusCode::from_u16(response.status).unwrap_or(StatusCode::OK),
+            String::new(),
+            response.body.as_str(),
+        ),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\n{location}Content-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        status.as_u16(),
+        status.canonical_reason().unwrap_or_default(),
+        body.len(),
+    )?;
+    stream.flush()
+}
+
+/// The path and query of a url, e.g. `/search?q=rust` for `https://www.google.com/search?q=rust`.
+fn path_and_query(url: &str) -> &str {
+    let without_scheme = url.split_once("://").map_or(url, |(_, rest)| rest);
+    without_scheme
+        .find('/')
+        .map_or("/", |idx| &without_scheme[idx..])
+}
+
+/// The host (and port) of a url, e.g. `www.google.com` for `https://www.google.com/search`.
+fn host(url: &str) -> &str {
+    let without_scheme = url.split_once("://").map_or(url, |(_, rest)| rest);
+    without_scheme
+        .split_once('/')
+        .map_or(without_scheme, |(host, _)| host)
+}
+
+fn header_pairs(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            (
+                name.to_string(),
+                value.to_str().unwrap_or_default().to_string(),
+            )
+        })
+        .collect()
+}
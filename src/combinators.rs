@@ -0,0 +1,513 @@
+//! Wrappers which build new engines out of existing ones, e.g. a Rust docs search from
+//! [`GoogleEngine`](crate::GoogleEngine) restricted to `docs.rs`.
+//!
+//! ```ignore
+//! let docs = SiteRestricted::new(GoogleEngine::default(), ["docs.rs", "doc.rust-lang.org"]);
+//! let docs = Renamed::new(docs, "google-docs").into_tagged();
+//! ```
+//!
+//! Engines which only implement quaero's [`Engine`] can be wrapped with [`Plain`] first.
+
+use anyhttp::Response;
+use http::HeaderMap;
+use quaero_shared::models::{
+    engine::{Engine, TaggedEngine},
+    search::{SafeSearch, SearchError, SearchOptions, SearchResult},
+};
+
+use crate::{
+    config::EngineConfig,
+    engine::{RichEngine, parse_for_quaero},
+    error::ParseError,
+    pacing::RequestBudget,
+    query::{ParsedQuery, QuerySyntax, url_is_on_site},
+    results::{ParsedPage, RichSearchResult},
+};
+
+/// Implements methods of [`Engine`] and [`RichEngine`] by passing them to the wrapped engine
+/// (the wrapper's `engine` field), so a wrapper only writes out the methods it changes.
+///
+/// ```ignore
+/// impl<E: RichEngine> RichEngine for Renamed<E> {
+///     delegate_to_engine!(config, results_per_page, parse_rich);
+///
+///     fn id(&self) -> &str {
+///         &self.id
+///     }
+/// }
+/// ```
+macro_rules! delegate_to_engine {
+    ($($method:ident),+ $(,)?) => {
+        $(delegate_to_engine!(@$method);)+
+    };
+    (@homepage) => {
+        fn homepage(&self) -> &'static str {
+            self.engine.homepage()
+        }
+    };
+    (@url) => {
+        fn url(&self, query: &str, options: &SearchOptions) -> Result<String, SearchError> {
+            self.engine.url(query, options)
+        }
+    };
+    (@headers) => {
+        fn headers(&self, headers: &mut HeaderMap, options: &SearchOptions) {
+            self.engine.headers(headers, options)
+        }
+    };
+    (@validate_response) => {
+        fn validate_response(&self, response: &Response) -> Result<(), SearchError> {
+            self.engine.validate_response(response)
+        }
+    };
+    (@id) => {
+        fn id(&self) -> &str {
+            self.engine.id()
+        }
+    };
+    (@config) => {
+        fn config(&self) -> &EngineConfig {
+            self.engine.config()
+        }
+    };
+    (@results_per_page) => {
+        fn results_per_page(&self) -> usize {
+            self.engine.results_per_page()
+        }
+    };
+    (@request_budget) => {
+        fn request_budget(&self) -> RequestBudget {
+            self.engine.request_budget()
+        }
+    };
+    (@supports_safe_search) => {
+        fn supports_safe_search(&self, safe_search: &SafeSearch) -> bool {
+            self.engine.supports_safe_search(safe_search)
+        }
+    };
+    (@query_syntax) => {
+        fn query_syntax(&self) -> QuerySyntax {
+            self.engine.query_syntax()
+        }
+    };
+    (@warm_up_tokens) => {
+        fn warm_up_tokens(&self, homepage: &str) -> Vec<(String, String)> {
+            self.engine.warm_up_tokens(homepage)
+        }
+    };
+    (@parse_rich) => {
+        fn parse_rich(&self, response_text: String) -> Result<ParsedPage, ParseError> {
+            self.engine.parse_rich(response_text)
+        }
+    };
+}
+
+/// An engine whose results are restricted to a set of sites.
+pub struct SiteRestricted<E> {
+    engine: E,
+    sites: Vec<String>,
+}
+
+impl<E: RichEngine> SiteRestricted<E> {
+    /// Restricts `engine` to `sites`, which may include a path (e.g. `docs.rs/tokio`).
+    pub fn new(engine: E, sites: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            engine,
+            sites: sites
+                .into_iter()
+                .map(|this| this.into().to_lowercase())
+                .collect(),
+        }
+    }
+
+    /// Converts the engine into a [`TaggedEngine`] for quaero.
+    pub fn into_tagged(self) -> TaggedEngine
+    where
+        Self: 'static,
+    {
+        TaggedEngine::new(self)
+    }
+
+    /// Adds the site restriction to `query`, unless it's already restricted to one of the
+    /// allowed sites. Sites outside of them are still removed from the results.
+    fn restrict(&self, query: &str) -> String {
+        let is_restricted = ParsedQuery::parse(query).sites.iter().any(|site| {
+            self.sites
+                .iter()
+                .any(|allowed_site| url_is_on_site(site, allowed_site))
+        });
+        if is_restricted {
+            return query.to_string();
+        }
+
+        let restriction = ParsedQuery {
+            sites: self.sites.clone(),
+            ..ParsedQuery::default()
+        }
+        .render(&self.engine.query_syntax())
+        .query;
+
+        format!("{query} {restriction}")
+    }
+}
+
+#[async_trait::async_trait]
+impl<E: RichEngine> Engine for SiteRestricted<E> {
+    delegate_to_engine!(homepage, headers, validate_response);
+
+    fn url(&self, query: &str, options: &SearchOptions) -> Result<String, SearchError> {
+        self.engine.url(&self.restrict(query), options)
+    }
+
+    fn parse<'a>(&self, response_text: String) -> Result<Vec<(String, SearchResult)>, SearchError> {
+        parse_for_quaero(self, response_text)
+    }
+}
+
+impl<E: RichEngine> RichEngine for SiteRestricted<E> {
+    delegate_to_engine!(
+        id,
+        config,
+        results_per_page,
+        request_budget,
+        supports_safe_search,
+        query_syntax,
+        warm_up_tokens
+    );
+
+    // Results are also filtered here, as not every provider supports `site:` and the ones
+    // which do still mix in the odd result from elsewhere.
+    fn parse_rich(&self, response_text: String) -> Result<ParsedPage, ParseError> {
+        let mut page = self.engine.parse_rich(response_text)?;
+
+        page.results.retain(|result| {
+            self.sites
+                .iter()
+                .any(|site| url_is_on_site(&result.url, site))
+        });
+
+        Ok(page)
+    }
+}
+
+/// An engine which drops the results that don't satisfy a predicate.
+///
+/// Use [`excluding_sites`] to drop results by domain. To drop them by a pattern, match it in the
+/// predicate (e.g. `move |result| !pattern.is_match(&result.url)`).
+pub struct Filtered<E, F> {
+    engine: E,
+    predicate: F,
+}
+
+impl<E, F> Filtered<E, F>
+where
+    E: RichEngine,
+    F: Fn(&RichSearchResult) -> bool + Send + Sync,
+{
+    /// Keeps only the results from `engine` for which `predicate` returns `true`.
+    pub fn new(engine: E, predicate: F) -> Self {
+        Self { engine, predicate }
+    }
+
+    /// Converts the engine into a [`TaggedEngine`] for quaero.
+    pub fn into_tagged(self) -> TaggedEngine
+    where
+        Self: 'static,
+    {
+        TaggedEngine::new(self)
+    }
+}
+
+/// Drops the results from `engine` which are on any of `sites`.
+pub fn excluding_sites<E: RichEngine>(
+    engine: E,
+    sites: impl IntoIterator<Item = impl Into<String>>,
+) -> Filtered<E, impl Fn(&RichSearchResult) -> bool + Send + Sync> {
+    let sites = sites
+        .into_iter()
+        .map(|this| this.into().to_lowercase())
+        .collect::<Vec<_>>();
+
+    Filtered::new(engine, move |result: &RichSearchResult| {
+        !sites.iter().any(|site| url_is_on_site(&result.url, site))
+    })
+}
+
+#[async_trait::async_trait]
+impl<E, F> Engine for Filtered<E, F>
+where
+    E: RichEngine,
+    F: Fn(&RichSearchResult) -> bool + Send + Sync,
+{
+    delegate_to_engine!(homepage, url, headers, validate_response);
+
+    fn parse<'a>(&self, response_text: String) -> Result<Vec<(String, SearchResult)>, SearchError> {
+        parse_for_quaero(self, response_text)
+    }
+}
+
+impl<E, F> RichEngine for Filtered<E, F>
+where
+    E: RichEngine,
+    F: Fn(&RichSearchResult) -> bool + Send + Sync,
+{
+    delegate_to_engine!(
+        id,
+        config,
+        results_per_page,
+        request_budget,
+        supports_safe_search,
+        query_syntax,
+        warm_up_tokens
+    );
+
+    fn parse_rich(&self, response_text: String) -> Result<ParsedPage, ParseError> {
+        let mut page = self.engine.parse_rich(response_text)?;
+        page.results.retain(|result| (self.predicate)(result));
+
+        Ok(page)
+    }
+}
+
+/// An engine with a different id, so it can be told apart from the engine it wraps.
+pub struct Renamed<E> {
+    engine: E,
+    id: String,
+}
+
+impl<E: RichEngine> Renamed<E> {
+    /// Gives `engine` the id `id`.
+    pub fn new(engine: E, id: impl Into<String>) -> Self {
+        Self {
+            engine,
+            id: id.into(),
+        }
+    }
+
+    /// Converts the engine into a [`TaggedEngine`] for quaero.
+    pub fn into_tagged(self) -> TaggedEngine
+    where
+        Self: 'static,
+    {
+        TaggedEngine::new(self)
+    }
+}
+
+#[async_trait::async_trait]
+impl<E: RichEngine> Engine for Renamed<E> {
+    delegate_to_engine!(homepage, url, headers, validate_response);
+
+    fn parse<'a>(&self, response_text: String) -> Result<Vec<(String, SearchResult)>, SearchError> {
+        parse_for_quaero(self, response_text)
+    }
+}
+
+impl<E: RichEngine> RichEngine for Renamed<E> {
+    delegate_to_engine!(
+        config,
+        results_per_page,
+        request_budget,
+        supports_safe_search,
+        query_syntax,
+        warm_up_tokens,
+        parse_rich
+    );
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+/// An engine which only implements quaero's [`Engine`], so it can be wrapped like the engines in
+/// this crate. Its results only have a url (their key), so a [`Filtered`] predicate can't look at
+/// their titles or summaries.
+pub struct Plain<E> {
+    engine: E,
+    id: String,
+    config: EngineConfig,
+}
+
+impl<E: Engine> Plain<E> {
+    /// Wraps `engine`, giving it the id `id`.
+    pub fn new(engine: E, id: impl Into<String>) -> Self {
+        Self {
+            engine,
+            id: id.into(),
+            config: EngineConfig::default(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<E: Engine> Engine for Plain<E> {
+    delegate_to_engine!(homepage, url, headers, validate_response);
+
+    fn parse<'a>(&self, response_text: String) -> Result<Vec<(String, SearchResult)>, SearchError> {
+        self.engine.parse(response_text)
+    }
+}
+
+impl<E: Engine> RichEngine for Plain<E> {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn config(&self) -> &EngineConfig {
+        &self.config
+    }
+
+    fn parse_rich(&self, response_text: String) -> Result<ParsedPage, ParseError> {
+        let results = self
+            .engine
+            .parse(response_text)?
+            .into_iter()
+            .map(RichSearchResult::from_pair)
+            .collect();
+
+        Ok(ParsedPage::new(results))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::{TestEngine, options};
+
+    const PAGE: &str = "https://docs.rs/tokio Tokio\nhttps://www.pinterest.com/pin/1 Pin\nhttps://doc.rust-lang.org/book The Book";
+
+    fn urls(page: ParsedPage) -> Vec<String> {
+        page.results.into_iter().map(|this| this.url).collect()
+    }
+
+    #[test]
+    fn appends_the_site_restriction_to_the_query() {
+        let engine = SiteRestricted::new(TestEngine::default(), ["docs.rs", "doc.rust-lang.org"]);
+
+        assert_eq!(
+            engine
+                .url(r#"-java "borrow checker" rust"#, &options())
+                .unwrap(),
+            r#"http://search.test/search?q=-java "borrow checker" rust (site:docs.rs OR site:doc.rust-lang.org)&page=0"#
+        );
+    }
+
+    #[test]
+    fn keeps_a_site_restriction_within_the_allowed_sites() {
+        let engine = SiteRestricted::new(TestEngine::default(), ["docs.rs"]);
+
+        assert_eq!(
+            engine.url("spawn site:docs.rs/tokio", &options()).unwrap(),
+            "http://search.test/search?q=spawn site:docs.rs/tokio&page=0"
+        );
+    }
+
+    #[test]
+    fn removes_results_outside_of_the_allowed_sites() {
+        let engine = SiteRestricted::new(TestEngine::default(), ["docs.rs", "doc.rust-lang.org"]);
+
+        assert_eq!(
+            urls(engine.parse_rich(PAGE.to_string()).unwrap()),
+            ["https://docs.rs/tokio", "https://doc.rust-lang.org/book"]
+        );
+    }
+
+    #[test]
+    fn removes_results_which_fail_the_predicate() {
+        let engine = Filtered::new(TestEngine::default(), |result: &RichSearchResult| {
+            result.title != "Tokio"
+        });
+
+        assert_eq!(
+            urls(engine.parse_rich(PAGE.to_string()).unwrap()),
+            [
+                "https://www.pinterest.com/pin/1",
+                "https://doc.rust-lang.org/book"
+            ]
+        );
+    }
+
+    #[test]
+    fn removes_results_on_excluded_sites() {
+        let engine = excluding_sites(TestEngine::default(), ["pinterest.com"]);
+
+        assert_eq!(
+            urls(engine.parse_rich(PAGE.to_string()).unwrap()),
+            ["https://docs.rs/tokio", "https://doc.rust-lang.org/book"]
+        );
+    }
+
+    #[test]
+    fn tags_results_with_the_new_id() {
+        let engine = Renamed::new(TestEngine::default(), "test-docs");
+        let page = engine.parse_page(PAGE.to_string(), &options()).unwrap();
+
+        assert_eq!(engine.id(), "test-docs");
+        assert!(
+            page.results
+                .iter()
+                .all(|this| this.provenance.engine == "test-docs")
+        );
+    }
+
+    #[test]
+    fn passes_everything_else_to_the_wrapped_engine() {
+        let engine = Renamed::new(TestEngine::default(), "test-docs");
+
+        assert_eq!(engine.homepage(), "http://search.test/");
+        assert_eq!(
+            engine.url("rust", &options()).unwrap(),
+            "http://search.test/search?q=rust&page=0"
+        );
+        assert_eq!(
+            engine.results_per_page(),
+            TestEngine::default().results_per_page()
+        );
+        assert_eq!(urls(engine.parse_rich(PAGE.to_string()).unwrap()).len(), 3);
+    }
+
+    /// An engine which only implements quaero's [`Engine`].
+    struct PlainEngine;
+
+    #[async_trait::async_trait]
+    impl Engine for PlainEngine {
+        fn homepage(&self) -> &'static str {
+            "http://plain.test/"
+        }
+
+        fn url(&self, query: &str, _options: &SearchOptions) -> Result<String, SearchError> {
+            Ok(format!("http://plain.test/?q={query}"))
+        }
+
+        fn parse<'a>(
+            &self,
+            response_text: String,
+        ) -> Result<Vec<(String, SearchResult)>, SearchError> {
+            Ok(response_text
+                .lines()
+                .filter_map(|this| this.split_once(' '))
+                .map(|(url, title)| {
+                    SearchResult::new(title.to_string(), url.to_string(), String::new())
+                })
+                .collect())
+        }
+    }
+
+    #[test]
+    fn wraps_a_plain_engine() {
+        let engine = SiteRestricted::new(Plain::new(PlainEngine, "plain"), ["docs.rs"]);
+
+        assert_eq!(
+            engine.url("tokio", &options()).unwrap(),
+            "http://plain.test/?q=tokio site:docs.rs"
+        );
+        assert_eq!(
+            urls(engine.parse_rich(PAGE.to_string()).unwrap()),
+            ["https://docs.rs/tokio"]
+        );
+
+        let pairs = Engine::parse(&engine, PAGE.to_string()).unwrap();
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(pairs[0].0, "https://docs.rs/tokio");
+    }
+}
@@ -0,0 +1,101 @@
+//! Configuration for the engines in this crate.
+
+use std::sync::Arc;
+
+use anyhttp::Response;
+use http::{HeaderMap, HeaderValue, header::COOKIE};
+
+use crate::{
+    cookies::CookieJar,
+    egress::Egress,
+    overrides::{EngineSelectors, SelectorOverrides},
+    pacing::Pacer,
+    profiles::BrowserProfile,
+    warmup::WarmUp,
+};
+
+/// Options which change how an engine from this crate behaves.
+#[derive(Debug, Clone, Default)]
+pub struct EngineConfig {
+    /// What the engine does with sponsored results (ads).
+    pub ads: AdHandling,
+    /// How many results to request per page. This is clamped to the provider's maximum,
+    /// and ignored by engines whose provider doesn't let you change the page size.
+    /// quaero's search options have no page size, so create an engine with another config
+    /// for requests which need a different one.
+    pub results_per_page: Option<usize>,
+    /// Selectors which replace the engine's built-in ones, e.g. after a provider renamed its
+    /// classes.
+    pub selector_overrides: Option<Arc<SelectorOverrides>>,
+    /// The browser whose headers the engine sends. By default engines pick a random browser for
+    /// each request, from the browsers their provider serves the expected pages to.
+    pub browser_profile: Option<BrowserProfile>,
+    /// Keeps the cookies the provider sets between requests. The jar is seeded with the engine's
+    /// default cookies, and without one only the defaults are sent.
+    pub cookie_jar: Option<CookieJar>,
+    /// Visits the engine's homepage before searching, to collect the cookies and tokens its
+    /// provider expects. Pair this with a [`cookie_jar`](Self::cookie_jar) to keep the cookies.
+    pub warm_up: Option<WarmUp>,
+    /// How the engine's requests are routed, e.g. through a proxy. By default they connect
+    /// directly.
+    pub egress: Egress,
+    /// Paces the engine's requests to stay within its
+    /// [`request_budget`](crate::engine::RichEngine::request_budget), when it's driven with
+    /// the [`fetch`](crate::fetch) functions.
+    pub pacer: Option<Pacer>,
+}
+
+impl EngineConfig {
+    /// The page size to request from a provider which returns `default` results per page
+    /// and at most `max`.
+    pub(crate) fn results_per_page(&self, default: usize, max: usize) -> usize {
+        self.results_per_page
+            .map_or(default, |results_per_page| results_per_page.clamp(1, max))
+    }
+
+    /// The url param `name` for the page size, or nothing if the provider's `default` page
+    /// size is used, so the urls are the same as without a page size.
+    pub(crate) fn results_per_page_param(&self, name: &str, default: usize, max: usize) -> String {
+        match self.results_per_page(default, max) {
+            results_per_page if results_per_page == default => String::new(),
+            results_per_page => format!("&{name}={results_per_page}"),
+        }
+    }
+
+    /// The current selector overrides for the engine with the id `engine_id`.
+    pub(crate) fn selectors(&self, engine_id: &str) -> Arc<EngineSelectors> {
+        self.selector_overrides
+            .as_ref()
+            .map(|this| this.for_engine(engine_id))
+            .unwrap_or_default()
+    }
+
+    /// Sets the `Cookie` header for a request to `url` from the cookie jar, or to the engine's
+    /// `defaults` without one.
+    pub(crate) fn apply_cookies(&self, headers: &mut HeaderMap, url: &str, defaults: &str) {
+        if let Some(cookie_jar) = &self.cookie_jar {
+            cookie_jar.apply(headers, url, defaults);
+        } else if let Ok(defaults) = HeaderValue::from_str(defaults)
+            && !defaults.is_empty()
+        {
+            headers.insert(COOKIE, defaults);
+        }
+    }
+
+    /// Keeps the cookies `response` sets in the cookie jar, if there is one.
+    pub(crate) fn store_cookies(&self, response: &Response) {
+        if let Some(cookie_jar) = &self.cookie_jar {
+            cookie_jar.store_response_headers(response.url().as_str(), response.headers());
+        }
+    }
+}
+
+/// What an engine does with the sponsored results (ads) on a results page.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AdHandling {
+    /// Ads are removed and only counted in [`ParsedPage::dropped_ads`](crate::results::ParsedPage::dropped_ads).
+    #[default]
+    Drop,
+    /// Ads are kept and marked as [`sponsored`](crate::results::RichSearchResult::sponsored).
+    Label,
+}
@@ -0,0 +1,534 @@
+//! Keeping the cookies providers set between requests, so sessions look like a returning browser.
+
+use std::{
+    collections::BTreeMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use http::{
+    HeaderMap, HeaderValue, Uri,
+    header::{COOKIE, SET_COOKIE},
+};
+
+/// The cookies for a single engine's provider. Cloning the jar shares its cookies.
+///
+/// Cookies are kept per domain and path like a browser does (see RFC 6265), so each one is only
+/// sent to the pages it was set for, even when the jar is shared between providers.
+#[derive(Debug, Clone, Default)]
+pub struct CookieJar {
+    cookies: Arc<Mutex<BTreeMap<CookieKey, Cookie>>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct CookieKey {
+    domain: String,
+    path: String,
+    name: String,
+}
+
+#[derive(Debug, Clone)]
+struct Cookie {
+    value: String,
+    /// Whether the cookie is only sent to the host which set it, rather than its subdomains too.
+    host_only: bool,
+    secure: bool,
+    expires: Option<DateTime<Utc>>,
+}
+
+/// The parts of a request's url which decide which cookies are sent with it.
+struct Target {
+    secure: bool,
+    host: String,
+    path: String,
+}
+
+impl CookieJar {
+    /// Creates an empty cookie jar.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The value of the cookie `name` which would be sent with a request to `url`.
+    pub fn get(&self, url: &str, name: &str) -> Option<String> {
+        let target = Target::parse(url)?;
+
+        self.lock()
+            .iter()
+            .find(|(key, cookie)| key.name == name && cookie.applies_to(key, &target, Utc::now()))
+            .map(|(_, cookie)| cookie.value.clone())
+    }
+
+    /// Sets the cookie `name` for the host of `url`, replacing its current value.
+    pub fn set(&self, url: &str, name: impl Into<String>, value: impl Into<String>) {
+        let Some(target) = Target::parse(url) else {
+            return;
+        };
+
+        self.lock().insert(
+            CookieKey {
+                domain: target.host,
+                path: String::from("/"),
+                name: name.into(),
+            },
+            Cookie {
+                value: value.into(),
+                host_only: true,
+                secure: false,
+                expires: None,
+            },
+        );
+    }
+
+    /// Removes the cookies named `name` which would be sent with a request to `url`.
+    pub fn remove(&self, url: &str, name: &str) {
+        let Some(target) = Target::parse(url) else {
+            return;
+        };
+
+        let now = Utc::now();
+        self.lock()
+            .retain(|key, cookie| key.name != name || !cookie.applies_to(key, &target, now));
+    }
+
+    /// Removes every cookie, e.g. to start a new session after being blocked.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    /// Adds the cookies in a `Cookie` header value (e.g. `a=1; b=2`) for the host of `url`,
+    /// unless a cookie with the same name would already be sent to it.
+    pub fn seed(&self, url: &str, cookies: &str) {
+        for (name, value) in cookies.split(';').filter_map(parse_pair) {
+            if self.get(url, name).is_none() {
+                self.set(url, name, value);
+            }
+        }
+    }
+
+    /// Stores the cookies from the `Set-Cookie` headers of a response from `url`, removing any
+    /// which the provider expired.
+    pub fn store_response_headers(&self, url: &str, headers: &HeaderMap) {
+        for set_cookie in headers.get_all(SET_COOKIE) {
+            if let Ok(set_cookie) = set_cookie.to_str() {
+                self.store_set_cookie(url, set_cookie);
+            }
+        }
+    }
+
+    /// Stores the cookie from a single `Set-Cookie` header value of a response from `url`.
+    /// Cookies for a domain which `url` isn't on are ignored, like browsers do.
+    pub fn store_set_cookie(&self, url: &str, set_cookie: &str) {
+        let Some(target) = Target::parse(url) else {
+            return;
+        };
+
+        let mut parts = set_cookie.split(';');
+        let Some((name, value)) = parts.next().and_then(parse_pair) else {
+            return;
+        };
+
+        let now = Utc::now();
+        let mut key = CookieKey {
+            domain: target.host.clone(),
+            path: default_path(&target.path).to_string(),
+            name: name.to_string(),
+        };
+        let mut cookie = Cookie {
+            value: value.to_string(),
+            host_only: true,
+            secure: false,
+            expires: None,
+        };
+        let mut max_age = None;
+
+        for (attribute, value) in parts.map(parse_attribute) {
+            if attribute.eq_ignore_ascii_case("max-age") {
+                // `Max-Age` takes precedence over `Expires`.
+                max_age = value.parse::<i64>().ok().map(|max_age| {
+                    Duration::try_seconds(max_age)
+                        .and_then(|this| now.checked_add_signed(this))
+                        .unwrap_or(if max_age > 0 {
+                            DateTime::<Utc>::MAX_UTC
+                        } else {
+                            DateTime::<Utc>::MIN_UTC
+                        })
+                });
+            } else if attribute.eq_ignore_ascii_case("expires") {
+                cookie.expires = parse_cookie_date(value).or(cookie.expires);
+            } else if attribute.eq_ignore_ascii_case("domain") {
+                let domain = value.trim_start_matches('.').to_ascii_lowercase();
+                if domain.is_empty() {
+                    continue;
+                }
+                if !domain_matches(&target.host, &domain) {
+                    return;
+                }
+
+                key.domain = domain;
+                cookie.host_only = false;
+            } else if attribute.eq_ignore_ascii_case("path") && value.starts_with('/') {
+                key.path = value.to_string();
+            } else if attribute.eq_ignore_ascii_case("secure") {
+                cookie.secure = true;
+            }
+        }
+        cookie.expires = max_age.or(cookie.expires);
+
+        let mut jar = self.lock();
+        if cookie.expires.is_some_and(|expires| expires <= now) {
+            jar.remove(&key);
+        } else {
+            jar.insert(key, cookie);
+        }
+    }
+
+    /// The cookies to send with a request to `url` as a `Cookie` header value, or `None` if
+    /// there aren't any. Cookies with longer paths are listed first.
+    pub fn header_value(&self, url: &str) -> Option<HeaderValue> {
+        let target = Target::parse(url)?;
+        let now = Utc::now();
+        let jar = self.lock();
+
+        let mut cookies = jar
+            .iter()
+            .filter(|(key, cookie)| cookie.applies_to(key, &target, now))
+            .collect::<Vec<_>>();
+        if cookies.is_empty() {
+            return None;
+        }
+        cookies.sort_by_key(|(key, _)| std::cmp::Reverse(key.path.len()));
+
+        let cookies = cookies
+            .into_iter()
+            .map(|(key, cookie)| format!("{}={}", key.name, cookie.value))
+            .collect::<Vec<_>>()
+            .join("; ");
+
+        HeaderValue::from_str(&cookies).ok()
+    }
+
+    /// Adds the default cookies which aren't set yet, then sets the `Cookie` header for a request
+    /// to `url` from the jar.
+    pub(crate) fn apply(&self, headers: &mut HeaderMap, url: &str, defaults: &str) {
+        self.seed(url, defaults);
+
+        if let Some(cookies) = self.header_value(url) {
+            headers.insert(COOKIE, cookies);
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<CookieKey, Cookie>> {
+        self.cookies.lock().unwrap_or_else(|this| this.into_inner())
+    }
+}
+
+impl Cookie {
+    /// Whether the cookie is sent with a request to `target` at `now`.
+    fn applies_to(&self, key: &CookieKey, target: &Target, now: DateTime<Utc>) -> bool {
+        let domain_matches = if self.host_only {
+            target.host == key.domain
+        } else {
+            domain_matches(&target.host, &key.domain)
+        };
+
+        domain_matches
+            && path_matches(&target.path, &key.path)
+            && (!self.secure || target.secure)
+            && self.expires.is_none_or(|expires| expires > now)
+    }
+}
+
+impl Target {
+    fn parse(url: &str) -> Option<Self> {
+        let uri = url.parse::<Uri>().ok()?;
+
+        Some(Self {
+            secure: uri.scheme_str() == Some("https"),
+            host: uri.host()?.to_ascii_lowercase(),
+            path: match uri.path() {
+                "" => String::from("/"),
+                path => path.to_string(),
+            },
+        })
+    }
+}
+
+/// Whether `host` is `domain` or one of its subdomains.
+fn domain_matches(host: &str, domain: &str) -> bool {
+    host == domain
+        || (host
+            .strip_suffix(domain)
+            .is_some_and(|subdomain| subdomain.ends_with('.'))
+            && host.parse::<IpAddr>().is_err())
+}
+
+/// Whether a cookie with the path `cookie_path` is sent with a request for `request_path`.
+fn path_matches(request_path: &str, cookie_path: &str) -> bool {
+    request_path
+        .strip_prefix(cookie_path)
+        .is_some_and(|rest| rest.is_empty() || cookie_path.ends_with('/') || rest.starts_with('/'))
+}
+
+/// The path of a cookie set without a `Path`: the directory of the page which set it.
+fn default_path(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(0) | None => "/",
+        Some(idx) => &path[..idx],
+    }
+}
+
+/// Parses an `Expires` date the way browsers do (RFC 6265 section 5.1.1), which accepts
+/// `Thu, 01 Jan 1970 00:00:00 GMT` along with older forms like `Thu, 01-Jan-70 00:00:00 GMT`
+/// and `Thu Jan  1 00:00:00 1970`.
+fn parse_cookie_date(value: &str) -> Option<DateTime<Utc>> {
+    let (mut time, mut day, mut month, mut year) = (None, None, None, None);
+
+    for token in value
+        .split(|this: char| !this.is_ascii_alphanumeric() && this != ':')
+        .filter(|this| !this.is_empty())
+    {
+        if time.is_none()
+            && let Some(parsed) = parse_time(token)
+        {
+            time = Some(parsed);
+        } else if day.is_none()
+            && let Some(parsed) = leading_number(token, 1..=2)
+        {
+            day = Some(parsed);
+        } else if month.is_none()
+            && let Some(parsed) = parse_month(token)
+        {
+            month = Some(parsed);
+        } else if year.is_none()
+            && let Some(parsed) = leading_number(token, 2..=4)
+        {
+            year = Some(parsed);
+        }
+    }
+
+    let year = match year? {
+        year @ 70..=99 => year + 1900,
+        year @ 0..=69 => year + 2000,
+        year => year,
+    };
+    let (hour, minute, second) = time?;
+
+    NaiveDate::from_ymd_opt(year as i32, month?, day?)?
+        .and_hms_opt(hour, minute, second)
+        .map(|this| this.and_utc())
+}
+
+/// Parses a `hh:mm:ss` time, where each part has one or two digits.
+fn parse_time(token: &str) -> Option<(u32, u32, u32)> {
+    let mut parts = token.splitn(3, ':');
+    let mut part = || leading_number(parts.next()?, 1..=2);
+
+    Some((part()?, part()?, part()?))
+}
+
+/// Parses the digits at the start of `token`, if there are `digits` of them.
+fn leading_number(token: &str, digits: std::ops::RangeInclusive<usize>) -> Option<u32> {
+    let len = token
+        .find(|this: char| !this.is_ascii_digit())
+        .unwrap_or(token.len());
+
+    digits
+        .contains(&len)
+        .then(|| token[..len].parse().ok())
+        .flatten()
+}
+
+fn parse_month(token: &str) -> Option<u32> {
+    let prefix = token.get(..3)?.to_ascii_lowercase();
+
+    MONTHS
+        .iter()
+        .position(|this| *this == prefix)
+        .map(|idx| idx as u32 + 1)
+}
+
+fn parse_pair(pair: &str) -> Option<(&str, &str)> {
+    let (name, value) = pair.trim().split_once('=')?;
+    let name = name.trim();
+
+    (!name.is_empty()).then(|| (name, value.trim()))
+}
+
+/// Splits an attribute into its name and value. Flags like `Secure` have an empty value.
+fn parse_attribute(attribute: &str) -> (&str, &str) {
+    let (name, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+
+    (name.trim(), value.trim())
+}
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cookies(jar: &CookieJar, url: &str) -> Option<String> {
+        jar.header_value(url)
+            .map(|this| this.to_str().unwrap().to_string())
+    }
+
+    #[test]
+    fn parses_each_cookie_date_format() {
+        let epoch = DateTime::UNIX_EPOCH;
+        let date = NaiveDate::from_ymd_opt(2031, 4, 9)
+            .unwrap()
+            .and_hms_opt(8, 5, 3)
+            .unwrap()
+            .and_utc();
+
+        assert_eq!(
+            parse_cookie_date("Thu, 01 Jan 1970 00:00:00 GMT"),
+            Some(epoch)
+        );
+        assert_eq!(
+            parse_cookie_date("Thu, 01-Jan-1970 00:00:00 GMT"),
+            Some(epoch)
+        );
+        assert_eq!(
+            parse_cookie_date("Wednesday, 09-Apr-31 08:05:03 GMT"),
+            Some(date)
+        );
+        assert_eq!(parse_cookie_date("Wed Apr  9 8:5:3 2031"), Some(date));
+        assert_eq!(parse_cookie_date("Thu, 01-Jan-1970"), None);
+        assert_eq!(parse_cookie_date("Thu, 31-Feb-2031 00:00:00 GMT"), None);
+    }
+
+    #[test]
+    fn removes_cookies_expired_with_a_dashed_date() {
+        let jar = CookieJar::new();
+        jar.store_set_cookie("https://www.bing.com/search", "MUID=1; Path=/");
+
+        jar.store_set_cookie(
+            "https://www.bing.com/search",
+            "MUID=; Path=/; Expires=Thu, 01-Jan-1970 00:00:00 GMT",
+        );
+
+        assert_eq!(jar.get("https://www.bing.com/", "MUID"), None);
+    }
+
+    #[test]
+    fn prefers_max_age_over_expires() {
+        let jar = CookieJar::new();
+
+        jar.store_set_cookie(
+            "https://www.bing.com/",
+            "a=1; Max-Age=3600; Expires=Thu, 01 Jan 1970 00:00:00 GMT",
+        );
+        jar.store_set_cookie(
+            "https://www.bing.com/",
+            "b=2; Max-Age=0; Expires=Fri, 01 Jan 2100 00:00:00 GMT",
+        );
+
+        assert_eq!(
+            cookies(&jar, "https://www.bing.com/").as_deref(),
+            Some("a=1")
+        );
+    }
+
+    #[test]
+    fn keeps_cookies_with_the_same_name_for_each_domain() {
+        let jar = CookieJar::new();
+
+        jar.store_set_cookie("https://www.google.com/search", "NID=google");
+        jar.store_set_cookie("https://www.bing.com/search", "NID=bing");
+
+        assert_eq!(
+            cookies(&jar, "https://www.google.com/search").as_deref(),
+            Some("NID=google")
+        );
+        assert_eq!(
+            cookies(&jar, "https://www.bing.com/search").as_deref(),
+            Some("NID=bing")
+        );
+        assert_eq!(cookies(&jar, "https://search.brave.com/search"), None);
+    }
+
+    #[test]
+    fn sends_domain_cookies_to_subdomains_only_when_asked_to() {
+        let jar = CookieJar::new();
+
+        jar.store_set_cookie("https://www.google.com/", "host=1");
+        jar.store_set_cookie("https://www.google.com/", "domain=2; Domain=.google.com");
+        jar.store_set_cookie("https://www.google.com/", "other=3; Domain=bing.com");
+
+        assert_eq!(
+            cookies(&jar, "https://www.google.com/").as_deref(),
+            Some("domain=2; host=1")
+        );
+        assert_eq!(
+            cookies(&jar, "https://news.google.com/").as_deref(),
+            Some("domain=2")
+        );
+        assert_eq!(cookies(&jar, "https://www.bing.com/"), None);
+    }
+
+    #[test]
+    fn only_sends_cookies_to_their_path() {
+        let jar = CookieJar::new();
+
+        jar.store_set_cookie("https://search.yahoo.com/", "root=1; Path=/");
+        jar.store_set_cookie("https://search.yahoo.com/", "search=2; Path=/search");
+        // Without a `Path`, the cookie is for the directory of the page which set it.
+        jar.store_set_cookie("https://search.yahoo.com/web/search", "web=3");
+
+        assert_eq!(
+            cookies(&jar, "https://search.yahoo.com/search/images").as_deref(),
+            Some("search=2; root=1")
+        );
+        assert_eq!(
+            cookies(&jar, "https://search.yahoo.com/searches").as_deref(),
+            Some("root=1")
+        );
+        assert_eq!(
+            cookies(&jar, "https://search.yahoo.com/web/search").as_deref(),
+            Some("web=3; root=1")
+        );
+    }
+
+    #[test]
+    fn only_sends_secure_cookies_over_https() {
+        let jar = CookieJar::new();
+
+        jar.store_set_cookie("https://www.mojeek.com/", "session=1; Secure; HttpOnly");
+
+        assert_eq!(
+            cookies(&jar, "https://www.mojeek.com/search").as_deref(),
+            Some("session=1")
+        );
+        assert_eq!(cookies(&jar, "http://www.mojeek.com/search"), None);
+    }
+
+    #[test]
+    fn seeds_cookies_which_are_not_set_yet() {
+        let jar = CookieJar::new();
+        jar.set("https://www.bing.com", "SRCHD", "AF=SET");
+
+        jar.seed("https://www.bing.com", "SRCHD=AF=NOFORM; _EDGE_V=1");
+
+        assert_eq!(
+            cookies(&jar, "https://www.bing.com/search").as_deref(),
+            Some("SRCHD=AF=SET; _EDGE_V=1")
+        );
+    }
+
+    #[test]
+    fn removes_the_cookies_sent_to_a_url() {
+        let jar = CookieJar::new();
+        jar.set("https://www.bing.com", "a", "1");
+        jar.set("https://www.google.com", "a", "2");
+
+        jar.remove("https://www.bing.com/search", "a");
+
+        assert_eq!(jar.get("https://www.bing.com", "a"), None);
+        assert_eq!(jar.get("https://www.google.com", "a").as_deref(), Some("2"));
+    }
+}
@@ -0,0 +1,160 @@
+//! Helpers for parsing the result counts providers print above their results.
+
+/// Parses the first number in some text, ignoring thousands separators
+/// (e.g. `About 1,230,000 results` -> `1230000`) and expanding abbreviations (e.g. `1.2k` -> `1200`).
+pub(crate) fn parse_count(text: &str) -> Option<u64> {
+    parse_counts(text).next()
+}
+
+/// Parses the total number of results from a result count, which is the largest number in it
+/// (e.g. `11-20 of 1,234 results` -> `1234`), as later pages also show the range being viewed.
+pub(crate) fn parse_total(text: &str) -> Option<u64> {
+    parse_counts(text).max()
+}
+
+/// Parses each number in some text.
+fn parse_counts(text: &str) -> impl Iterator<Item = u64> + '_ {
+    let mut rest = text;
+
+    std::iter::from_fn(move || {
+        loop {
+            let start = rest.find(|this: char| this.is_ascii_digit())?;
+            let (number, after_number) = split_number(&rest[start..]);
+
+            let (multiplier, after_suffix) =
+                split_suffix(after_number).unwrap_or((1, after_number));
+            rest = after_suffix;
+
+            if let Some(count) = to_count(number, multiplier) {
+                return Some(count);
+            }
+        }
+    })
+}
+
+/// Splits the number at the start of `text` off the rest of it.
+/// Separators are only part of the number if they're followed by another digit.
+fn split_number(text: &str) -> (&str, &str) {
+    let mut end = 0;
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((idx, this)) = chars.next() {
+        let is_separator = THOUSANDS_SEPARATORS.contains(&this)
+            && chars.peek().is_some_and(|(_, next)| next.is_ascii_digit());
+
+        if !this.is_ascii_digit() && !is_separator {
+            break;
+        }
+
+        end = idx + this.len_utf8();
+    }
+
+    text.split_at(end)
+}
+
+/// Splits an abbreviation suffix (e.g. the `k` in `1.2k`) off the start of `text`,
+/// returning what it multiplies the number by.
+fn split_suffix(text: &str) -> Option<(u64, &str)> {
+    let mut chars = text.chars();
+
+    let multiplier = match chars.next()? {
+        'k' | 'K' => 1_000,
+        'm' | 'M' => 1_000_000,
+        'b' | 'B' => 1_000_000_000,
+        _ => return None,
+    };
+
+    // The letter is the start of a word rather than a suffix (e.g. `3 mins`, `12Mar`).
+    if chars.as_str().starts_with(char::is_alphabetic) {
+        return None;
+    }
+
+    Some((multiplier, chars.as_str()))
+}
+
+fn to_count(number: &str, multiplier: u64) -> Option<u64> {
+    let digits = |text: &str| {
+        text.chars()
+            .filter(|this| this.is_ascii_digit())
+            .collect::<String>()
+    };
+
+    if multiplier == 1 {
+        return digits(number).parse().ok();
+    }
+
+    // Abbreviated numbers may have a decimal part (e.g. `1.2k` or `1,5M`), which is never
+    // three digits long like a group of thousands is.
+    let (whole, fraction) = match number.rsplit_once(['.', ',']) {
+        Some((whole, fraction)) if fraction.len() < 3 => (whole, fraction),
+        _ => (number, ""),
+    };
+
+    let whole = digits(whole).parse::<u64>().ok()?.checked_mul(multiplier)?;
+    let fraction = match fraction {
+        "" => 0,
+        fraction => fraction.parse::<u64>().ok()? * multiplier / 10_u64.pow(fraction.len() as u32),
+    };
+
+    whole.checked_add(fraction)
+}
+
+const THOUSANDS_SEPARATORS: [char; 5] = [',', '.', ' ', '\u{a0}', '\u{202f}'];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ignores_thousands_separators() {
+        assert_eq!(parse_count("About 1,230,000 results"), Some(1_230_000));
+        assert_eq!(parse_count("1.234.567 Ergebnisse"), Some(1_234_567));
+        assert_eq!(parse_count("1 234 résultats"), Some(1_234));
+        assert_eq!(parse_count("1\u{a0}234\u{202f}567"), Some(1_234_567));
+    }
+
+    #[test]
+    fn expands_abbreviations() {
+        assert_eq!(parse_count("1.2k"), Some(1_200));
+        assert_eq!(parse_count("1.2k replies"), Some(1_200));
+        assert_eq!(parse_count("12.5K votes"), Some(12_500));
+        assert_eq!(parse_count("(1,5k)"), Some(1_500));
+        assert_eq!(parse_count("3M reviews"), Some(3_000_000));
+        assert_eq!(parse_count("2.25M"), Some(2_250_000));
+        assert_eq!(parse_count("1B"), Some(1_000_000_000));
+        assert_eq!(parse_count("1,234k"), Some(1_234_000));
+    }
+
+    #[test]
+    fn does_not_treat_words_as_abbreviations() {
+        assert_eq!(parse_count("12 replies"), Some(12));
+        assert_eq!(parse_count("3 mins"), Some(3));
+        assert_eq!(parse_count("12Mar"), Some(12));
+        assert_eq!(parse_count("5 Mar 2024"), Some(5));
+    }
+
+    #[test]
+    fn parses_the_first_number() {
+        assert_eq!(parse_count("42 votes, 7 replies"), Some(42));
+        assert_eq!(parse_count("1, 2, 3"), Some(1));
+    }
+
+    #[test]
+    fn parses_totals_on_first_and_later_pages() {
+        assert_eq!(parse_total("About 1,234 results"), Some(1_234));
+        assert_eq!(parse_total("11-20 of 1,234 results"), Some(1_234));
+        assert_eq!(
+            parse_total("1-10 of about 5,670,000 results"),
+            Some(5_670_000)
+        );
+        assert_eq!(parse_total("Page 3 of 2.1M results"), Some(2_100_000));
+        assert_eq!(parse_total("No results"), None);
+    }
+
+    #[test]
+    fn returns_none_without_a_number() {
+        assert_eq!(parse_count(""), None);
+        assert_eq!(parse_count("No results"), None);
+        assert_eq!(parse_count("k"), None);
+    }
+}
@@ -0,0 +1,207 @@
+//! Helpers for parsing the dates providers print next to their results.
+
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
+
+/// Parses either an absolute (`Mar 5, 2024`) or relative (`3 days ago`) date.
+/// Relative dates are resolved against `now`.
+pub(crate) fn parse_date(text: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let text = text
+        .trim()
+        .trim_end_matches(['-', '—', '–', '·', '\u{a0}', ' ']);
+
+    parse_relative_date(text, now).or_else(|| parse_absolute_date(text))
+}
+
+/// Splits a date prefix (e.g. `3 days ago — `) off the start of a summary,
+/// returning the parsed date and the rest of the summary.
+pub(crate) fn split_date_prefix(
+    summary: &str,
+    now: DateTime<Utc>,
+) -> Option<(DateTime<Utc>, &str)> {
+    DATE_SEPARATORS.iter().find_map(|separator| {
+        let (prefix, rest) = summary.split_once(separator)?;
+
+        // Dates are short, so anything longer is part of the summary itself.
+        if prefix.chars().count() > MAX_DATE_PREFIX_LEN {
+            return None;
+        }
+
+        parse_date(prefix, now).map(|date| (date, rest.trim_start()))
+    })
+}
+
+fn parse_relative_date(text: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let text = text.to_lowercase();
+
+    match text.as_str() {
+        "just now" | "now" | "today" => return Some(now),
+        "yesterday" => return Some(now - Duration::days(1)),
+        _ => {}
+    }
+
+    let text = text.strip_suffix("ago")?.trim_end();
+
+    // Splits `3 days` or `3d` into the amount and the unit.
+    let unit_idx = text.find(|this: char| !this.is_ascii_digit())?;
+    let (amount, unit) = text.split_at(unit_idx);
+    let amount: i32 = amount.parse().ok()?;
+
+    let unit_duration = match unit.trim().trim_end_matches('s') {
+        "sec" | "second" => Duration::try_seconds(1),
+        "m" | "min" | "minute" => Duration::try_minutes(1),
+        "h" | "hr" | "hour" => Duration::try_hours(1),
+        "d" | "day" => Duration::try_days(1),
+        "w" | "wk" | "week" => Duration::try_weeks(1),
+        "mo" | "month" => Duration::try_days(30),
+        "y" | "yr" | "year" => Duration::try_days(365),
+        _ => None,
+    }?;
+
+    // Huge amounts (e.g. `300000 years ago`) are out of range, so they aren't dates.
+    now.checked_sub_signed(unit_duration.checked_mul(amount)?)
+}
+
+fn parse_absolute_date(text: &str) -> Option<DateTime<Utc>> {
+    // Removes ordinal suffixes (e.g. `5th Mar 2024`) and stray commas (e.g. `5 Mar, 2024`).
+    let text = text.replace(',', "");
+    let text = text
+        .split_whitespace()
+        .map(|this| {
+            let is_ordinal = ["st", "nd", "rd", "th"].iter().any(|suffix| {
+                this.strip_suffix(suffix).is_some_and(|this| {
+                    !this.is_empty() && this.chars().all(|c| c.is_ascii_digit())
+                })
+            });
+
+            if is_ordinal {
+                &this[..this.len() - 2]
+            } else {
+                this
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    ABSOLUTE_DATE_FORMATS.iter().find_map(|format| {
+        let date = NaiveDate::parse_from_str(&text, format).ok()?;
+        Some(Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0)?))
+    })
+}
+
+const MAX_DATE_PREFIX_LEN: usize = 32;
+
+const DATE_SEPARATORS: [&str; 5] = [" — ", " – ", " - ", " · ", "\u{a0}· "];
+
+const ABSOLUTE_DATE_FORMATS: [&str; 7] = [
+    "%b %d %Y", "%B %d %Y", "%d %b %Y", "%d %B %Y", "%Y-%m-%d", "%d.%m.%Y", "%d/%m/%Y",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, 10, 12, 0, 0).unwrap()
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, 0, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn parses_each_absolute_format() {
+        let cases = [
+            ("Mar 5, 2024", date(2024, 3, 5)),
+            ("March 5, 2024", date(2024, 3, 5)),
+            ("5 Mar 2024", date(2024, 3, 5)),
+            ("5 March 2024", date(2024, 3, 5)),
+            ("2024-03-05", date(2024, 3, 5)),
+            ("05.03.2024", date(2024, 3, 5)),
+            ("05/03/2024", date(2024, 3, 5)),
+        ];
+
+        for (text, expected) in cases {
+            assert_eq!(parse_date(text, now()), Some(expected), "{text}");
+        }
+    }
+
+    #[test]
+    fn parses_ordinals_and_trailing_separators() {
+        assert_eq!(parse_date("5th Mar, 2024", now()), Some(date(2024, 3, 5)));
+        assert_eq!(parse_date("Mar 21st 2024 —", now()), Some(date(2024, 3, 21)));
+        assert_eq!(parse_date("  2024-03-05 · ", now()), Some(date(2024, 3, 5)));
+    }
+
+    #[test]
+    fn parses_relative_dates() {
+        let cases = [
+            ("just now", now()),
+            ("Today", now()),
+            ("yesterday", now() - Duration::days(1)),
+            ("30 seconds ago", now() - Duration::seconds(30)),
+            ("5 mins ago", now() - Duration::minutes(5)),
+            ("2 hours ago", now() - Duration::hours(2)),
+            ("3h ago", now() - Duration::hours(3)),
+            ("3 days ago", now() - Duration::days(3)),
+            ("1d ago", now() - Duration::days(1)),
+            ("2 weeks ago", now() - Duration::weeks(2)),
+            ("1 month ago", now() - Duration::days(30)),
+            ("4mo ago", now() - Duration::days(120)),
+            ("2 years ago", now() - Duration::days(730)),
+        ];
+
+        for (text, expected) in cases {
+            assert_eq!(parse_date(text, now()), Some(expected), "{text}");
+        }
+    }
+
+    #[test]
+    fn rejects_text_that_is_not_a_date() {
+        assert_eq!(parse_date("", now()), None);
+        assert_eq!(parse_date("Rust programming language", now()), None);
+        assert_eq!(parse_date("3 bananas ago", now()), None);
+        assert_eq!(parse_date("ago", now()), None);
+    }
+
+    #[test]
+    fn rejects_relative_dates_which_are_out_of_range() {
+        assert_eq!(parse_date("300000 years ago", now()), None);
+        assert_eq!(parse_date("3000000000 days ago", now()), None);
+        assert_eq!(parse_date("99999999999999999999 days ago", now()), None);
+    }
+
+    #[test]
+    fn splits_date_prefixes_off_summaries() {
+        let cases = [
+            ("3 days ago — Rust is fast.", now() - Duration::days(3)),
+            ("Mar 5, 2024 – Rust is fast.", date(2024, 3, 5)),
+            ("2024-03-05 - Rust is fast.", date(2024, 3, 5)),
+            ("yesterday · Rust is fast.", now() - Duration::days(1)),
+            ("5 Mar 2024\u{a0}· Rust is fast.", date(2024, 3, 5)),
+        ];
+
+        for (summary, expected) in cases {
+            assert_eq!(
+                split_date_prefix(summary, now()),
+                Some((expected, "Rust is fast.")),
+                "{summary}"
+            );
+        }
+    }
+
+    #[test]
+    fn keeps_summaries_without_a_date_prefix() {
+        assert_eq!(split_date_prefix("Rust is fast.", now()), None);
+        assert_eq!(
+            split_date_prefix("Rust — a language empowering everyone", now()),
+            None
+        );
+        assert_eq!(
+            split_date_prefix(
+                "A summary which is far too long to be a date - but has a separator",
+                now()
+            ),
+            None
+        );
+    }
+}
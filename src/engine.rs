@@ -0,0 +1,143 @@
+//! Extensions to quaero's [`Engine`] trait which every engine in this crate implements.
+
+use quaero_shared::models::{
+    engine::Engine,
+    search::{SafeSearch, SearchError, SearchOptions, SearchResult},
+};
+
+use crate::{
+    config::EngineConfig,
+    error::ParseError,
+    pacing::RequestBudget,
+    query::{self, QuerySyntax},
+    results::{ParsedPage, Provenance},
+};
+
+/// An [`Engine`] which can parse its results pages into [`RichSearchResult`](crate::results::RichSearchResult)s.
+pub trait RichEngine: Engine {
+    /// A short, stable identifier for the engine (e.g. `"google"`).
+    fn id(&self) -> &str;
+
+    /// The options the engine was created with.
+    fn config(&self) -> &EngineConfig;
+
+    /// The number of results the provider returns on each page.
+    fn results_per_page(&self) -> usize {
+        10
+    }
+
+    /// How many requests can be sent to the provider before it's likely to start serving
+    /// captchas. This is only enforced with a [`Pacer`](crate::pacing::Pacer), on the requests
+    /// this crate sends rather than the ones quaero sends.
+    fn request_budget(&self) -> RequestBudget {
+        DEFAULT_REQUEST_BUDGET
+    }
+
+    /// Whether the engine's provider has the given safe search level. Engines search levels
+    /// their provider doesn't have with the closest level it does have.
+    fn supports_safe_search(&self, _safe_search: &SafeSearch) -> bool {
+        true
+    }
+
+    /// How the engine's provider writes query operators like `site:`.
+    fn query_syntax(&self) -> QuerySyntax {
+        QuerySyntax::COMMON
+    }
+
+    /// Rewrites the operators in a raw query which the engine's provider writes differently
+    /// (see [`query_syntax`](Self::query_syntax)), keeping everything else as it was typed.
+    /// Operators the provider doesn't support are left out, as their results can only be
+    /// filtered with [`fetch_parsed_page`](crate::fetch::fetch_parsed_page).
+    fn render_query(&self, query: &str) -> String {
+        query::render_for_url(query, &self.query_syntax())
+    }
+
+    /// Finds the tokens on the engine's homepage which its search requests need, for a
+    /// [`WarmUp`](crate::warmup::WarmUp) session.
+    fn warm_up_tokens(&self, _homepage: &str) -> Vec<(String, String)> {
+        Vec::new()
+    }
+
+    /// Parses a results page into rich results.
+    fn parse_rich(&self, response_text: String) -> Result<ParsedPage, ParseError>;
+
+    /// Parses the results page which was requested with `options`, tagging every result with
+    /// its position on the page, the page number and the id of this engine.
+    fn parse_page(
+        &self,
+        response_text: String,
+        options: &SearchOptions,
+    ) -> Result<ParsedPage, ParseError> {
+        let mut page = self.parse_rich(response_text)?;
+        page.health.engine = self.id().to_string();
+
+        let mut rank = 0;
+        for result in page.results.iter_mut() {
+            if !result.sponsored {
+                rank += 1;
+            }
+
+            result.provenance = Provenance {
+                engine: self.id().to_string(),
+                page: options.page_num,
+                rank: if result.sponsored { 0 } else { rank },
+            };
+        }
+
+        Ok(page)
+    }
+}
+
+/// Parses a results page into quaero's results.
+pub(crate) fn parse_for_quaero<E: RichEngine + ?Sized>(
+    engine: &E,
+    response_text: String,
+) -> Result<Vec<(String, SearchResult)>, SearchError> {
+    Ok(engine.parse_rich(response_text)?.into_pairs())
+}
+
+const DEFAULT_REQUEST_BUDGET: RequestBudget = RequestBudget::per_minute(30);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        config::AdHandling,
+        testing::{TestEngine, options},
+    };
+
+    #[test]
+    fn tags_each_result_with_its_provenance() {
+        let engine = TestEngine {
+            config: EngineConfig {
+                ads: AdHandling::Label,
+                ..EngineConfig::default()
+            },
+        };
+        let options = SearchOptions {
+            page_num: 2,
+            ..options()
+        };
+
+        let page = engine
+            .parse_page(
+                "https://a.test A\nad https://ad.test Ad\nhttps://b.test B".to_string(),
+                &options,
+            )
+            .unwrap();
+
+        let provenance = |rank| Provenance {
+            engine: String::from("test"),
+            page: 2,
+            rank,
+        };
+        let provenances = page
+            .results
+            .iter()
+            .map(|this| this.provenance.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(provenances, [provenance(1), provenance(0), provenance(2)]);
+        assert_eq!(page.health.engine, "test");
+    }
+}
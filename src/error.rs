@@ -0,0 +1,268 @@
+//! Errors which can occur while fetching or parsing results from an engine.
+
+use std::{error::Error, fmt, time::Duration};
+
+use quaero_shared::models::search::SearchError;
+
+/// An error which occurred while fetching or parsing a results page.
+#[derive(Debug)]
+pub enum EngineError {
+    /// The engine rejected the request or couldn't parse the response.
+    Search(SearchError),
+    /// The provider is rate limiting requests (e.g. it responded with `429 Too Many Requests`).
+    RateLimited,
+    /// The engine's [`RequestBudget`](crate::pacing::RequestBudget) was used up, and its
+    /// [`Pacer`](crate::pacing::Pacer) rejects requests rather than queueing them.
+    BudgetExceeded {
+        /// How long until the budget allows another request.
+        retry_after: Duration,
+    },
+    /// The results page didn't have the layout the engine expected.
+    ParseFailed(ParseDiagnostics),
+    /// The request couldn't be sent, or its response couldn't be read.
+    Transport(Box<dyn Error + Send + Sync>),
+}
+
+/// An error which occurred while parsing a results page.
+#[derive(Debug)]
+pub enum ParseError {
+    /// The engine rejected the page, e.g. because the query had no results.
+    Search(SearchError),
+    /// An element the engine needs to find the results didn't match, which usually means the
+    /// provider changed its markup rather than the query having no results.
+    SelectorMissing(ParseDiagnostics),
+}
+
+/// Details of a results page which couldn't be parsed, for telling a changed layout apart from
+/// a query with no results.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseDiagnostics {
+    /// The id of the engine which parsed the page.
+    pub engine: String,
+    /// The selector which didn't match, including any override of it.
+    pub selector: String,
+    /// The length of the response in bytes.
+    pub response_len: usize,
+    /// The page's `<title>`, which often shows it's a consent or error page.
+    pub page_title: Option<String>,
+    /// The start of the page's text, with its tags removed.
+    pub excerpt: String,
+}
+
+impl ParseDiagnostics {
+    /// Describes a response in which `engine`'s `selector` didn't match.
+    pub(crate) fn new(engine: &str, selector: impl Into<String>, response_text: &str) -> Self {
+        Self {
+            engine: engine.to_string(),
+            selector: selector.into(),
+            response_len: response_text.len(),
+            page_title: page_title(response_text),
+            excerpt: excerpt(response_text),
+        }
+    }
+}
+
+impl EngineError {
+    /// Whether the provider blocked the request, with a captcha or by rate limiting it, or the
+    /// request was held back to avoid that.
+    pub fn is_blocked(&self) -> bool {
+        matches!(
+            self,
+            Self::Search(SearchError::Captcha) | Self::RateLimited | Self::BudgetExceeded { .. }
+        )
+    }
+}
+
+impl From<SearchError> for EngineError {
+    fn from(error: SearchError) -> Self {
+        Self::Search(error)
+    }
+}
+
+impl From<ParseError> for EngineError {
+    fn from(error: ParseError) -> Self {
+        match error {
+            ParseError::Search(error) => Self::Search(error),
+            ParseError::SelectorMissing(diagnostics) => Self::ParseFailed(diagnostics),
+        }
+    }
+}
+
+impl From<SearchError> for ParseError {
+    fn from(error: SearchError) -> Self {
+        Self::Search(error)
+    }
+}
+
+/// Quaero's [`SearchError`] has no room for diagnostics, so a missing selector is reported to it
+/// as the page having no results, and the diagnostics are logged as a warning instead.
+impl From<ParseError> for SearchError {
+    fn from(error: ParseError) -> Self {
+        match error {
+            ParseError::Search(error) => error,
+            ParseError::SelectorMissing(diagnostics) => {
+                log::warn!("{diagnostics}");
+                SearchError::NoResultsFound
+            }
+        }
+    }
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Search(error) => write!(f, "search error: {error:?}"),
+            Self::RateLimited => write!(f, "rate limited"),
+            Self::BudgetExceeded { retry_after } => {
+                write!(f, "request budget exceeded, retry after {retry_after:?}")
+            }
+            Self::ParseFailed(diagnostics) => write!(f, "parse error: {diagnostics}"),
+            Self::Transport(error) => write!(f, "transport error: {error}"),
+        }
+    }
+}
+
+impl Error for EngineError {}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Search(error) => write!(f, "search error: {error:?}"),
+            Self::SelectorMissing(diagnostics) => diagnostics.fmt(f),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+/// Writes e.g. `yahoo selector ".searchCenterMiddle" didn't match in a 5120 byte response titled "Yahoo": ...`.
+impl fmt::Display for ParseDiagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} selector {:?} didn't match in a {} byte response",
+            self.engine, self.selector, self.response_len
+        )?;
+
+        if let Some(page_title) = &self.page_title {
+            write!(f, " titled {page_title:?}")?;
+        }
+
+        write!(f, ": {:?}", self.excerpt)
+    }
+}
+
+fn page_title(html: &str) -> Option<String> {
+    let start = html.find("<title")?;
+    let title = &html[start..];
+    let title = &title[title.find('>')? + 1..];
+    let title = &title[..title.find("</title>")?];
+
+    Some(html_escape::decode_html_entities(title.trim()).into_owned())
+        .filter(|this| !this.is_empty())
+}
+
+// Skips to the `<body>` (if there is one) and drops the tags, so the excerpt is the page's text
+// rather than its `<head>`.
+fn excerpt(html: &str) -> String {
+    let body = html.find("<body").map_or(html, |start| &html[start..]);
+
+    let mut text = String::new();
+    let mut is_in_tag = false;
+    for char in body.chars() {
+        match char {
+            '<' => is_in_tag = true,
+            '>' if is_in_tag => {
+                is_in_tag = false;
+                text.push(' ');
+            }
+            _ if !is_in_tag => text.push(char),
+            _ => {}
+        }
+    }
+
+    let text = html_escape::decode_html_entities(&text);
+    let mut words = text.split_whitespace();
+    let mut excerpt: String = words
+        .next()
+        .map(|this| this.chars().take(MAX_EXCERPT_CHARS).collect())
+        .unwrap_or_default();
+    for word in words {
+        if excerpt.chars().count() + 1 + word.chars().count() > MAX_EXCERPT_CHARS {
+            break;
+        }
+        excerpt.push(' ');
+        excerpt.push_str(word);
+    }
+
+    excerpt
+}
+
+const MAX_EXCERPT_CHARS: usize = 200;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_page_title() {
+        assert_eq!(
+            page_title("<html><head><title> Before you continue to Google </title></head></html>"),
+            Some(String::from("Before you continue to Google"))
+        );
+        assert_eq!(
+            page_title(r#"<title lang="en">Rust &amp; Cargo &#8211; Yahoo</title>"#),
+            Some(String::from("Rust & Cargo – Yahoo"))
+        );
+        assert_eq!(page_title("<title></title>"), None);
+        assert_eq!(page_title("<html><body>No title</body></html>"), None);
+    }
+
+    #[test]
+    fn excerpts_the_body_text() {
+        let html = "<html><head><title>Title</title><style>p{}</style></head>\
+            <body><h1>Are you a robot?</h1><p>Prove it &amp; try again.</p></body></html>";
+
+        assert_eq!(excerpt(html), "Are you a robot? Prove it & try again.");
+    }
+
+    #[test]
+    fn excerpts_pages_without_a_body() {
+        assert_eq!(
+            excerpt("<div>Unusual traffic</div><div>from your network</div>"),
+            "Unusual traffic from your network"
+        );
+        assert_eq!(excerpt(""), "");
+    }
+
+    #[test]
+    fn truncates_excerpts_at_a_word() {
+        let html = format!("<body>{}</body>", "word ".repeat(100));
+        let excerpt = excerpt(&html);
+
+        assert!(excerpt.chars().count() <= MAX_EXCERPT_CHARS);
+        assert!(excerpt.chars().count() > MAX_EXCERPT_CHARS - "word ".len());
+        assert!(excerpt.ends_with("word"));
+
+        let long_word = "a".repeat(MAX_EXCERPT_CHARS * 2);
+        assert_eq!(
+            super::excerpt(&format!("<body>{long_word}</body>")),
+            long_word[..MAX_EXCERPT_CHARS]
+        );
+    }
+
+    #[test]
+    fn describes_the_selector_which_did_not_match() {
+        let diagnostics = ParseDiagnostics::new(
+            "yahoo",
+            ".searchCenterMiddle",
+            "<title>Yahoo</title><body>Sorry</body>",
+        );
+
+        assert_eq!(diagnostics.response_len, 38);
+        assert_eq!(
+            diagnostics.to_string(),
+            r#"yahoo selector ".searchCenterMiddle" didn't match in a 38 byte response titled "Yahoo": "Sorry""#
+        );
+    }
+}
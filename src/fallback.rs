@@ -0,0 +1,156 @@
+//! Falling back to other engines when an engine's provider blocks requests.
+
+use quaero_shared::models::search::SearchOptions;
+
+use crate::{
+    engine::RichEngine,
+    error::EngineError,
+    fetch::{Fetcher, fetch_page},
+    results::ParsedPage,
+};
+
+/// An ordered list of engines which are tried in turn until one isn't blocked,
+/// e.g. Google, then Brave, then Bing.
+pub struct FallbackChain {
+    engines: Vec<Box<dyn RichEngine + Send + Sync>>,
+}
+
+/// A results page fetched from a [`FallbackChain`].
+#[derive(Debug)]
+pub struct FallbackPage {
+    /// The results page.
+    pub page: ParsedPage,
+    /// The id of the engine which answered.
+    pub engine: String,
+    /// The ids of the engines which were tried first but were blocked, along with their errors.
+    pub blocked: Vec<(String, EngineError)>,
+}
+
+impl FallbackChain {
+    /// Creates a chain which tries `engine` first.
+    pub fn new(engine: impl RichEngine + Send + Sync + 'static) -> Self {
+        Self {
+            engines: vec![Box::new(engine)],
+        }
+    }
+
+    /// Adds an engine to try when every engine before it was blocked.
+    pub fn then(mut self, engine: impl RichEngine + Send + Sync + 'static) -> Self {
+        self.engines.push(Box::new(engine));
+        self
+    }
+
+    /// The ids of the engines in the chain, in the order they're tried.
+    pub fn engine_ids(&self) -> impl Iterator<Item = &str> {
+        self.engines.iter().map(|this| this.id())
+    }
+
+    /// Fetches the results page for `query` from the first engine which isn't blocked by its
+    /// provider (see [`EngineError::is_blocked`]). Any other error is returned straight away,
+    /// as the next engine is unlikely to do better. If every engine is blocked, the last
+    /// engine's error is returned.
+    pub async fn fetch_page<F>(
+        &self,
+        fetcher: &F,
+        query: &str,
+        options: &SearchOptions,
+    ) -> Result<FallbackPage, EngineError>
+    where
+        F: Fetcher + ?Sized,
+    {
+        let mut blocked = Vec::new();
+
+        for engine in &self.engines {
+            match fetch_page(engine.as_ref(), fetcher, query, options).await {
+                Ok(page) => {
+                    return Ok(FallbackPage {
+                        page,
+                        engine: engine.id().to_string(),
+                        blocked,
+                    });
+                }
+                Err(error) if error.is_blocked() => blocked.push((engine.id().to_string(), error)),
+                Err(error) => return Err(error),
+            }
+        }
+
+        let (_, error) = blocked
+            .pop()
+            .expect("a fallback chain has at least one engine");
+        Err(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use quaero_shared::models::search::SearchError;
+
+    use super::*;
+
+    use crate::{
+        combinators::Renamed,
+        testing::{FakeFetcher, TestEngine, options},
+    };
+
+    fn chain() -> FallbackChain {
+        FallbackChain::new(Renamed::new(TestEngine::default(), "first"))
+            .then(Renamed::new(TestEngine::default(), "second"))
+    }
+
+    #[tokio::test]
+    async fn moves_to_the_next_engine_when_blocked() {
+        let fetcher = FakeFetcher::default()
+            .with_response(Some("http://search.test/captcha"), &[], "")
+            .with_body("https://a.test A");
+
+        let page = chain()
+            .fetch_page(&fetcher, "rust", &options())
+            .await
+            .unwrap();
+
+        assert_eq!(page.engine, "second");
+        assert_eq!(page.page.results[0].url, "https://a.test");
+        assert!(matches!(
+            page.blocked.as_slice(),
+            [(engine, EngineError::Search(SearchError::Captcha))] if engine == "first"
+        ));
+    }
+
+    #[tokio::test]
+    async fn returns_other_errors_without_trying_the_next_engine() {
+        let fetcher = FakeFetcher::default().with_body("");
+
+        let error = chain()
+            .fetch_page(&fetcher, "rust", &options())
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            error,
+            EngineError::Search(SearchError::NoResultsFound)
+        ));
+        assert_eq!(fetcher.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn returns_the_last_error_when_every_engine_is_blocked() {
+        let fetcher = FakeFetcher::default()
+            .with_response(Some("http://search.test/captcha"), &[], "")
+            .with_error(EngineError::RateLimited);
+
+        let error = chain()
+            .fetch_page(&fetcher, "rust", &options())
+            .await
+            .unwrap_err();
+
+        assert!(matches!(error, EngineError::RateLimited));
+    }
+
+    #[test]
+    fn lists_its_engines_in_order() {
+        assert_eq!(
+            chain().engine_ids().collect::<Vec<_>>(),
+            ["first", "second"]
+        );
+    }
+}
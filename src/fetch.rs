@@ -0,0 +1,207 @@
+//! Fetching results pages from engines, for when you want to drive engines without quaero.
+
+use std::time::Duration;
+
+use anyhttp::Response;
+use http::HeaderMap;
+use quaero_shared::models::search::SearchOptions;
+
+use crate::{
+    egress::Egress,
+    engine::RichEngine,
+    error::EngineError,
+    pacing,
+    query::ParsedQuery,
+    results::ParsedPage,
+    warmup::{end_blocked_session, warm_up},
+};
+
+/// Sends the requests for results pages. Implement this for whichever http client you use.
+#[async_trait::async_trait]
+pub trait Fetcher: Send + Sync {
+    /// Sends a `GET` request through the request's [`Egress`] and reads the body of its response.
+    /// Clients which accept their own connections can open them with [`Egress::connect`].
+    /// Responses which show the provider is rate limiting requests should be returned as
+    /// [`EngineError::RateLimited`].
+    async fn fetch(&self, request: FetchRequest) -> Result<FetchedResponse, EngineError>;
+
+    /// Waits for `duration` without blocking the thread. Used by [`Pacer`](crate::pacing::Pacer)s
+    /// which queue requests. Clients should wait with their runtime's timer (e.g.
+    /// `tokio::time::sleep`). By default the wait is timed by a single timer thread shared by
+    /// every fetcher, so it works with any runtime.
+    async fn sleep(&self, duration: Duration) {
+        pacing::sleep(duration).await
+    }
+}
+
+/// A request for a results page.
+#[derive(Debug, Clone)]
+pub struct FetchRequest {
+    /// The url to request.
+    pub url: String,
+    /// The headers to send with the request.
+    pub headers: HeaderMap,
+    /// How the request has to be routed, e.g. through the engine's proxy.
+    pub egress: Egress,
+}
+
+/// A response to a [`FetchRequest`] along with its body.
+pub struct FetchedResponse {
+    /// The response, used to validate that the engine wasn't blocked and to keep the cookies
+    /// it sets.
+    pub response: Response,
+    /// The body of the response.
+    pub body: String,
+}
+
+/// Fetches and parses the results page for `query` from `engine`.
+pub async fn fetch_page<E, F>(
+    engine: &E,
+    fetcher: &F,
+    query: &str,
+    options: &SearchOptions,
+) -> Result<ParsedPage, EngineError>
+where
+    E: RichEngine + ?Sized,
+    F: Fetcher + ?Sized,
+{
+    let body = fetch_body(engine, fetcher, query, options).await?;
+    Ok(engine.parse_page(body, options)?)
+}
+
+/// Fetches the body of the results page for `query` from `engine`, after checking that the
+/// engine wasn't blocked. The engine's [`WarmUp`](crate::warmup::WarmUp) session is refreshed
+/// first if it needs to be.
+pub(crate) async fn fetch_body<E, F>(
+    engine: &E,
+    fetcher: &F,
+    query: &str,
+    options: &SearchOptions,
+) -> Result<String, EngineError>
+where
+    E: RichEngine + ?Sized,
+    F: Fetcher + ?Sized,
+{
+    warm_up(engine, fetcher, options).await?;
+
+    let url = engine.url(query, options)?;
+
+    let FetchedResponse { response, body } = send(engine, fetcher, url, options).await?;
+    engine
+        .validate_response(&response)
+        .inspect_err(|this| end_blocked_session(engine, this))?;
+
+    Ok(body)
+}
+
+/// Sends a request for `url` with `engine`'s headers and egress, once its pacer allows it.
+/// The engine keeps the cookies the provider sets when the response is validated.
+pub(crate) async fn send<E, F>(
+    engine: &E,
+    fetcher: &F,
+    url: String,
+    options: &SearchOptions,
+) -> Result<FetchedResponse, EngineError>
+where
+    E: RichEngine + ?Sized,
+    F: Fetcher + ?Sized,
+{
+    let config = engine.config();
+
+    if let Some(pacer) = &config.pacer {
+        let delay = pacer.reserve(engine.request_budget())?;
+        if !delay.is_zero() {
+            fetcher.sleep(delay).await;
+        }
+    }
+
+    let mut headers = HeaderMap::new();
+    engine.headers(&mut headers, options);
+
+    fetcher
+        .fetch(FetchRequest {
+            url,
+            headers,
+            egress: config.egress.clone(),
+        })
+        .await
+}
+
+/// Fetches the results page for a parsed query from `engine`. The query is rendered into the
+/// engine's own syntax, and operators the engine doesn't support are emulated by filtering its
+/// results where possible, or otherwise reported in [`ParsedPage::unsupported_operators`].
+pub async fn fetch_parsed_page<E, F>(
+    engine: &E,
+    fetcher: &F,
+    query: &ParsedQuery,
+    options: &SearchOptions,
+) -> Result<ParsedPage, EngineError>
+where
+    E: RichEngine + ?Sized,
+    F: Fetcher + ?Sized,
+{
+    let rendered_query = query.render(&engine.query_syntax());
+
+    let mut page = fetch_page(engine, fetcher, &rendered_query.query, options).await?;
+    query.retain_matching(&mut page.results, &rendered_query.emulated);
+    page.unsupported_operators = rendered_query.unsupported;
+
+    Ok(page)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        config::EngineConfig,
+        pacing::{Pacer, RequestBudget},
+        testing::{FakeFetcher, TestEngine, options},
+    };
+
+    fn paced_engine(pacer: Pacer) -> TestEngine {
+        TestEngine {
+            config: EngineConfig {
+                pacer: Some(pacer.with_jitter(Duration::ZERO)),
+                ..EngineConfig::default()
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn waits_for_a_queueing_pacer_before_sending() {
+        let engine = paced_engine(Pacer::queueing().with_budget(RequestBudget::per_minute(1)));
+        let fetcher = FakeFetcher::default()
+            .with_body("https://a.test A")
+            .with_body("https://b.test B");
+
+        fetch_page(&engine, &fetcher, "rust", &options())
+            .await
+            .unwrap();
+        fetch_page(&engine, &fetcher, "rust", &options())
+            .await
+            .unwrap();
+
+        let sleeps = fetcher.sleeps();
+        assert_eq!(sleeps.len(), 1);
+        assert!(sleeps[0] > Duration::from_secs(59));
+        assert_eq!(fetcher.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn doesnt_send_requests_a_rejecting_pacer_rejects() {
+        let engine = paced_engine(Pacer::rejecting().with_budget(RequestBudget::per_minute(1)));
+        let fetcher = FakeFetcher::default().with_body("https://a.test A");
+
+        fetch_page(&engine, &fetcher, "rust", &options())
+            .await
+            .unwrap();
+        let error = fetch_page(&engine, &fetcher, "rust", &options())
+            .await
+            .unwrap_err();
+
+        assert!(matches!(error, EngineError::BudgetExceeded { .. }));
+        assert_eq!(fetcher.requests().len(), 1);
+        assert!(fetcher.sleeps().is_empty());
+    }
+}
@@ -1,28 +1,46 @@
 use std::borrow::Cow;
 
 use anyhttp::Response;
-use chrono::Duration;
-use html_hybrid_parser::{ClassNames, Node, Query, class_names_exact};
-use http::{
-    HeaderMap, HeaderValue,
-    header::{ACCEPT, COOKIE, REFERER, USER_AGENT},
+use chrono::{Duration, Utc};
+use html_hybrid_parser::{
+    ClassName, ClassNames, Node, Query, QueryClassNames, class_names_any, class_names_exact,
 };
+use http::HeaderMap;
 use query_parameters::query_params;
 
 use quaero_shared::models::{
     engine::{Engine, TaggedEngine},
-    sanitized_url::SanitizedUrl,
     search::{SearchError, SearchOptions, SearchResult},
-    user_agent::UserAgent,
+};
+
+use crate::{
+    config::EngineConfig,
+    dates::split_date_prefix,
+    engine::{RichEngine, parse_for_quaero},
+    error::ParseError,
+    health::{Field, ParseHealth, SelectorHits, Selectors, first_match},
+    overrides::AnyOf,
+    pacing::RequestBudget,
+    profiles::BrowserProfile,
+    results::{ParsedPage, RichSearchResult, Sitelink, ad_positions},
+    spans::{align_spans, collect_spans},
 };
 
 /// An engine which parses search results from Google.
-pub struct GoogleEngine;
+#[derive(Default)]
+pub struct GoogleEngine {
+    config: EngineConfig,
+}
 
 impl GoogleEngine {
     /// Creates a new Google engine.
     pub fn new() -> TaggedEngine {
-        TaggedEngine::new(Self {})
+        Self::with_config(EngineConfig::default())
+    }
+
+    /// Creates a new Google engine with a custom config.
+    pub fn with_config(config: EngineConfig) -> TaggedEngine {
+        TaggedEngine::new(Self { config })
     }
 }
 
@@ -42,9 +60,11 @@ impl Engine for GoogleEngine {
             ..
         }: &SearchOptions,
     ) -> Result<String, SearchError> {
+        let query = self.render_query(query);
+
         // Turns the page number into the index of the first result.
         // Page 0 is `0`, Page 1 is `10`, Page 2 is `20`, etc...
-        let results_per_page = 10;
+        let results_per_page = self.results_per_page();
         let page_start_idx = page_num * results_per_page;
 
         let safe_search = safe_search.as_lowercase_string();
@@ -58,6 +78,12 @@ impl Engine for GoogleEngine {
             Cow::Borrowed("")
         };
 
+        let results_per_page_param = self.config.results_per_page_param(
+            "num",
+            DEFAULT_RESULTS_PER_PAGE,
+            MAX_RESULTS_PER_PAGE,
+        );
+
         let query_params = query_params! {
             "q" => query,
             "ie" => "utf8",
@@ -67,23 +93,22 @@ impl Engine for GoogleEngine {
         };
 
         Ok(format!(
-            "https://www.google.com/search?{query_params}{date_time_range_param}"
+            "https://www.google.com/search?{query_params}{results_per_page_param}{date_time_range_param}"
         ))
     }
 
     fn headers(&self, headers: &mut HeaderMap, _options: &SearchOptions) {
-        headers.insert(USER_AGENT, UserAgent::random_no_js().into());
-        headers.insert(
-            ACCEPT,
-            HeaderValue::from_static(
-                "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8",
-            ),
-        );
-        headers.append(COOKIE, HeaderValue::from_static("SOCS=CAESHAgBEhIaAB"));
-        headers.append(REFERER, HeaderValue::from_static("https://google.com/"));
+        self.config
+            .browser_profile
+            .unwrap_or_else(BrowserProfile::random_no_js)
+            .apply(headers, self.homepage(), Some("https://google.com/"));
+        self.config
+            .apply_cookies(headers, self.homepage(), "SOCS=CAESHAgBEhIaAB");
     }
 
     fn validate_response(&self, response: &Response) -> Result<(), SearchError> {
+        self.config.store_cookies(response);
+
         let url = response.url();
 
         let was_captcha_gated =
@@ -97,62 +122,244 @@ impl Engine for GoogleEngine {
     }
 
     fn parse<'a>(&self, response_text: String) -> Result<Vec<(String, SearchResult)>, SearchError> {
+        parse_for_quaero(self, response_text)
+    }
+}
+
+impl RichEngine for GoogleEngine {
+    fn id(&self) -> &str {
+        "google"
+    }
+
+    fn config(&self) -> &EngineConfig {
+        &self.config
+    }
+
+    fn request_budget(&self) -> RequestBudget {
+        REQUEST_BUDGET
+    }
+
+    fn results_per_page(&self) -> usize {
+        self.config
+            .results_per_page(DEFAULT_RESULTS_PER_PAGE, MAX_RESULTS_PER_PAGE)
+    }
+
+    fn parse_rich(&self, response_text: String) -> Result<ParsedPage, ParseError> {
         let dom = html_hybrid_parser::Parser::fast_but_constrained(&response_text);
         let parser = dom.parser();
 
-        let nodes = dom.get_nodes_with_classes(&SEARCH_RESULT_CLASSES, parser);
+        let overrides = self.config.selectors(self.id());
+        let search_result_classes =
+            overrides.classes("SEARCH_RESULT_CLASSES", &SEARCH_RESULT_CLASSES);
+        let title_classes = overrides.classes("TITLE_CLASSES", &TITLE_CLASSES);
+        let title_text_classes = overrides.classes("TITLE_TEXT_CLASSES", &TITLE_TEXT_CLASSES);
+        let summary_classes = overrides.classes("SUMMARY_CLASSES", &SUMMARY_CLASSES);
+
+        let nodes = dom
+            .get_nodes_with_classes(&search_result_classes, parser)
+            .collect::<Vec<_>>();
+        let containers = nodes.len();
+        let mut selector_hits = SelectorHits::default();
+        let now = Utc::now();
 
-        Ok(nodes
-            .filter_map(|this| {
-                let Some(title_node) = this.get_first_node_with_classes(&TITLE_CLASSES, parser)
+        let results = nodes
+            .into_iter()
+            .map(|this| {
+                let Some(title_node) = this.get_first_node_with_classes(&title_classes, parser)
                 else {
                     return None;
                 };
 
-                let title = title_node
-                    .get_first_node_with_classes(&TITLE_TEXT_CLASSES, parser)
-                    .and_then(|this| this.text(parser).map(|this| this.to_string()))
-                    .unwrap_or_default();
+                let title = first_match!(selector_hits, Field::Title, {
+                    "TITLE_TEXT_CLASSES" => title_node
+                        .get_first_node_with_classes(&title_text_classes, parser)
+                        .and_then(|this| this.text(parser).map(|this| this.to_string())),
+                })
+                .unwrap_or_default();
 
-                let url = title_node
-                    .get_first_node_with_tag("a", parser)
-                    .and_then(|this| {
-                        this.get_href().map(|this| {
-                            this.strip_prefix("/url?q=")
-                                .unwrap_or(this.as_ref())
-                                .to_owned()
-                        })
+                let url = first_match!(selector_hits, Field::Url, {
+                    "{TITLE_CLASSES} a" => title_node
+                        .get_first_node_with_tag("a", parser)
+                        .and_then(|this| {
+                            this.get_href().map(|this| {
+                                this.strip_prefix("/url?q=")
+                                    .unwrap_or(this.as_ref())
+                                    .to_owned()
+                            })
+                        }),
+                })
+                .unwrap_or_default();
+
+                let summary_spans = this
+                    .get_first_node_with_classes(&summary_classes, parser)
+                    .and_then(|this| this.get_first_node_with_classes(&summary_classes, parser))
+                    .map(|this| {
+                        collect_spans(
+                            this.get_child_nodes(parser)
+                                .map(|this| (this.tag_name(), this.text(parser))),
+                        )
                     })
                     .unwrap_or_default();
 
-                let summary = this
-                    .get_first_node_with_classes(&SUMMARY_CLASSES, parser)
-                    .and_then(|this| {
-                        this.get_first_node_with_classes(&SUMMARY_CLASSES, parser)
+                let (summary, summary_selector) =
+                    first_match!(@selector selector_hits, Field::Summary, {
+                        "SUMMARY_CLASSES" => this
+                            .get_first_node_with_classes(&summary_classes, parser)
                             .and_then(|this| {
-                                this.children_raw_text(parser).map(|this| this.to_string())
+                                this.get_first_node_with_classes(&summary_classes, parser)
+                                    .and_then(|this| {
+                                        this.children_raw_text(parser).map(|this| this.to_string())
+                                    })
+                            }),
+                    })
+                    .unwrap_or_default();
+
+                // Summaries may start with the date the result was published (e.g. `3 days ago — `).
+                let (date, summary) = match first_match!(selector_hits, Field::Date, {
+                    summary_selector => split_date_prefix(&summary, now),
+                }) {
+                    Some((date, summary)) => (Some(date), summary.to_string()),
+                    None => (None, summary),
+                };
+
+                // Sitelinks are shown as a list of links underneath the main result.
+                let sitelinks = this
+                    .get_first_node_with_classes(&SITELINKS_WRAPPER_CLASSES, parser)
+                    .map(|this| {
+                        this.get_child_nodes(parser)
+                            .filter_map(|this| {
+                                let link = this.get_first_node_with_tag("a", parser)?;
+
+                                let title = link.text(parser)?.trim().to_string();
+
+                                let href = link.get_href()?;
+                                let url = href.strip_prefix("/url?q=").unwrap_or(href.as_ref());
+
+                                let description = this
+                                    .get_first_node_with_classes(
+                                        &SITELINK_DESCRIPTION_CLASSES,
+                                        parser,
+                                    )
+                                    .and_then(|this| {
+                                        this.text(parser).map(|this| this.to_string())
+                                    });
+
+                                Some(Sitelink::new(
+                                    title,
+                                    url,
+                                    description,
+                                    filter_search_param_in_result_url,
+                                ))
                             })
+                            .collect()
                     })
                     .unwrap_or_default();
 
-                let sanitized_url = SanitizedUrl::new(&url, filter_search_param_in_result_url);
-                Some(SearchResult::new_from_sanitized_url(
-                    title,
-                    sanitized_url,
-                    summary,
-                ))
+                let summary_spans = align_spans(summary_spans, &summary);
+
+                Some(RichSearchResult {
+                    summary_spans,
+                    sitelinks,
+                    date,
+                    ..RichSearchResult::new_with_url_filter(
+                        title,
+                        url,
+                        summary,
+                        filter_search_param_in_result_url,
+                    )
+                })
             })
-            .collect())
+            .collect::<Vec<_>>();
+
+        let ad_positions = ad_positions(
+            &results,
+            dom.get_nodes_with_classes(&AnyOf(&search_result_classes, &AD_CLASSES), parser)
+                .map(|this| AD_CLASSES.matches(this.class())),
+        );
+
+        let mut page = ParsedPage::new(results.into_iter().flatten().collect());
+        page.health = ParseHealth::new(&SELECTORS, &overrides, containers, selector_hits);
+
+        // Each ad is shown in its own block above, below or amongst the results.
+        let ads = dom
+            .get_nodes_with_classes(&AD_CLASSES, parser)
+            .zip(ad_positions)
+            .filter_map(|(this, position)| {
+                let link = this.get_first_node_with_tag("a", parser)?;
+
+                let title = link
+                    .get_first_node_with_classes(&title_text_classes, parser)
+                    .and_then(|this| this.text(parser))
+                    .or_else(|| link.text(parser))?
+                    .to_string();
+
+                let href = link.get_href()?;
+                let url = href
+                    .strip_prefix("/url?q=")
+                    .unwrap_or(href.as_ref())
+                    .to_owned();
+
+                let summary = this
+                    .get_first_node_with_classes(&summary_classes, parser)
+                    .and_then(|this| this.children_raw_text(parser).map(|this| this.to_string()))
+                    .unwrap_or_default();
+
+                Some((
+                    position,
+                    RichSearchResult::new_with_url_filter(
+                        title,
+                        url,
+                        summary,
+                        filter_search_param_in_result_url,
+                    ),
+                ))
+            });
+        page.add_ads(ads, self.config.ads);
+
+        // The no-js results page doesn't show a result count, but it does link to the next page.
+        page.has_next_page = Some(dom.get_nodes_with_classes(&PAGE_LINK_CLASSES, parser).any(
+            |this| {
+                this.get_attribute("aria-label")
+                    .is_some_and(|this| this.as_ref() == "Next page")
+            },
+        ));
+
+        Ok(page)
     }
 }
 
+const DEFAULT_RESULTS_PER_PAGE: usize = 10;
+const MAX_RESULTS_PER_PAGE: usize = 100;
+
+const REQUEST_BUDGET: RequestBudget = RequestBudget::per_minute(10);
+
+pub(crate) const SELECTORS: Selectors = Selectors {
+    container: "{SEARCH_RESULT_CLASSES}",
+    title: "{TITLE_CLASSES} {TITLE_TEXT_CLASSES}",
+    url: "{TITLE_CLASSES} a",
+    summary: "{SUMMARY_CLASSES}",
+    classes: &[
+        ("SEARCH_RESULT_CLASSES", ".Gx5Zad.xpd.EtOod.pkphOe"),
+        ("TITLE_CLASSES", ".egMi0.kCrYT"),
+        ("TITLE_TEXT_CLASSES", ".ilUpNd.UFvD1.aSRlid"),
+        ("SUMMARY_CLASSES", ".ilUpNd.H66NU.aSRlid"),
+    ],
+};
+
 const SEARCH_RESULT_CLASSES: ClassNames = class_names_exact! { "Gx5Zad", "xpd", "EtOod", "pkphOe" };
 
+const AD_CLASSES: ClassName = class_names_any! { "uEierd" };
+
 const TITLE_CLASSES: ClassNames = class_names_exact! { "egMi0", "kCrYT" };
 const TITLE_TEXT_CLASSES: ClassNames = class_names_exact! { "ilUpNd", "UFvD1", "aSRlid" };
 
 const SUMMARY_CLASSES: ClassNames = class_names_exact! { "ilUpNd", "H66NU", "aSRlid" };
 
+const SITELINKS_WRAPPER_CLASSES: ClassNames = class_names_exact! { "ilUpNd", "JrSeUb" };
+const SITELINK_DESCRIPTION_CLASSES: ClassNames = class_names_exact! { "ilUpNd", "Q9XhPe" };
+
+const PAGE_LINK_CLASSES: ClassName = class_names_any! { "nBDE1b" };
+
 const DATE_TIME_PRESETS: [(Duration, &'static str); 5] = [
     (Duration::hours(1), "h"),
     (Duration::hours(24), "d"),
@@ -164,3 +371,167 @@ const DATE_TIME_PRESETS: [(Duration, &'static str); 5] = [
 fn filter_search_param_in_result_url(key: &str, _value: &str) -> bool {
     key == "ved" || key == "sa" || key == "usg" || key.starts_with("utm")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use quaero_shared::models::search::SafeSearch;
+
+    use crate::{config::AdHandling, testing::options};
+
+    fn result(title: &str, url: &str) -> String {
+        format!(
+            r#"<div class="Gx5Zad xpd EtOod pkphOe"><div class="egMi0 kCrYT"><a href="/url?q={url}"><div class="ilUpNd UFvD1 aSRlid">{title}</div></a></div></div>"#
+        )
+    }
+
+    fn ad(title: &str, url: &str) -> String {
+        format!(
+            r#"<div class="uEierd"><a href="{url}"><div class="ilUpNd UFvD1 aSRlid">{title}</div></a></div>"#
+        )
+    }
+
+    #[test]
+    fn keeps_labelled_ads_where_they_were_shown() {
+        let engine = GoogleEngine {
+            config: EngineConfig {
+                ads: AdHandling::Label,
+                ..EngineConfig::default()
+            },
+        };
+
+        let html = [
+            ad("Rust Training", "https://training.example/rust"),
+            result("Rust", "https://www.rust-lang.org/"),
+            result("Docs", "https://doc.rust-lang.org/"),
+            ad("Rust Books", "https://books.example/rust"),
+        ]
+        .concat();
+        let page = engine
+            .parse_rich(format!("<html><body>{html}</body></html>"))
+            .unwrap();
+
+        let titles = page
+            .results
+            .iter()
+            .map(|this| (this.title.as_str(), this.sponsored))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            titles,
+            [
+                ("Rust Training", true),
+                ("Rust", false),
+                ("Docs", false),
+                ("Rust Books", true),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_sitelinks_under_a_result() {
+        let html = r#"<div class="Gx5Zad xpd EtOod pkphOe"><div class="egMi0 kCrYT"><a href="/url?q=https://www.rust-lang.org/"><div class="ilUpNd UFvD1 aSRlid">Rust</div></a></div>
+            <div class="ilUpNd JrSeUb">
+                <div><a href="/url?q=https://www.rust-lang.org/learn&amp;sa=U">Learn</a><div class="ilUpNd Q9XhPe">Get started with Rust.</div></div>
+                <div><a href="/url?q=https://www.rust-lang.org/tools/install">Install</a></div>
+            </div></div>"#;
+
+        let page = GoogleEngine::default()
+            .parse_rich(format!("<html><body>{html}</body></html>"))
+            .unwrap();
+
+        let sitelinks = page.results[0]
+            .sitelinks
+            .iter()
+            .map(|this| (this.title.as_str(), this.description.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sitelinks,
+            [("Learn", Some("Get started with Rust.")), ("Install", None)]
+        );
+    }
+
+    #[test]
+    fn keeps_the_highlighting_in_a_summary_after_its_date() {
+        let html = r#"<div class="Gx5Zad xpd EtOod pkphOe"><div class="egMi0 kCrYT"><a href="/url?q=https://doc.rust-lang.org/book/"><div class="ilUpNd UFvD1 aSRlid">The Book</div></a></div><div class="ilUpNd H66NU aSRlid"><div class="ilUpNd H66NU aSRlid">3 days ago — An introductory book about <b>Rust</b>.</div></div></div>"#;
+
+        let page = GoogleEngine::default()
+            .parse_rich(format!("<html><body>{html}</body></html>"))
+            .unwrap();
+
+        let result = &page.results[0];
+        assert_eq!(result.summary, "An introductory book about Rust.");
+        let spans = result
+            .summary_spans
+            .iter()
+            .map(|this| (this.text.as_str(), this.highlighted))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            spans,
+            [
+                ("An introductory book about ", false),
+                ("Rust", true),
+                (".", false)
+            ]
+        );
+    }
+
+    fn safe_search_url(safe_search: SafeSearch) -> String {
+        let options = SearchOptions {
+            safe_search,
+            ..options()
+        };
+
+        GoogleEngine::default().url("rust", &options).unwrap()
+    }
+
+    fn paged_url(results_per_page: usize) -> String {
+        let engine = GoogleEngine {
+            config: EngineConfig {
+                results_per_page: Some(results_per_page),
+                ..EngineConfig::default()
+            },
+        };
+        let options = SearchOptions {
+            page_num: 2,
+            ..options()
+        };
+
+        engine.url("rust", &options).unwrap()
+    }
+
+    #[test]
+    fn requests_the_configured_number_of_results_per_page() {
+        assert!(paged_url(20).contains("&start=40&"));
+        assert!(paged_url(20).contains("&num=20"));
+        assert!(paged_url(500).contains("&start=200&"));
+        assert!(paged_url(500).contains("&num=100"));
+        assert!(!paged_url(10).contains("num="));
+    }
+
+    #[test]
+    fn sends_the_safe_search_level() {
+        assert!(safe_search_url(SafeSearch::Off).contains("&safe=off"));
+        assert!(safe_search_url(SafeSearch::Moderate).contains("&safe=moderate"));
+        assert!(safe_search_url(SafeSearch::Strict).contains("&safe=strict"));
+    }
+
+    #[test]
+    fn supports_every_safe_search_level() {
+        for safe_search in [SafeSearch::Off, SafeSearch::Moderate, SafeSearch::Strict] {
+            assert!(GoogleEngine::default().supports_safe_search(&safe_search));
+        }
+    }
+
+    #[tokio::test]
+    async fn replays_its_cassette() {
+        use crate::testing::cassettes::{cassette_config, replay_cassette};
+
+        let engine = GoogleEngine {
+            config: cassette_config(),
+        };
+
+        let page = replay_cassette(&engine, "google").await;
+        assert_eq!(page.results[0].title, "Rust Programming Language");
+    }
+}
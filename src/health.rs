@@ -0,0 +1,381 @@
+//! Reports on how well an engine's selectors matched a results page, so changes to a provider's
+//! markup can be noticed before users do.
+
+use std::fmt;
+
+use crate::overrides::EngineSelectors;
+
+/// How many of the result containers on a page each field's selector matched in, as recorded
+/// while the page was parsed. This tells a page without results (no containers) apart from a
+/// selector which no longer matches (containers, but no fields).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParseHealth {
+    /// The id of the engine which parsed the page.
+    /// This is only filled in by [`RichEngine::parse_page`](crate::engine::RichEngine::parse_page).
+    pub engine: String,
+    /// The selector for the result containers, including any overrides.
+    pub container_selector: String,
+    /// The number of result containers which matched.
+    pub containers: usize,
+    /// How well the selector for each field matched.
+    pub fields: Vec<FieldHealth>,
+    /// Which selector found each field, which shows when a fallback selector is being used.
+    pub selector_hits: Vec<SelectorHit>,
+}
+
+/// How well the selector for a single field matched.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldHealth {
+    /// The field.
+    pub field: Field,
+    /// The selector used to find the field, including any overrides.
+    pub selector: String,
+    /// The number of containers the field's selector (or one of its fallbacks) matched in.
+    pub matched: usize,
+}
+
+/// How often one of a field's selectors was the one which matched.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelectorHit {
+    /// The field.
+    pub field: Field,
+    /// The selector which matched. This is the name of its class name constant (e.g.
+    /// `SUMMARY_CLASSES`), or the selector written with it if the field isn't on the node it
+    /// matched (e.g. `{TITLE_CLASSES} a`).
+    pub selector: &'static str,
+    /// The number of containers the selector was the first to match in.
+    pub matched: usize,
+}
+
+/// A field of a result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    /// The title.
+    Title,
+    /// The url.
+    Url,
+    /// The summary.
+    Summary,
+    /// The date the result was published. It's optional, so it's only recorded in
+    /// [`ParseHealth::selector_hits`].
+    Date,
+}
+
+/// The selectors an engine uses for the required fields of its results, as written in its markup.
+/// Each class name constant which can be overridden is written as `{NAME}`, and listed in
+/// `classes` with its built-in css.
+pub(crate) struct Selectors {
+    pub(crate) container: &'static str,
+    pub(crate) title: &'static str,
+    pub(crate) url: &'static str,
+    pub(crate) summary: &'static str,
+    pub(crate) classes: &'static [(&'static str, &'static str)],
+}
+
+impl Selectors {
+    /// Whether the constant `name` can be overridden.
+    pub(crate) fn can_override(&self, name: &str) -> bool {
+        self.classes.iter().any(|(this, _)| *this == name)
+    }
+
+    /// The names of the constants which can be overridden.
+    pub(crate) fn names(&self) -> impl Iterator<Item = &'static str> {
+        self.classes.iter().map(|(name, _)| *name)
+    }
+
+    /// Writes `selector` with each constant's css, or its override's.
+    fn render(&self, selector: &str, overrides: &EngineSelectors) -> String {
+        self.classes
+            .iter()
+            .fold(selector.to_string(), |selector, (name, builtin)| {
+                let css = overrides
+                    .get(name)
+                    .map_or_else(|| builtin.to_string(), |this| this.to_nested_css());
+
+                selector.replace(&format!("{{{name}}}"), &css)
+            })
+    }
+}
+
+/// Counts which selector found each field while parsing a page, see [`first_match`].
+#[derive(Default)]
+pub(crate) struct SelectorHits(Vec<SelectorHit>);
+
+impl SelectorHits {
+    pub(crate) fn record(&mut self, field: Field, selector: &'static str) {
+        match self
+            .0
+            .iter_mut()
+            .find(|this| this.field == field && this.selector == selector)
+        {
+            Some(hit) => hit.matched += 1,
+            None => self.0.push(SelectorHit {
+                field,
+                selector,
+                matched: 1,
+            }),
+        }
+    }
+}
+
+/// Tries a field's selectors in order and returns the value from the first one which matched,
+/// recording which one it was. Each alternative is written as `"name" => expression`, where the
+/// expression returns an `Option`. This lets engines support a provider's old and new markup
+/// (or A/B variants) at the same time.
+///
+/// Every engine reads its titles, urls, summaries and dates through it, so their hits are always
+/// recorded, but most fields list a single selector. Alternatives are only added once a provider
+/// is known to show another variant, like Bing's card summaries.
+///
+/// ```ignore
+/// let summary = first_match!(selector_hits, Field::Summary, {
+///     "SUMMARY_CLASSES" => node.get_first_node_with_classes(&SUMMARY_CLASSES, parser),
+///     "OLD_SUMMARY_CLASSES" => node.get_first_node_with_classes(&OLD_SUMMARY_CLASSES, parser),
+/// });
+/// ```
+///
+/// Starting with `@selector` returns the name of the alternative which matched along with its
+/// value, so fields read from another field (like a date at the start of a summary) can be
+/// recorded under the same selector.
+macro_rules! first_match {
+    (@selector $hits:expr, $field:expr, { $($name:expr => $alternative:expr),+ $(,)? }) => {
+        None
+            $(.or_else(|| $alternative.map(|this| (this, $name))))+
+            .map(|(this, selector)| {
+                $hits.record($field, selector);
+                (this, selector)
+            })
+    };
+    ($hits:expr, $field:expr, { $($name:expr => $alternative:expr),+ $(,)? }) => {
+        first_match!(@selector $hits, $field, { $($name => $alternative),+ })
+            .map(|(this, _)| this)
+    };
+}
+
+pub(crate) use first_match;
+
+impl ParseHealth {
+    /// Checks which fields were found in `containers` result containers, from the selectors
+    /// which matched while parsing them with `overrides`.
+    pub(crate) fn new(
+        selectors: &Selectors,
+        overrides: &EngineSelectors,
+        containers: usize,
+        selector_hits: SelectorHits,
+    ) -> Self {
+        let field = |field, selector| FieldHealth {
+            field,
+            selector: selectors.render(selector, overrides),
+            matched: selector_hits
+                .0
+                .iter()
+                .filter(|this| this.field == field)
+                .map(|this| this.matched)
+                .sum::<usize>()
+                .min(containers),
+        };
+
+        Self {
+            engine: String::new(),
+            container_selector: selectors.render(selectors.container, overrides),
+            containers,
+            fields: vec![
+                field(Field::Title, selectors.title),
+                field(Field::Url, selectors.url),
+                field(Field::Summary, selectors.summary),
+            ],
+            selector_hits: selector_hits.0,
+        }
+    }
+
+    /// The number of containers which were missing `field`.
+    pub fn missing(&self, field: Field) -> usize {
+        self.fields
+            .iter()
+            .find(|this| this.field == field)
+            .map_or(0, |this| self.containers - this.matched)
+    }
+
+    /// The fields whose selector didn't match in any of the containers, which usually means
+    /// the provider changed its markup.
+    pub fn failed_fields(&self) -> impl Iterator<Item = &FieldHealth> {
+        self.fields
+            .iter()
+            .filter(|this| self.containers > 0 && this.matched == 0)
+    }
+
+    /// Whether every field's selector matched in at least one container.
+    /// A page with no containers is healthy, as the query may have had no results.
+    pub fn is_healthy(&self) -> bool {
+        self.failed_fields().next().is_none()
+    }
+}
+
+/// Writes a line for each field, e.g. `bing title selector "b_algoheader" matched 0 of 10 containers`.
+impl fmt::Display for ParseHealth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} container selector {:?} matched {} containers",
+            self.engine, self.container_selector, self.containers
+        )?;
+
+        for field in &self.fields {
+            write!(
+                f,
+                "\n{} {} selector {:?} matched {} of {} containers",
+                self.engine, field.field, field.selector, field.matched, self.containers
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Title => "title",
+            Self::Url => "url",
+            Self::Summary => "summary",
+            Self::Date => "date",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SELECTORS: Selectors = Selectors {
+        container: "{RESULT_CLASSES}",
+        title: "{TITLE_CLASSES}",
+        url: "{TITLE_CLASSES} a",
+        summary: ".summary",
+        classes: &[("RESULT_CLASSES", ".result"), ("TITLE_CLASSES", ".title")],
+    };
+
+    fn health(containers: usize, selector_hits: SelectorHits) -> ParseHealth {
+        ParseHealth::new(
+            &SELECTORS,
+            &EngineSelectors::default(),
+            containers,
+            selector_hits,
+        )
+    }
+
+    fn hits(hits: &[(Field, &'static str)]) -> SelectorHits {
+        let mut selector_hits = SelectorHits::default();
+        for (field, selector) in hits {
+            selector_hits.record(*field, selector);
+        }
+
+        selector_hits
+    }
+
+    #[test]
+    fn treats_a_page_without_containers_as_healthy() {
+        let health = health(0, SelectorHits::default());
+
+        assert!(health.is_healthy());
+        assert_eq!(health.missing(Field::Title), 0);
+    }
+
+    #[test]
+    fn reports_a_selector_which_matched_in_no_containers() {
+        let health = health(
+            2,
+            hits(&[(Field::Url, "TITLE_CLASSES"), (Field::Url, "TITLE_CLASSES")]),
+        );
+
+        let failed = health
+            .failed_fields()
+            .map(|this| this.field)
+            .collect::<Vec<_>>();
+        assert_eq!(failed, [Field::Title, Field::Summary]);
+        assert_eq!(health.missing(Field::Url), 0);
+        assert!(!health.is_healthy());
+    }
+
+    #[test]
+    fn counts_the_hits_of_every_fallback_selector() {
+        let health = health(
+            3,
+            hits(&[
+                (Field::Summary, "SUMMARY_CLASSES"),
+                (Field::Summary, "OLD_SUMMARY_CLASSES"),
+                (Field::Summary, "SUMMARY_CLASSES"),
+            ]),
+        );
+
+        assert_eq!(health.missing(Field::Summary), 0);
+        assert_eq!(
+            health.selector_hits,
+            [
+                SelectorHit {
+                    field: Field::Summary,
+                    selector: "SUMMARY_CLASSES",
+                    matched: 2,
+                },
+                SelectorHit {
+                    field: Field::Summary,
+                    selector: "OLD_SUMMARY_CLASSES",
+                    matched: 1,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn records_the_first_alternative_which_matched() {
+        let mut selector_hits = SelectorHits::default();
+
+        let summary = first_match!(selector_hits, Field::Summary, {
+            "SUMMARY_CLASSES" => None::<&str>,
+            "OLD_SUMMARY_CLASSES" => Some("old"),
+            "OLDER_SUMMARY_CLASSES" => Some("older"),
+        });
+
+        assert_eq!(summary, Some("old"));
+        assert_eq!(selector_hits.0.len(), 1);
+        assert_eq!(selector_hits.0[0].selector, "OLD_SUMMARY_CLASSES");
+    }
+
+    #[test]
+    fn returns_the_alternative_which_matched() {
+        let mut selector_hits = SelectorHits::default();
+
+        let summary = first_match!(@selector selector_hits, Field::Summary, {
+            "SUMMARY_CLASSES" => None::<&str>,
+            "OLD_SUMMARY_CLASSES" => Some("3 days ago — old"),
+        });
+        let (summary, selector) = summary.unwrap();
+        let date = first_match!(selector_hits, Field::Date, {
+            selector => summary.split_once(" — ").map(|(date, _)| date),
+        });
+
+        assert_eq!(date, Some("3 days ago"));
+        assert_eq!(
+            selector_hits.0.last(),
+            Some(&SelectorHit {
+                field: Field::Date,
+                selector: "OLD_SUMMARY_CLASSES",
+                matched: 1,
+            })
+        );
+    }
+
+    #[test]
+    fn writes_a_line_for_each_field() {
+        let mut health = health(1, hits(&[(Field::Title, "TITLE_CLASSES")]));
+        health.engine = String::from("test");
+
+        assert_eq!(
+            health.to_string(),
+            "test container selector \".result\" matched 1 containers\n\
+             test title selector \".title\" matched 1 of 1 containers\n\
+             test url selector \".title a\" matched 0 of 1 containers\n\
+             test summary selector \".summary\" matched 0 of 1 containers"
+        );
+    }
+}
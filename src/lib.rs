@@ -4,6 +4,8 @@
 
 use quaero_shared::models::engine::TaggedEngine;
 
+use crate::config::EngineConfig;
+
 macro_rules! pub_use_modules {
     ($($name:ident),+) => {
         $(
@@ -15,6 +17,33 @@ macro_rules! pub_use_modules {
 
 pub_use_modules![bing, brave, google, mojeek, yahoo, yandex];
 
+mod counts;
+mod dates;
+mod spans;
+#[cfg(test)]
+mod testing;
+
+pub mod bangs;
+pub mod cache;
+#[cfg(any(test, feature = "cassettes"))]
+pub mod cassette;
+pub mod combinators;
+pub mod config;
+pub mod cookies;
+pub mod egress;
+pub mod engine;
+pub mod error;
+pub mod fallback;
+pub mod fetch;
+pub mod health;
+pub mod overrides;
+pub mod pacing;
+pub mod pagination;
+pub mod profiles;
+pub mod query;
+pub mod results;
+pub mod warmup;
+
 /// A list of the default engines.
 pub fn default() -> [TaggedEngine; 6] {
     [
@@ -26,3 +55,22 @@ pub fn default() -> [TaggedEngine; 6] {
         YandexEngine::new(),
     ]
 }
+
+/// Creates the engine with the given id (e.g. `"google"`).
+pub fn by_id(id: &str) -> Option<TaggedEngine> {
+    by_id_with_config(id, EngineConfig::default())
+}
+
+/// Creates the engine with the given id (e.g. `"google"`) and a custom config, so each engine
+/// can have its own settings (like routing only Google through a proxy).
+pub fn by_id_with_config(id: &str, config: EngineConfig) -> Option<TaggedEngine> {
+    match id {
+        "bing" => Some(BingEngine::with_config(config)),
+        "brave" => Some(BraveEngine::with_config(config)),
+        "google" => Some(GoogleEngine::with_config(config)),
+        "mojeek" => Some(MojeekEngine::with_config(config)),
+        "yahoo" => Some(YahooEngine::with_config(config)),
+        "yandex" => Some(YandexEngine::with_config(config)),
+        _ => None,
+    }
+}
@@ -1,26 +1,45 @@
 use std::borrow::Cow;
 
-use chrono::Datelike;
+use anyhttp::Response;
+use chrono::{Datelike, Utc};
 use html_hybrid_parser::{ClassName, Node, Query, class_names_any};
-use http::{
-    HeaderMap, HeaderValue,
-    header::{ACCEPT, REFERER, USER_AGENT},
-};
+use http::HeaderMap;
 use query_parameters::query_params;
 
 use quaero_shared::models::{
     engine::{Engine, TaggedEngine},
-    search::{DateTimeRange, SearchError, SearchOptions, SearchResult},
-    user_agent::UserAgent,
+    search::{DateTimeRange, SafeSearch, SearchError, SearchOptions, SearchResult},
+};
+
+use crate::{
+    config::EngineConfig,
+    counts::parse_count,
+    dates::parse_date,
+    engine::{RichEngine, parse_for_quaero},
+    error::{ParseDiagnostics, ParseError},
+    health::{Field, ParseHealth, SelectorHits, Selectors, first_match},
+    pacing::RequestBudget,
+    profiles::BrowserProfile,
+    query::QuerySyntax,
+    results::{ParsedPage, RichSearchResult},
+    spans::{align_spans, collect_spans},
 };
 
 /// An engine which parses search results from Mojeek.
-pub struct MojeekEngine;
+#[derive(Default)]
+pub struct MojeekEngine {
+    config: EngineConfig,
+}
 
 impl MojeekEngine {
     /// Creates a new Mojeek engine.
     pub fn new() -> TaggedEngine {
-        TaggedEngine::new(Self {})
+        Self::with_config(EngineConfig::default())
+    }
+
+    /// Creates a new Mojeek engine with a custom config.
+    pub fn with_config(config: EngineConfig) -> TaggedEngine {
+        TaggedEngine::new(Self { config })
     }
 }
 
@@ -40,10 +59,12 @@ impl Engine for MojeekEngine {
             ..
         }: &SearchOptions,
     ) -> Result<String, SearchError> {
+        let query = self.render_query(query);
+
         // Turns the page number into the index of the first result.
         // Page 0 is `1`, Page 1 is `11`, Page 2 is `21`, etc...
-        const RESULTS_PER_PAGE: usize = 10;
-        let page_start_idx = RESULTS_PER_PAGE * page_num + 1;
+        let results_per_page = self.results_per_page();
+        let page_start_idx = results_per_page * page_num + 1;
 
         let date_time_range_query_param = if let Some(DateTimeRange {
             start: start_range,
@@ -77,6 +98,8 @@ impl Engine for MojeekEngine {
             "arc" => "none",
             "date" => "1",
             "cdate" => "1",
+            // This is the max length of result titles, not the number of results per page,
+            // which Mojeek's web interface doesn't let us change.
             "tlen" => "100",
             "ref" => "1",
             "hp" => "minimal",
@@ -104,27 +127,84 @@ impl Engine for MojeekEngine {
     }
 
     fn headers(&self, headers: &mut HeaderMap, _options: &SearchOptions) {
-        headers.insert(USER_AGENT, UserAgent::random_no_js().into());
-        headers.insert(
-            ACCEPT,
-            HeaderValue::from_static(
-                "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8",
-            ),
-        );
-        headers.append(REFERER, HeaderValue::from_static("https://google.com/"));
+        self.config
+            .browser_profile
+            .unwrap_or_else(BrowserProfile::random_no_js)
+            .apply(headers, self.homepage(), Some("https://google.com/"));
+        self.config.apply_cookies(headers, self.homepage(), "");
+    }
+
+    fn validate_response(&self, response: &Response) -> Result<(), SearchError> {
+        self.config.store_cookies(response);
+        Ok(())
     }
 
     fn parse<'a>(&self, response_text: String) -> Result<Vec<(String, SearchResult)>, SearchError> {
+        parse_for_quaero(self, response_text)
+    }
+}
+
+impl RichEngine for MojeekEngine {
+    fn id(&self) -> &str {
+        "mojeek"
+    }
+
+    fn config(&self) -> &EngineConfig {
+        &self.config
+    }
+
+    fn request_budget(&self) -> RequestBudget {
+        REQUEST_BUDGET
+    }
+
+    // Mojeek's safe search is either on or off, so moderate safe search turns it on.
+    fn supports_safe_search(&self, safe_search: &SafeSearch) -> bool {
+        !matches!(safe_search, SafeSearch::Moderate)
+    }
+
+    // Mojeek supports `site:`, phrases and negation, but not the other operators.
+    fn query_syntax(&self) -> QuerySyntax {
+        QuerySyntax {
+            excluded_site: None,
+            filetype: None,
+            title: None,
+            or: None,
+            ..QuerySyntax::COMMON
+        }
+    }
+
+    fn parse_rich(&self, response_text: String) -> Result<ParsedPage, ParseError> {
         let dom = html_hybrid_parser::Parser::fast_but_constrained(&response_text);
         let parser = dom.parser();
 
-        let Some(node) = dom.get_first_node_with_classes(&SEARCH_RESULT_WRAPPER_CLASSES, parser)
+        let overrides = self.config.selectors(self.id());
+        let search_result_wrapper_classes = overrides.classes(
+            "SEARCH_RESULT_WRAPPER_CLASSES",
+            &SEARCH_RESULT_WRAPPER_CLASSES,
+        );
+        let title_classes = overrides.classes("TITLE_CLASSES", &TITLE_CLASSES);
+        let summary_classes = overrides.classes("SUMMARY_CLASSES", &SUMMARY_CLASSES);
+
+        let Some(node) = dom.get_first_node_with_classes(&search_result_wrapper_classes, parser)
         else {
-            return Err(SearchError::NoResultsFound);
+            return Err(ParseError::SelectorMissing(ParseDiagnostics::new(
+                self.id(),
+                search_result_wrapper_classes.selector(".results-standard"),
+                &response_text,
+            )));
         };
 
-        Ok(node
+        // Skips the text nodes between results.
+        let nodes = node
             .get_child_nodes(parser)
+            .filter(|this| this.tag_name().is_some())
+            .collect::<Vec<_>>();
+        let containers = nodes.len();
+        let mut selector_hits = SelectorHits::default();
+        let now = Utc::now();
+
+        let results = nodes
+            .into_iter()
             .filter_map(|this| {
                 let Some(title_node_outer) = this.get_first_child_node_with_tag("h2", parser)
                 else {
@@ -132,34 +212,197 @@ impl Engine for MojeekEngine {
                 };
 
                 let Some(title_node) =
-                    title_node_outer.get_first_child_node_with_classes(&TITLE_CLASSES, parser)
+                    title_node_outer.get_first_child_node_with_classes(&title_classes, parser)
                 else {
                     return None;
                 };
 
-                let title = title_node
-                    .text(parser)
-                    .map(|this| this.to_string())
-                    .unwrap_or_default();
+                let title = first_match!(selector_hits, Field::Title, {
+                    "TITLE_CLASSES" => title_node.text(parser).map(|this| this.to_string()),
+                })
+                .unwrap_or_default();
 
-                let url = title_node
-                    .get_href()
-                    .map(|this| this.to_string())
-                    .unwrap_or_default();
+                let url = first_match!(selector_hits, Field::Url, {
+                    "TITLE_CLASSES" => title_node.get_href().map(|this| this.to_string()),
+                })
+                .unwrap_or_default();
 
-                let summary = this
-                    .get_first_child_node_with_classes(&SUMMARY_CLASSES, parser)
-                    .and_then(|this| this.text(parser).map(|this| this.to_string()))
+                let summary_spans = this
+                    .get_first_child_node_with_classes(&summary_classes, parser)
+                    .map(|this| {
+                        collect_spans(
+                            this.get_child_nodes(parser)
+                                .map(|this| (this.tag_name(), this.text(parser))),
+                        )
+                    })
                     .unwrap_or_default();
 
-                Some(SearchResult::new(title, url, summary))
+                let summary = first_match!(selector_hits, Field::Summary, {
+                    "SUMMARY_CLASSES" => this
+                        .get_first_child_node_with_classes(&summary_classes, parser)
+                        .and_then(|this| this.text(parser).map(|this| this.to_string())),
+                })
+                .unwrap_or_default();
+
+                // Dates are only shown because we request them with the `date` and `cdate` params.
+                let date = first_match!(selector_hits, Field::Date, {
+                    "DATE_CLASSES" => this
+                        .get_first_node_with_classes(&DATE_CLASSES, parser)
+                        .and_then(|this| this.text(parser).and_then(|this| parse_date(&this, now))),
+                });
+
+                let summary_spans = align_spans(summary_spans, &summary);
+
+                Some(RichSearchResult {
+                    summary_spans,
+                    date,
+                    ..RichSearchResult::new(title, url, summary)
+                })
             })
-            .collect())
+            .collect();
+
+        let mut page = ParsedPage::new(results);
+        page.health = ParseHealth::new(&SELECTORS, &overrides, containers, selector_hits);
+
+        // The result count is shown as `Results 1 to 10 from 1,234,567 in 0.09s`.
+        page.estimated_total = dom
+            .get_first_node_with_classes(&RESULT_COUNT_CLASSES, parser)
+            .and_then(|this| this.text(parser))
+            .and_then(|this| {
+                this.split_once(" from ")
+                    .and_then(|(_, this)| parse_count(this))
+            });
+
+        page.has_next_page = Some(
+            dom.get_first_node_with_classes(&PAGINATION_CLASSES, parser)
+                .is_some_and(|this| {
+                    this.get_first_node_with_classes(&NEXT_PAGE_CLASSES, parser)
+                        .is_some()
+                }),
+        );
+
+        Ok(page)
     }
 }
 
+const REQUEST_BUDGET: RequestBudget = RequestBudget::per_minute(60);
+
+pub(crate) const SELECTORS: Selectors = Selectors {
+    container: "{SEARCH_RESULT_WRAPPER_CLASSES} > *",
+    title: "h2 > {TITLE_CLASSES}",
+    url: "h2 > {TITLE_CLASSES}[href]",
+    summary: "{SUMMARY_CLASSES}",
+    classes: &[
+        ("SEARCH_RESULT_WRAPPER_CLASSES", ".results-standard"),
+        ("TITLE_CLASSES", ".title"),
+        ("SUMMARY_CLASSES", ".s"),
+    ],
+};
+
 const SEARCH_RESULT_WRAPPER_CLASSES: ClassName = class_names_any! { "results-standard" };
 
 const TITLE_CLASSES: ClassName = class_names_any! { "title" };
 
 const SUMMARY_CLASSES: ClassName = class_names_any! { "s" };
+
+const DATE_CLASSES: ClassName = class_names_any! { "mdate" };
+
+const RESULT_COUNT_CLASSES: ClassName = class_names_any! { "top-info" };
+const PAGINATION_CLASSES: ClassName = class_names_any! { "pagination" };
+const NEXT_PAGE_CLASSES: ClassName = class_names_any! { "next" };
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        fetch::fetch_parsed_page,
+        query::ParsedQuery,
+        testing::{FakeFetcher, options},
+    };
+
+    fn safe_search_url(safe_search: SafeSearch) -> String {
+        let options = SearchOptions {
+            safe_search,
+            ..options()
+        };
+
+        MojeekEngine::default().url("rust", &options).unwrap()
+    }
+
+    #[test]
+    fn sends_the_safe_search_level() {
+        assert!(safe_search_url(SafeSearch::Off).contains("&safe=0"));
+        assert!(safe_search_url(SafeSearch::Moderate).contains("&safe=1"));
+        assert!(safe_search_url(SafeSearch::Strict).contains("&safe=1"));
+    }
+
+    #[test]
+    fn only_supports_safe_search_on_or_off() {
+        let engine = MojeekEngine::default();
+
+        assert!(engine.supports_safe_search(&SafeSearch::Off));
+        assert!(!engine.supports_safe_search(&SafeSearch::Moderate));
+        assert!(engine.supports_safe_search(&SafeSearch::Strict));
+    }
+
+    #[test]
+    fn leaves_unsupported_operators_out_of_the_url() {
+        let url = MojeekEngine::default()
+            .url("rust -site:pinterest.com", &options())
+            .unwrap();
+
+        assert!(url.contains("?q=rust&"));
+        assert!(!url.contains("pinterest"));
+    }
+
+    #[test]
+    fn does_not_filter_results_with_the_operators_of_earlier_searches() {
+        let engine = MojeekEngine::default();
+        engine.url("rust -site:pinterest.com", &options()).unwrap();
+        engine.url("rust", &options()).unwrap();
+
+        let page = r#"<html><body><input type="search" name="q" value="rust">
+            <ul class="results-standard">
+            <li><h2><a class="title" href="https://www.rust-lang.org/">Rust</a></h2><p class="s">A language.</p></li>
+            <li><h2><a class="title" href="https://www.pinterest.com/pin/1">Rust memes</a></h2><p class="s">Pins.</p></li>
+            </ul></body></html>"#;
+
+        assert_eq!(Engine::parse(&engine, page.to_string()).unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn emulates_unsupported_operators_of_a_parsed_query() {
+        let fetcher = FakeFetcher::default().with_body(
+            r#"<html><body><ul class="results-standard">
+            <li><h2><a class="title" href="https://www.rust-lang.org/">Rust</a></h2><p class="s">A language.</p></li>
+            <li><h2><a class="title" href="https://www.pinterest.com/pin/1">Rust memes</a></h2><p class="s">Pins.</p></li>
+            </ul></body></html>"#,
+        );
+        let query = ParsedQuery::parse("rust -site:pinterest.com");
+
+        let page = fetch_parsed_page(&MojeekEngine::default(), &fetcher, &query, &options())
+            .await
+            .unwrap();
+
+        assert!(fetcher.requests()[0].url.contains("?q=rust&"));
+        let urls = page
+            .results
+            .iter()
+            .map(|this| this.url.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(urls, ["https://www.rust-lang.org/"]);
+    }
+
+    #[tokio::test]
+    async fn replays_its_cassette() {
+        use crate::testing::cassettes::{cassette_config, replay_cassette};
+
+        let engine = MojeekEngine {
+            config: cassette_config(),
+        };
+
+        let page = replay_cassette(&engine, "mojeek").await;
+        assert_eq!(page.results[0].title, "Rust Programming Language");
+    }
+}
@@ -0,0 +1,332 @@
+//! Selector overrides loaded from a file, so an engine broken by a provider's markup changes can
+//! be patched (and reloaded) without a new release. The built-in selectors stay the defaults.
+//!
+//! Each line of the file overrides one of an engine's class name constants:
+//!
+//! ```text
+//! # <engine>.<constant> = <any|exact> <class>...
+//! google.SEARCH_RESULT_CLASSES = exact Gx5Zad xpd EtOod pkphOe
+//! bing.TITLE_CLASSES = any b_algoheader b_title
+//! ```
+//!
+//! The constants for the result containers, titles and summaries can be overridden. Every engine
+//! reads a result's url from the link in its title, so the title's constant covers urls too.
+//! Unknown engines and constants are rejected, so a typo doesn't leave a broken selector in place.
+
+use std::{
+    borrow::Cow,
+    collections::{BTreeSet, HashMap},
+    fmt, fs, io,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
+
+use html_hybrid_parser::QueryClassNames;
+
+use crate::health::Selectors;
+
+/// Class names which are matched against an element's `class` attribute.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassSelector {
+    classes: Vec<String>,
+    mode: ClassMatch,
+}
+
+/// How a [`ClassSelector`] matches an element.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClassMatch {
+    /// The element has any of the classes, like `class_names_any!`.
+    Any,
+    /// The element has exactly the classes, like `class_names_exact!`.
+    Exact,
+}
+
+impl ClassSelector {
+    /// Creates a selector which matches elements with any of `classes`.
+    pub fn any(classes: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self::new(classes, ClassMatch::Any)
+    }
+
+    /// Creates a selector which matches elements with exactly `classes`, in any order.
+    pub fn exact(classes: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self::new(classes, ClassMatch::Exact)
+    }
+
+    fn new(classes: impl IntoIterator<Item = impl Into<String>>, mode: ClassMatch) -> Self {
+        Self {
+            classes: classes.into_iter().map(Into::into).collect(),
+            mode,
+        }
+    }
+
+    /// Writes the selector as css which can be combined with other selectors, e.g.
+    /// `:is(.b_algoheader, .b_title) a`.
+    pub(crate) fn to_nested_css(&self) -> String {
+        match self.mode {
+            ClassMatch::Any if self.classes.len() > 1 => format!(":is({self})"),
+            _ => self.to_string(),
+        }
+    }
+
+    fn matches_class_attribute(&self, class_attribute: &str) -> bool {
+        let mut element_classes = class_attribute.split_whitespace();
+
+        match self.mode {
+            ClassMatch::Any => {
+                element_classes.any(|this| self.classes.iter().any(|class| class == this))
+            }
+            ClassMatch::Exact => {
+                element_classes.collect::<BTreeSet<_>>()
+                    == self.classes.iter().map(String::as_str).collect()
+            }
+        }
+    }
+}
+
+/// Writes the selector as css, e.g. `.b_algoheader, .b_title` or `.Gx5Zad.xpd`.
+impl fmt::Display for ClassSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let separator = match self.mode {
+            ClassMatch::Any => ", .",
+            ClassMatch::Exact => ".",
+        };
+
+        write!(f, ".{}", self.classes.join(separator))
+    }
+}
+
+impl QueryClassNames for ClassSelector {
+    fn matches(&self, class: Option<Cow<'_, str>>) -> bool {
+        class.is_some_and(|this| self.matches_class_attribute(&this))
+    }
+}
+
+/// The selector overrides for a single engine, keyed by the name of the constant they replace.
+#[derive(Debug, Clone, Default)]
+pub struct EngineSelectors(HashMap<String, ClassSelector>);
+
+impl EngineSelectors {
+    /// The override for the constant `name`, if there is one.
+    pub fn get(&self, name: &str) -> Option<&ClassSelector> {
+        self.0.get(name)
+    }
+
+    /// The override for the constant `name`, or the constant's own `classes`.
+    pub(crate) fn classes<'a, C>(&'a self, name: &str, classes: &'a C) -> Classes<'a, C> {
+        match self.get(name) {
+            Some(selector) => Classes::Override(selector),
+            None => Classes::Builtin(classes),
+        }
+    }
+}
+
+/// Either an engine's built-in class names or their override.
+pub(crate) enum Classes<'a, C> {
+    Builtin(&'a C),
+    Override(&'a ClassSelector),
+}
+
+impl<C> Classes<'_, C> {
+    /// The css selector for the classes, where `builtin` is the selector of the built-in ones.
+    pub(crate) fn selector(&self, builtin: &str) -> String {
+        match self {
+            Self::Builtin(_) => builtin.to_string(),
+            Self::Override(selector) => selector.to_string(),
+        }
+    }
+}
+
+impl<C: QueryClassNames> QueryClassNames for Classes<'_, C> {
+    fn matches(&self, class: Option<Cow<'_, str>>) -> bool {
+        match self {
+            Self::Builtin(classes) => classes.matches(class),
+            Self::Override(selector) => selector.matches(class),
+        }
+    }
+}
+
+/// Matches the elements which either set of class names matches, e.g. to find results and ads in
+/// the order they're shown.
+pub(crate) struct AnyOf<'a, A: ?Sized, B: ?Sized>(pub(crate) &'a A, pub(crate) &'a B);
+
+impl<A: QueryClassNames + ?Sized, B: QueryClassNames + ?Sized> QueryClassNames for AnyOf<'_, A, B> {
+    fn matches(&self, class: Option<Cow<'_, str>>) -> bool {
+        self.0.matches(class.clone()) || self.1.matches(class)
+    }
+}
+
+/// Selector overrides for every engine, read from a file.
+/// Share it between engines through [`EngineConfig::selector_overrides`](crate::config::EngineConfig::selector_overrides).
+#[derive(Debug)]
+pub struct SelectorOverrides {
+    path: PathBuf,
+    engines: RwLock<HashMap<String, Arc<EngineSelectors>>>,
+}
+
+impl SelectorOverrides {
+    /// Reads the overrides from the file at `path`.
+    pub fn load(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let engines = parse_overrides(&fs::read_to_string(&path)?)?;
+
+        Ok(Self {
+            path,
+            engines: RwLock::new(engines),
+        })
+    }
+
+    /// Reads the overrides from the file again. If the file can't be read or parsed, the
+    /// current overrides are kept. Pages which are already being parsed keep using the old ones.
+    pub fn reload(&self) -> io::Result<()> {
+        let engines = parse_overrides(&fs::read_to_string(&self.path)?)?;
+        *self
+            .engines
+            .write()
+            .unwrap_or_else(|this| this.into_inner()) = engines;
+
+        Ok(())
+    }
+
+    /// The current overrides for the engine with the id `engine_id`.
+    pub fn for_engine(&self, engine_id: &str) -> Arc<EngineSelectors> {
+        self.engines
+            .read()
+            .unwrap_or_else(|this| this.into_inner())
+            .get(engine_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+fn parse_overrides(contents: &str) -> io::Result<HashMap<String, Arc<EngineSelectors>>> {
+    let mut engines: HashMap<String, EngineSelectors> = HashMap::new();
+
+    for (idx, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let invalid_line = |reason: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: {reason}", idx + 1),
+            )
+        };
+
+        let (key, value) = line.split_once('=').ok_or_else(|| {
+            invalid_line("expected `<engine>.<constant> = <any|exact> <class>...`")
+        })?;
+        let (engine, name) = key
+            .trim()
+            .split_once('.')
+            .ok_or_else(|| invalid_line("expected `<engine>.<constant>`"))?;
+
+        let selectors = engine_selectors(engine).ok_or_else(|| {
+            invalid_line(&format!(
+                "unknown engine `{engine}`, expected one of {}",
+                ENGINE_IDS.join(", ")
+            ))
+        })?;
+        if !selectors.can_override(name) {
+            return Err(invalid_line(&format!(
+                "`{engine}` has no constant `{name}` which can be overridden, expected one of {}",
+                selectors.names().collect::<Vec<_>>().join(", ")
+            )));
+        }
+
+        let mut value = value.split_whitespace();
+        let selector = match value.next() {
+            Some("any") => ClassSelector::any(value),
+            Some("exact") => ClassSelector::exact(value),
+            _ => return Err(invalid_line("expected `any` or `exact`")),
+        };
+        if selector.classes.is_empty() {
+            return Err(invalid_line("expected at least one class"));
+        }
+
+        engines
+            .entry(engine.to_string())
+            .or_default()
+            .0
+            .insert(name.to_string(), selector);
+    }
+
+    Ok(engines
+        .into_iter()
+        .map(|(engine, selectors)| (engine, Arc::new(selectors)))
+        .collect())
+}
+
+/// The selectors of the engine with the id `engine_id`, which list its overridable constants.
+fn engine_selectors(engine_id: &str) -> Option<&'static Selectors> {
+    match engine_id {
+        "bing" => Some(&crate::bing::SELECTORS),
+        "brave" => Some(&crate::brave::SELECTORS),
+        "google" => Some(&crate::google::SELECTORS),
+        "mojeek" => Some(&crate::mojeek::SELECTORS),
+        "yahoo" => Some(&crate::yahoo::SELECTORS),
+        "yandex" => Some(&crate::yandex::SELECTORS),
+        _ => None,
+    }
+}
+
+const ENGINE_IDS: [&str; 6] = ["bing", "brave", "google", "mojeek", "yahoo", "yandex"];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(contents: &str) -> String {
+        parse_overrides(contents).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn parses_overrides_for_each_engine() {
+        let engines = parse_overrides(
+            "# Bing's titles were renamed.\n\
+             bing.TITLE_CLASSES = any b_algoheader b_title\n\
+             google.SEARCH_RESULT_CLASSES = exact Gx5Zad xpd",
+        )
+        .unwrap();
+
+        assert_eq!(
+            engines["bing"].get("TITLE_CLASSES"),
+            Some(&ClassSelector::any(["b_algoheader", "b_title"]))
+        );
+        assert_eq!(
+            engines["google"].get("SEARCH_RESULT_CLASSES"),
+            Some(&ClassSelector::exact(["Gx5Zad", "xpd"]))
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_engines() {
+        assert_eq!(
+            error("bign.TITLE_CLASSES = any b_title"),
+            "line 1: unknown engine `bign`, expected one of bing, brave, google, mojeek, yahoo, yandex"
+        );
+    }
+
+    #[test]
+    fn rejects_constants_which_cannot_be_overridden() {
+        assert_eq!(
+            error("\nbing.TITLE_CLASS = any b_title"),
+            "line 2: `bing` has no constant `TITLE_CLASS` which can be overridden, expected one of \
+             SEARCH_RESULT_CLASSES, TITLE_CLASSES, TEXT_SUMMARY_WRAPPER_CLASSES, TEXT_SUMMARY_CLASSES"
+        );
+    }
+
+    #[test]
+    fn nests_selectors_which_match_any_class() {
+        assert_eq!(
+            ClassSelector::any(["b_algoheader", "b_title"]).to_nested_css(),
+            ":is(.b_algoheader, .b_title)"
+        );
+        assert_eq!(ClassSelector::any(["title"]).to_nested_css(), ".title");
+        assert_eq!(
+            ClassSelector::exact(["egMi0", "kCrYT"]).to_nested_css(),
+            ".egMi0.kCrYT"
+        );
+    }
+}
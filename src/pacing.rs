@@ -0,0 +1,364 @@
+//! Pacing an engine's requests to stay within the budget its provider tolerates, rather than
+//! sending them until the provider starts serving captchas.
+//!
+//! Pacing only applies to requests sent through this crate, e.g. with
+//! [`fetch_page`](crate::fetch::fetch_page). quaero sends the requests for engines it drives
+//! through their [`Engine`](quaero_shared::models::engine::Engine) implementation itself, so
+//! those aren't paced.
+
+use std::{
+    collections::{BTreeMap, VecDeque, hash_map::RandomState},
+    future::Future,
+    hash::{BuildHasher, Hasher},
+    pin::Pin,
+    sync::{
+        Arc, Condvar, Mutex, Once,
+        atomic::{AtomicUsize, Ordering},
+    },
+    task::{Context, Poll, Waker},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::error::EngineError;
+
+/// How many requests can be sent to a provider in a period of time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestBudget {
+    /// The number of requests.
+    pub requests: usize,
+    /// The period the requests can be sent in.
+    pub per: Duration,
+}
+
+impl RequestBudget {
+    /// A budget of `requests` requests per minute.
+    pub const fn per_minute(requests: usize) -> Self {
+        Self {
+            requests,
+            per: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Enforces an engine's [`RequestBudget`]. Cloning the pacer shares its budget, so one pacer
+/// should be used for every instance of an engine which sends requests from the same address.
+/// See the [module docs](self) for which requests are paced.
+#[derive(Debug, Clone)]
+pub struct Pacer {
+    mode: PacingMode,
+    budget: Option<RequestBudget>,
+    jitter: Duration,
+    requests: Arc<Mutex<VecDeque<Instant>>>,
+}
+
+/// What a [`Pacer`] does with requests which exceed the budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacingMode {
+    /// The request waits until the budget allows it.
+    Queue,
+    /// The request fails with [`EngineError::BudgetExceeded`].
+    Reject,
+}
+
+impl Pacer {
+    /// Creates a pacer which makes requests wait until the budget allows them.
+    pub fn queueing() -> Self {
+        Self::new(PacingMode::Queue)
+    }
+
+    /// Creates a pacer which rejects requests exceeding the budget.
+    pub fn rejecting() -> Self {
+        Self::new(PacingMode::Reject)
+    }
+
+    fn new(mode: PacingMode) -> Self {
+        Self {
+            mode,
+            budget: None,
+            jitter: DEFAULT_JITTER,
+            requests: Arc::default(),
+        }
+    }
+
+    /// Uses `budget` instead of the budget the engine declares.
+    pub fn with_budget(mut self, budget: RequestBudget) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Delays each request by a random duration of up to `jitter` after its slot in the budget,
+    /// so requests aren't sent at suspiciously regular intervals. The jitter doesn't move the
+    /// slots of the requests queued after it.
+    pub fn with_jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Reserves a slot for a request within `engine_budget` (unless the pacer has its own), and
+    /// returns how long to wait before sending it.
+    pub(crate) fn reserve(&self, engine_budget: RequestBudget) -> Result<Duration, EngineError> {
+        let RequestBudget { requests, per } = self.budget.unwrap_or(engine_budget);
+        let now = Instant::now();
+
+        let mut reserved = self
+            .requests
+            .lock()
+            .unwrap_or_else(|this| this.into_inner());
+        while reserved
+            .front()
+            .is_some_and(|&this| now.saturating_duration_since(this) >= per)
+        {
+            reserved.pop_front();
+        }
+
+        // The request can be sent once the request `requests` places before it is out of the
+        // window. Reservations are in order, so that's the only one which needs checking.
+        let earliest = match reserved.len().checked_sub(requests.max(1)) {
+            Some(idx) => {
+                let available_at = reserved[idx] + per;
+
+                if self.mode == PacingMode::Reject {
+                    return Err(EngineError::BudgetExceeded {
+                        retry_after: available_at.saturating_duration_since(now),
+                    });
+                }
+
+                available_at.max(now)
+            }
+            None => now,
+        };
+
+        let slot = earliest.max(reserved.back().copied().unwrap_or(now));
+        reserved.push_back(slot);
+
+        Ok((slot + random_up_to(self.jitter)).saturating_duration_since(now))
+    }
+}
+
+// Uses the random keys of a new `RandomState`, to avoid depending on a random number generator.
+fn random_up_to(max: Duration) -> Duration {
+    let random = RandomState::new().build_hasher().finish();
+    max.mul_f64(random as f64 / u64::MAX as f64)
+}
+
+/// Waits for `duration` on the timer thread, which wakes the task once it's over.
+pub(crate) async fn sleep(duration: Duration) {
+    if !duration.is_zero() {
+        Sleep {
+            until: Instant::now() + duration,
+        }
+        .await
+    }
+}
+
+struct Sleep {
+    until: Instant,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if Instant::now() >= self.until {
+            return Poll::Ready(());
+        }
+
+        TIMER.wake_at(self.until, cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+/// A single thread which wakes sleeping tasks once their time is up, shared by every sleep so
+/// waiting doesn't start a thread each time. It's started by the first sleep.
+struct Timer {
+    wakers: Mutex<BTreeMap<(Instant, usize), Waker>>,
+    changed: Condvar,
+    next_id: AtomicUsize,
+    thread: Once,
+}
+
+impl Timer {
+    fn wake_at(&'static self, until: Instant, waker: Waker) {
+        self.thread.call_once(|| {
+            thread::spawn(|| self.run());
+        });
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.wakers
+            .lock()
+            .unwrap_or_else(|this| this.into_inner())
+            .insert((until, id), waker);
+        self.changed.notify_one();
+    }
+
+    fn run(&self) {
+        let mut wakers = self.wakers.lock().unwrap_or_else(|this| this.into_inner());
+
+        loop {
+            let now = Instant::now();
+            let pending = wakers.split_off(&(now, usize::MAX));
+            let due = std::mem::replace(&mut *wakers, pending);
+
+            if !due.is_empty() {
+                // Wakes the tasks without holding the lock, as they may sleep again right away.
+                drop(wakers);
+                due.into_values().for_each(Waker::wake);
+                wakers = self.wakers.lock().unwrap_or_else(|this| this.into_inner());
+                continue;
+            }
+
+            wakers = match wakers.keys().next() {
+                Some(&(until, _)) => {
+                    self.changed
+                        .wait_timeout(wakers, until.saturating_duration_since(now))
+                        .unwrap_or_else(|this| this.into_inner())
+                        .0
+                }
+                None => self
+                    .changed
+                    .wait(wakers)
+                    .unwrap_or_else(|this| this.into_inner()),
+            };
+        }
+    }
+}
+
+const DEFAULT_JITTER: Duration = Duration::from_millis(500);
+
+static TIMER: Timer = Timer {
+    wakers: Mutex::new(BTreeMap::new()),
+    changed: Condvar::new(),
+    next_id: AtomicUsize::new(0),
+    thread: Once::new(),
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUDGET: RequestBudget = RequestBudget::per_minute(2);
+
+    #[test]
+    fn rejects_requests_beyond_the_budget() {
+        let pacer = Pacer::rejecting().with_jitter(Duration::ZERO);
+
+        assert_eq!(pacer.reserve(BUDGET).unwrap(), Duration::ZERO);
+        assert_eq!(pacer.reserve(BUDGET).unwrap(), Duration::ZERO);
+
+        let Err(EngineError::BudgetExceeded { retry_after }) = pacer.reserve(BUDGET) else {
+            panic!("expected the budget to be exceeded");
+        };
+        assert!(retry_after > Duration::from_secs(59) && retry_after <= BUDGET.per);
+    }
+
+    #[test]
+    fn queues_requests_beyond_the_budget() {
+        let pacer = Pacer::queueing().with_jitter(Duration::ZERO);
+
+        pacer.reserve(BUDGET).unwrap();
+        pacer.reserve(BUDGET).unwrap();
+        let delay = pacer.reserve(BUDGET).unwrap();
+
+        assert!(delay > Duration::from_secs(59) && delay <= BUDGET.per);
+    }
+
+    #[test]
+    fn prefers_its_own_budget_to_the_engines() {
+        let pacer = Pacer::rejecting()
+            .with_budget(RequestBudget::per_minute(1))
+            .with_jitter(Duration::ZERO);
+
+        pacer.reserve(BUDGET).unwrap();
+
+        assert!(pacer.reserve(BUDGET).is_err());
+    }
+
+    #[test]
+    fn shares_its_budget_between_clones() {
+        let pacer = Pacer::rejecting().with_jitter(Duration::ZERO);
+        let clone = pacer.clone();
+
+        pacer.reserve(BUDGET).unwrap();
+        clone.reserve(BUDGET).unwrap();
+
+        assert!(pacer.reserve(BUDGET).is_err());
+    }
+
+    #[test]
+    fn allows_requests_again_once_earlier_ones_leave_the_period() {
+        let budget = RequestBudget {
+            requests: 1,
+            per: Duration::from_millis(10),
+        };
+        let pacer = Pacer::rejecting().with_jitter(Duration::ZERO);
+
+        pacer.reserve(budget).unwrap();
+        assert!(pacer.reserve(budget).is_err());
+
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(pacer.reserve(budget).unwrap(), Duration::ZERO);
+    }
+
+    #[test]
+    fn delays_each_request_by_up_to_its_jitter() {
+        let jitter = Duration::from_millis(100);
+        let pacer = Pacer::queueing().with_jitter(jitter);
+
+        let delays = (0..20)
+            .map(|_| pacer.reserve(RequestBudget::per_minute(100)).unwrap())
+            .collect::<Vec<_>>();
+
+        // The requests fit in the budget, so each is only delayed by its own jitter.
+        assert!(delays.iter().all(|this| *this <= jitter));
+        assert!(delays.iter().any(|this| *this > Duration::ZERO));
+    }
+
+    #[test]
+    fn does_not_carry_jitter_over_to_queued_requests() {
+        let budget = RequestBudget {
+            requests: 1,
+            per: Duration::from_secs(1),
+        };
+        let jitter = Duration::from_millis(100);
+        let pacer = Pacer::queueing().with_jitter(jitter);
+
+        let delays = (0..5)
+            .map(|_| pacer.reserve(budget).unwrap())
+            .collect::<Vec<_>>();
+
+        for (idx, delay) in delays.into_iter().enumerate() {
+            let slot = budget.per * idx as u32;
+            assert!(
+                delay + Duration::from_millis(10) >= slot,
+                "{idx}: {delay:?}"
+            );
+            assert!(delay <= slot + jitter, "{idx}: {delay:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn sleeps_without_a_runtime_timer() {
+        let start = Instant::now();
+
+        sleep(Duration::from_millis(20)).await;
+
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn wakes_concurrent_sleeps_in_order_from_one_thread() {
+        let sleepers = [30, 10, 20].map(|millis| {
+            thread::spawn(move || {
+                let runtime = tokio::runtime::Builder::new_current_thread()
+                    .build()
+                    .unwrap();
+                runtime.block_on(sleep(Duration::from_millis(millis)));
+                Instant::now()
+            })
+        });
+        let woken_at = sleepers.map(|this| this.join().unwrap());
+
+        assert!(woken_at[1] <= woken_at[2] && woken_at[2] <= woken_at[0]);
+    }
+}
@@ -0,0 +1,307 @@
+//! Fetching multiple consecutive results pages from a single engine.
+
+use std::collections::HashSet;
+
+use quaero_shared::models::search::{SearchError, SearchOptions};
+
+use crate::{
+    engine::RichEngine,
+    error::EngineError,
+    fetch::{Fetcher, fetch_page},
+    results::RichSearchResult,
+};
+
+/// How much to fetch with [`fetch_pages`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageLimit {
+    /// Fetch up to this many pages.
+    Pages(usize),
+    /// Fetch pages until there are at least this many results.
+    Results(usize),
+}
+
+/// The results of fetching multiple pages.
+#[derive(Debug, Default)]
+pub struct MultiPageResults {
+    /// The results from every page, with duplicates removed.
+    pub results: Vec<RichSearchResult>,
+    /// The number of pages which were fetched.
+    pub pages_fetched: usize,
+    /// Whether the engine ran out of results before the limit was reached.
+    pub exhausted: bool,
+    /// The total number of results the provider claims to have for the query.
+    pub estimated_total: Option<u64>,
+    /// The error which stopped fetching early, if any, including one from the first page.
+    /// Results from the pages before the error are still kept.
+    pub error: Option<EngineError>,
+}
+
+/// Fetches consecutive pages from `engine`, starting at `options.page_num`, until `limit` is
+/// reached or the engine runs out of results. Results which already appeared on an earlier page
+/// are removed, as providers often shift results between pages. Only organic results count
+/// towards a [`PageLimit::Results`] limit, so labelled ads don't take their place.
+pub async fn fetch_pages<E, F>(
+    engine: &E,
+    fetcher: &F,
+    query: &str,
+    options: &SearchOptions,
+    limit: PageLimit,
+) -> MultiPageResults
+where
+    E: RichEngine + ?Sized,
+    F: Fetcher + ?Sized,
+{
+    let max_pages = match limit {
+        PageLimit::Pages(pages) => pages,
+        PageLimit::Results(results) => results.div_ceil(engine.results_per_page()),
+    };
+
+    let mut multi_page_results = MultiPageResults::default();
+    let mut seen_keys = HashSet::new();
+
+    // Providers may return fewer results per page than they claim (e.g. after removing ads),
+    // so when fetching a number of results we allow a few extra pages to make up the difference.
+    let extra_pages = match limit {
+        PageLimit::Pages(_) => 0,
+        PageLimit::Results(_) => EXTRA_PAGES,
+    };
+
+    for page_offset in 0..max_pages + extra_pages {
+        if let PageLimit::Results(results) = limit {
+            if organic_results(&multi_page_results.results) >= results {
+                break;
+            }
+        }
+
+        let page_options = SearchOptions {
+            page_num: options.page_num + page_offset,
+            ..options.clone()
+        };
+
+        let page = match fetch_page(engine, fetcher, query, &page_options).await {
+            Ok(page) => page,
+
+            // The first page having no results is an error, but later pages having none just
+            // means we've reached the end of the results. A page which couldn't be parsed is
+            // still an error, as it may have had results.
+            Err(EngineError::Search(SearchError::NoResultsFound)) if page_offset > 0 => {
+                multi_page_results.exhausted = true;
+                break;
+            }
+
+            Err(error) => {
+                multi_page_results.error = Some(error);
+                break;
+            }
+        };
+
+        multi_page_results.pages_fetched += 1;
+        multi_page_results.estimated_total =
+            multi_page_results.estimated_total.or(page.estimated_total);
+
+        let organic_results_on_page = organic_results(&page.results);
+        let is_last_page = match page.has_next_page {
+            Some(has_next_page) => !has_next_page,
+            None => organic_results_on_page < engine.results_per_page(),
+        };
+
+        multi_page_results.results.extend(
+            page.results
+                .into_iter()
+                .filter(|this| seen_keys.insert(this.key().to_string())),
+        );
+
+        if organic_results_on_page == 0 || is_last_page {
+            multi_page_results.exhausted = true;
+            break;
+        }
+    }
+
+    // Ads shown amongst the organic results which are kept are kept too.
+    if let PageLimit::Results(results) = limit {
+        let mut organic_results = 0;
+        multi_page_results.results.retain(|this| {
+            if this.sponsored {
+                organic_results < results
+            } else {
+                organic_results += 1;
+                organic_results <= results
+            }
+        });
+    }
+
+    multi_page_results
+}
+
+fn organic_results(results: &[RichSearchResult]) -> usize {
+    results.iter().filter(|this| !this.sponsored).count()
+}
+
+const EXTRA_PAGES: usize = 2;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        config::{AdHandling, EngineConfig},
+        testing::{FakeFetcher, TestEngine, options},
+    };
+
+    #[tokio::test]
+    async fn stops_when_a_later_page_has_no_results() {
+        let fetcher = FakeFetcher::default()
+            .with_body("https://a.test A\nhttps://b.test B")
+            .with_body("");
+
+        let results = fetch_pages(
+            &TestEngine::default(),
+            &fetcher,
+            "rust",
+            &options(),
+            PageLimit::Pages(3),
+        )
+        .await;
+
+        assert_eq!(results.results.len(), 2);
+        assert_eq!(results.pages_fetched, 1);
+        assert!(results.exhausted);
+        assert!(results.error.is_none());
+
+        let urls = fetcher
+            .requests()
+            .into_iter()
+            .map(|this| this.url)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                "http://search.test/search?q=rust&page=0",
+                "http://search.test/search?q=rust&page=1"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn keeps_earlier_results_when_a_later_page_is_blocked() {
+        let fetcher = FakeFetcher::default()
+            .with_body("https://a.test A\nhttps://b.test B")
+            .with_error(EngineError::RateLimited);
+
+        let results = fetch_pages(
+            &TestEngine::default(),
+            &fetcher,
+            "rust",
+            &options(),
+            PageLimit::Pages(3),
+        )
+        .await;
+
+        assert_eq!(results.results.len(), 2);
+        assert!(!results.exhausted);
+        assert!(matches!(results.error, Some(EngineError::RateLimited)));
+    }
+
+    #[tokio::test]
+    async fn reports_later_pages_which_could_not_be_parsed() {
+        let fetcher = FakeFetcher::default()
+            .with_body("https://a.test A\nhttps://b.test B")
+            .with_body("<html><body>Unusual traffic</body></html>");
+
+        let results = fetch_pages(
+            &TestEngine::default(),
+            &fetcher,
+            "rust",
+            &options(),
+            PageLimit::Pages(3),
+        )
+        .await;
+
+        assert_eq!(results.results.len(), 2);
+        assert_eq!(results.pages_fetched, 1);
+        assert!(!results.exhausted);
+        match results.error {
+            Some(EngineError::ParseFailed(diagnostics)) => {
+                assert_eq!(diagnostics.engine, "test");
+                assert_eq!(diagnostics.excerpt, "Unusual traffic");
+            }
+            other => panic!("expected a parse error, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_a_first_page_which_could_not_be_parsed() {
+        let fetcher = FakeFetcher::default().with_body("<html><body>Unusual traffic</body></html>");
+
+        let results = fetch_pages(
+            &TestEngine::default(),
+            &fetcher,
+            "rust",
+            &options(),
+            PageLimit::Pages(3),
+        )
+        .await;
+
+        assert!(results.results.is_empty());
+        assert_eq!(results.pages_fetched, 0);
+        assert!(!results.exhausted);
+        assert!(matches!(results.error, Some(EngineError::ParseFailed(_))));
+    }
+
+    #[tokio::test]
+    async fn reports_a_first_page_without_results() {
+        let fetcher = FakeFetcher::default().with_body("");
+
+        let results = fetch_pages(
+            &TestEngine::default(),
+            &fetcher,
+            "rust",
+            &options(),
+            PageLimit::Pages(3),
+        )
+        .await;
+
+        assert!(results.results.is_empty());
+        assert!(matches!(
+            results.error,
+            Some(EngineError::Search(SearchError::NoResultsFound))
+        ));
+    }
+
+    #[tokio::test]
+    async fn only_counts_organic_results_towards_the_limit() {
+        let fetcher = FakeFetcher::default()
+            .with_body("https://a.test A\nad https://ads.test Ad\nhttps://b.test B")
+            .with_body("https://c.test C\nhttps://d.test D");
+
+        let results = fetch_pages(
+            &TestEngine {
+                config: EngineConfig {
+                    ads: AdHandling::Label,
+                    ..EngineConfig::default()
+                },
+            },
+            &fetcher,
+            "rust",
+            &options(),
+            PageLimit::Results(3),
+        )
+        .await;
+
+        let urls = results
+            .results
+            .iter()
+            .map(|this| (this.url.as_str(), this.sponsored))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                ("https://a.test", false),
+                ("https://ads.test", true),
+                ("https://b.test", false),
+                ("https://c.test", false),
+            ]
+        );
+        assert_eq!(results.pages_fetched, 2);
+    }
+}
@@ -0,0 +1,378 @@
+//! Header sets which match a single realistic browser, so the `User-Agent`, `Accept-*`,
+//! `Sec-Fetch-*` and client hint headers of a request never contradict each other.
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+};
+
+use http::{
+    HeaderMap, HeaderName, HeaderValue, Uri,
+    header::{
+        ACCEPT, ACCEPT_ENCODING, ACCEPT_LANGUAGE, REFERER, UPGRADE_INSECURE_REQUESTS, USER_AGENT,
+    },
+};
+
+/// A browser whose headers are sent with requests.
+///
+/// The profiles advertise the compression their browser supports, so the [`Fetcher`](crate::fetch::Fetcher)
+/// needs to decompress responses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrowserProfile {
+    /// Chrome on Windows.
+    Chrome,
+    /// Firefox on Windows.
+    Firefox,
+    /// Safari on macOS.
+    Safari,
+    /// The Lynx text browser, which doesn't support javascript.
+    Lynx,
+    /// The w3m text browser, which doesn't support javascript.
+    W3m,
+}
+
+impl BrowserProfile {
+    /// The profiles for browsers which support javascript.
+    pub const JS: [Self; 3] = [Self::Chrome, Self::Firefox, Self::Safari];
+    /// The profiles for browsers which don't support javascript, which some providers serve
+    /// simpler (and easier to parse) pages to.
+    pub const NO_JS: [Self; 2] = [Self::Lynx, Self::W3m];
+
+    /// Picks a random profile for a browser which supports javascript.
+    pub fn random() -> Self {
+        pick_random(&Self::JS)
+    }
+
+    /// Picks a random profile for a browser which doesn't support javascript.
+    pub fn random_no_js() -> Self {
+        pick_random(&Self::NO_JS)
+    }
+
+    /// Whether the browser supports javascript.
+    pub fn supports_js(&self) -> bool {
+        Self::JS.contains(self)
+    }
+
+    /// The `User-Agent` the browser sends.
+    pub fn user_agent(&self) -> &'static str {
+        self.headers().user_agent
+    }
+
+    /// Adds the browser's headers for navigating to the page at `url`, replacing any which were
+    /// already set. `referer` is the page the navigation came from, if any.
+    pub fn apply(&self, headers: &mut HeaderMap, url: &str, referer: Option<&'static str>) {
+        let profile_headers = self.headers();
+
+        headers.insert(
+            USER_AGENT,
+            HeaderValue::from_static(profile_headers.user_agent),
+        );
+        headers.insert(ACCEPT, HeaderValue::from_static(profile_headers.accept));
+        headers.insert(
+            ACCEPT_LANGUAGE,
+            HeaderValue::from_static(profile_headers.accept_language),
+        );
+        headers.insert(
+            ACCEPT_ENCODING,
+            HeaderValue::from_static(ACCEPT_ENCODING_VALUE),
+        );
+
+        for &(name, value) in profile_headers.client_hints {
+            headers.insert(
+                HeaderName::from_static(name),
+                HeaderValue::from_static(value),
+            );
+        }
+
+        if profile_headers.sends_fetch_metadata {
+            let fetch_metadata = [
+                ("sec-fetch-dest", "document"),
+                ("sec-fetch-mode", "navigate"),
+                ("sec-fetch-site", fetch_site(url, referer)),
+                ("sec-fetch-user", "?1"),
+            ];
+
+            for (name, value) in fetch_metadata {
+                headers.insert(
+                    HeaderName::from_static(name),
+                    HeaderValue::from_static(value),
+                );
+            }
+        }
+
+        if profile_headers.upgrade_insecure_requests {
+            headers.insert(UPGRADE_INSECURE_REQUESTS, HeaderValue::from_static("1"));
+        }
+
+        if let Some(referer) = referer {
+            headers.insert(REFERER, HeaderValue::from_static(referer));
+        }
+    }
+
+    fn headers(&self) -> &'static ProfileHeaders {
+        match self {
+            Self::Chrome => &CHROME,
+            Self::Firefox => &FIREFOX,
+            Self::Safari => &SAFARI,
+            Self::Lynx => &LYNX,
+            Self::W3m => &W3M,
+        }
+    }
+}
+
+struct ProfileHeaders {
+    user_agent: &'static str,
+    accept: &'static str,
+    accept_language: &'static str,
+    /// The `sec-ch-ua` headers, which only Chromium based browsers send.
+    client_hints: &'static [(&'static str, &'static str)],
+    /// Whether the browser sends the `Sec-Fetch-*` headers.
+    sends_fetch_metadata: bool,
+    upgrade_insecure_requests: bool,
+}
+
+/// The `Sec-Fetch-Site` of a navigation to `url` from `referer`: `none` without a referer,
+/// otherwise `same-origin`, `same-site` or `cross-site`.
+fn fetch_site(url: &str, referer: Option<&str>) -> &'static str {
+    let Some(referer) = referer else {
+        return "none";
+    };
+
+    match (url.parse::<Uri>(), referer.parse::<Uri>()) {
+        (Ok(url), Ok(referer))
+            if url.scheme() == referer.scheme() && url.authority() == referer.authority() =>
+        {
+            "same-origin"
+        }
+        (Ok(url), Ok(referer))
+            if url.scheme() == referer.scheme()
+                && url.host().map(site) == referer.host().map(site) =>
+        {
+            "same-site"
+        }
+        _ => "cross-site",
+    }
+}
+
+/// The site of `host`, taken as its last two labels (e.g. `google.com` for `www.google.com`).
+/// This doesn't know about public suffixes like `co.uk`, which none of the engines' providers use.
+fn site(host: &str) -> &str {
+    if host.parse::<std::net::IpAddr>().is_ok() || host.starts_with('[') {
+        return host;
+    }
+
+    host.rmatch_indices('.')
+        .nth(1)
+        .map_or(host, |(idx, _)| &host[idx + 1..])
+}
+
+// Uses the random keys of a new `RandomState`, to avoid depending on a random number generator.
+fn pick_random<T: Copy>(items: &[T]) -> T {
+    let random = RandomState::new().build_hasher().finish();
+    items[random as usize % items.len()]
+}
+
+// Browsers also advertise `br` and `zstd`, but the http client engines are used with may not
+// decode them, so every profile only advertises the encodings any client can decode.
+const ACCEPT_ENCODING_VALUE: &str = "gzip, deflate";
+
+const CHROME: ProfileHeaders = ProfileHeaders {
+    user_agent: "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/131.0.0.0 Safari/537.36",
+    accept: "text/html,application/xhtml+xml,application/xml;q=0.9,image/avif,image/webp,image/apng,*/*;q=0.8,application/signed-exchange;v=b3;q=0.7",
+    accept_language: "en-US,en;q=0.9",
+    client_hints: &[
+        (
+            "sec-ch-ua",
+            "\"Google Chrome\";v=\"131\", \"Chromium\";v=\"131\", \"Not_A Brand\";v=\"24\"",
+        ),
+        ("sec-ch-ua-mobile", "?0"),
+        ("sec-ch-ua-platform", "\"Windows\""),
+    ],
+    sends_fetch_metadata: true,
+    upgrade_insecure_requests: true,
+};
+
+const FIREFOX: ProfileHeaders = ProfileHeaders {
+    user_agent: "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:133.0) Gecko/20100101 Firefox/133.0",
+    accept: "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8",
+    accept_language: "en-US,en;q=0.5",
+    client_hints: &[],
+    sends_fetch_metadata: true,
+    upgrade_insecure_requests: true,
+};
+
+const SAFARI: ProfileHeaders = ProfileHeaders {
+    user_agent: "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/18.1 Safari/605.1.15",
+    accept: "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8",
+    accept_language: "en-US,en;q=0.9",
+    client_hints: &[],
+    sends_fetch_metadata: true,
+    upgrade_insecure_requests: false,
+};
+
+const LYNX: ProfileHeaders = ProfileHeaders {
+    user_agent: "Lynx/2.9.0dev.12 libwww-FM/2.14 SSL-MM/1.4.1 GNUTLS/3.7.9",
+    accept: "text/html, text/plain, text/sgml, text/css, */*;q=0.01",
+    accept_language: "en",
+    client_hints: &[],
+    sends_fetch_metadata: false,
+    upgrade_insecure_requests: false,
+};
+
+const W3M: ProfileHeaders = ProfileHeaders {
+    user_agent: "w3m/0.5.3+git20230121",
+    accept: "text/html, text/*;q=0.5, image/*",
+    accept_language: "en;q=1.0",
+    client_hints: &[],
+    sends_fetch_metadata: false,
+    upgrade_insecure_requests: false,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(profile: BrowserProfile, referer: Option<&'static str>) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        profile.apply(&mut headers, "https://www.google.com", referer);
+        headers
+    }
+
+    fn header(headers: &HeaderMap, name: &str) -> Option<String> {
+        headers
+            .get(name)
+            .map(|this| this.to_str().unwrap().to_string())
+    }
+
+    #[test]
+    fn sends_chromes_client_hints_and_fetch_metadata() {
+        let headers = headers(BrowserProfile::Chrome, None);
+
+        assert!(
+            header(&headers, "user-agent")
+                .unwrap()
+                .contains("Chrome/131")
+        );
+        assert_eq!(
+            header(&headers, "sec-ch-ua-platform").as_deref(),
+            Some("\"Windows\"")
+        );
+        assert_eq!(
+            header(&headers, "sec-fetch-mode").as_deref(),
+            Some("navigate")
+        );
+        assert_eq!(header(&headers, "sec-fetch-site").as_deref(), Some("none"));
+        assert_eq!(
+            header(&headers, "upgrade-insecure-requests").as_deref(),
+            Some("1")
+        );
+        assert_eq!(header(&headers, "referer"), None);
+    }
+
+    #[test]
+    fn sends_firefoxs_fetch_metadata_without_client_hints() {
+        let headers = headers(BrowserProfile::Firefox, None);
+
+        assert!(
+            header(&headers, "user-agent")
+                .unwrap()
+                .contains("Firefox/133")
+        );
+        assert_eq!(
+            header(&headers, "accept-language").as_deref(),
+            Some("en-US,en;q=0.5")
+        );
+        assert_eq!(header(&headers, "sec-ch-ua"), None);
+        assert_eq!(
+            header(&headers, "sec-fetch-dest").as_deref(),
+            Some("document")
+        );
+        assert_eq!(
+            header(&headers, "upgrade-insecure-requests").as_deref(),
+            Some("1")
+        );
+    }
+
+    #[test]
+    fn sends_safaris_headers_without_upgrade_insecure_requests() {
+        let headers = headers(BrowserProfile::Safari, None);
+
+        assert!(
+            header(&headers, "user-agent")
+                .unwrap()
+                .contains("Safari/605")
+        );
+        assert_eq!(header(&headers, "sec-ch-ua"), None);
+        assert_eq!(header(&headers, "sec-fetch-user").as_deref(), Some("?1"));
+        assert_eq!(header(&headers, "upgrade-insecure-requests"), None);
+    }
+
+    #[test]
+    fn sends_text_browser_headers_without_fetch_metadata() {
+        for profile in BrowserProfile::NO_JS {
+            let headers = headers(profile, Some("https://google.com/"));
+
+            assert_eq!(
+                header(&headers, "user-agent").as_deref(),
+                Some(profile.user_agent())
+            );
+            assert_eq!(header(&headers, "sec-fetch-site"), None);
+            assert_eq!(header(&headers, "sec-ch-ua"), None);
+            assert_eq!(
+                header(&headers, "referer").as_deref(),
+                Some("https://google.com/")
+            );
+        }
+    }
+
+    #[test]
+    fn only_advertises_gzip_and_deflate() {
+        for profile in BrowserProfile::JS.into_iter().chain(BrowserProfile::NO_JS) {
+            assert_eq!(
+                header(&headers(profile, None), "accept-encoding").as_deref(),
+                Some("gzip, deflate"),
+                "{profile:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn replaces_headers_which_were_already_set() {
+        let mut headers = HeaderMap::new();
+        headers.insert(USER_AGENT, HeaderValue::from_static("curl/8.0"));
+
+        BrowserProfile::Lynx.apply(&mut headers, "https://www.bing.com", None);
+
+        assert_eq!(headers.get_all(USER_AGENT).iter().count(), 1);
+        assert_eq!(
+            header(&headers, "user-agent").as_deref(),
+            Some(BrowserProfile::Lynx.user_agent())
+        );
+    }
+
+    #[test]
+    fn compares_the_referer_with_the_page() {
+        let fetch_site = |referer| {
+            header(
+                &headers(BrowserProfile::Chrome, Some(referer)),
+                "sec-fetch-site",
+            )
+            .unwrap()
+        };
+
+        assert_eq!(fetch_site("https://www.google.com/"), "same-origin");
+        assert_eq!(fetch_site("https://google.com/"), "same-site");
+        assert_eq!(fetch_site("https://news.google.com/home"), "same-site");
+        assert_eq!(fetch_site("http://www.google.com/"), "cross-site");
+        assert_eq!(fetch_site("https://www.bing.com/"), "cross-site");
+        assert_eq!(fetch_site("https://google.com.example/"), "cross-site");
+    }
+
+    #[test]
+    fn uses_the_last_two_labels_as_the_site() {
+        assert_eq!(site("www.google.com"), "google.com");
+        assert_eq!(site("google.com"), "google.com");
+        assert_eq!(site("localhost"), "localhost");
+        assert_eq!(site("127.0.0.1"), "127.0.0.1");
+    }
+}
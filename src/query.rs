@@ -0,0 +1,533 @@
+//! Parsed search queries, so operators like `site:` and `filetype:` behave the same on every engine.
+
+use crate::results::RichSearchResult;
+
+/// A search query with its operators parsed out.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParsedQuery {
+    /// Plain search terms.
+    pub terms: Vec<String>,
+    /// Phrases which must appear exactly (`"borrow checker"`).
+    pub phrases: Vec<String>,
+    /// Terms or phrases which results must not contain (`-java`).
+    pub negated_terms: Vec<String>,
+    /// Sites to restrict the results to (`site:docs.rs`).
+    pub sites: Vec<String>,
+    /// Sites to remove from the results (`-site:pinterest.com`).
+    pub excluded_sites: Vec<String>,
+    /// The file type results must have (`filetype:pdf`).
+    pub filetype: Option<String>,
+    /// Terms which must appear in result titles (`intitle:tutorial`).
+    pub title_terms: Vec<String>,
+    /// Groups of terms where only one needs to match (`rust OR golang`).
+    pub alternatives: Vec<Vec<String>>,
+}
+
+/// A query operator, used to report which operators an engine couldn't honour.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operator {
+    /// `site:`
+    Site,
+    /// `-site:`
+    ExcludedSite,
+    /// `"phrase"`
+    Phrase,
+    /// `-term`
+    Negation,
+    /// `filetype:`
+    Filetype,
+    /// `intitle:`
+    InTitle,
+    /// `OR`
+    Or,
+}
+
+/// How an engine's provider writes each operator. Operators set to `None` (or `false`) aren't
+/// supported by the provider.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuerySyntax {
+    /// The prefix used to restrict results to a site.
+    pub site: Option<&'static str>,
+    /// The prefix used to exclude a site.
+    pub excluded_site: Option<&'static str>,
+    /// Whether exact phrases can be quoted.
+    pub phrase: bool,
+    /// Whether terms can be negated with `-`.
+    pub negation: bool,
+    /// The prefix used to restrict the file type.
+    pub filetype: Option<&'static str>,
+    /// The prefix used to restrict terms to the title.
+    pub title: Option<&'static str>,
+    /// The keyword placed between alternatives.
+    pub or: Option<&'static str>,
+}
+
+impl QuerySyntax {
+    /// The syntax shared by most providers (Google, Bing, Brave and Yahoo).
+    pub const COMMON: Self = Self {
+        site: Some("site:"),
+        excluded_site: Some("-site:"),
+        phrase: true,
+        negation: true,
+        filetype: Some("filetype:"),
+        title: Some("intitle:"),
+        or: Some("OR"),
+    };
+}
+
+/// A query rendered into an engine's syntax.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RenderedQuery {
+    /// The query to send to the engine.
+    pub query: String,
+    /// Operators the engine doesn't support, which are emulated by filtering its results.
+    pub emulated: Vec<Operator>,
+    /// Operators the engine doesn't support and which can't be emulated.
+    pub unsupported: Vec<Operator>,
+}
+
+impl ParsedQuery {
+    /// Parses a query written with the common operator syntax.
+    pub fn parse(query: &str) -> Self {
+        let mut parsed_query = Self::default();
+        let mut tokens = tokenize(query).into_iter().peekable();
+
+        while let Some(token) = tokens.next() {
+            if is_or(&token) {
+                continue;
+            }
+
+            // Collects `a OR b OR c` into a single group.
+            if tokens.peek().is_some_and(|this| is_or(this)) && !is_operator(&token) {
+                let mut alternatives = vec![unquote(&token).to_string()];
+
+                while tokens.next_if(|this| is_or(this)).is_some() {
+                    if let Some(token) = tokens.next() {
+                        alternatives.push(unquote(&token).to_string());
+                    }
+                }
+
+                parsed_query.alternatives.push(alternatives);
+                continue;
+            }
+
+            parsed_query.push_token(&token);
+        }
+
+        parsed_query
+    }
+
+    fn push_token(&mut self, token: &str) {
+        if let Some(site) = token.strip_prefix("-site:") {
+            self.excluded_sites.push(site.to_lowercase());
+        } else if let Some(site) = token.strip_prefix("site:") {
+            self.sites.push(site.to_lowercase());
+        } else if let Some(filetype) = token
+            .strip_prefix("filetype:")
+            .or_else(|| token.strip_prefix("ext:"))
+        {
+            self.filetype = Some(filetype.trim_start_matches('.').to_lowercase());
+        } else if let Some(term) = token.strip_prefix("intitle:") {
+            self.title_terms.push(unquote(term).to_string());
+        } else if let Some(term) = token.strip_prefix('-').filter(|this| !this.is_empty()) {
+            self.negated_terms.push(unquote(term).to_string());
+        } else if token.len() > 1 && token.starts_with('"') {
+            self.phrases.push(unquote(token).to_string());
+        } else {
+            self.terms.push(token.to_string());
+        }
+    }
+
+    /// Renders the query into an engine's syntax, noting which operators have to be emulated
+    /// and which can't be honoured at all.
+    pub fn render(&self, syntax: &QuerySyntax) -> RenderedQuery {
+        let mut parts = self.terms.clone();
+        let mut emulated = Vec::new();
+        let mut unsupported = Vec::new();
+
+        for phrase in &self.phrases {
+            if syntax.phrase {
+                parts.push(format!("\"{phrase}\""));
+            } else {
+                parts.push(phrase.clone());
+                emulated.push(Operator::Phrase);
+            }
+        }
+
+        for term in &self.negated_terms {
+            if syntax.negation {
+                parts.push(format!("-{}", quote(term)));
+            } else {
+                emulated.push(Operator::Negation);
+            }
+        }
+
+        match (syntax.site, syntax.or, self.sites.as_slice()) {
+            (_, _, []) => {}
+            (Some(prefix), _, [site]) => parts.push(format!("{prefix}{site}")),
+            (Some(prefix), Some(or), sites) => parts.push(format!(
+                "({})",
+                sites
+                    .iter()
+                    .map(|site| format!("{prefix}{site}"))
+                    .collect::<Vec<_>>()
+                    .join(&format!(" {or} "))
+            )),
+            _ => emulated.push(Operator::Site),
+        }
+
+        for site in &self.excluded_sites {
+            match syntax.excluded_site {
+                Some(prefix) => parts.push(format!("{prefix}{site}")),
+                None => emulated.push(Operator::ExcludedSite),
+            }
+        }
+
+        if let Some(filetype) = &self.filetype {
+            match syntax.filetype {
+                Some(prefix) => parts.push(format!("{prefix}{filetype}")),
+                None => emulated.push(Operator::Filetype),
+            }
+        }
+
+        for term in &self.title_terms {
+            match syntax.title {
+                Some(prefix) => parts.push(format!("{prefix}{}", quote(term))),
+                None => {
+                    parts.push(term.clone());
+                    emulated.push(Operator::InTitle);
+                }
+            }
+        }
+
+        for alternatives in &self.alternatives {
+            let alternatives = alternatives.iter().map(|this| quote(this));
+
+            match syntax.or {
+                Some(or) => parts.push(alternatives.collect::<Vec<_>>().join(&format!(" {or} "))),
+                // Without `OR` the best we can do is search for every alternative.
+                None => {
+                    parts.extend(alternatives);
+                    unsupported.push(Operator::Or);
+                }
+            }
+        }
+
+        emulated.dedup();
+        unsupported.dedup();
+
+        RenderedQuery {
+            query: parts.join(" "),
+            emulated,
+            unsupported,
+        }
+    }
+
+    /// Removes the results which don't satisfy the given (emulated) operators.
+    pub fn retain_matching(&self, results: &mut Vec<RichSearchResult>, operators: &[Operator]) {
+        if operators.is_empty() {
+            return;
+        }
+
+        results.retain(|result| {
+            operators
+                .iter()
+                .all(|operator| self.matches(result, *operator))
+        });
+    }
+
+    fn matches(&self, result: &RichSearchResult, operator: Operator) -> bool {
+        let text = format!("{} {}", result.title, result.summary).to_lowercase();
+
+        match operator {
+            Operator::Site => self
+                .sites
+                .iter()
+                .any(|site| url_is_on_site(&result.url, site)),
+            Operator::ExcludedSite => !self
+                .excluded_sites
+                .iter()
+                .any(|site| url_is_on_site(&result.url, site)),
+            Operator::Phrase => self
+                .phrases
+                .iter()
+                .all(|phrase| text.contains(&phrase.to_lowercase())),
+            Operator::Negation => !self
+                .negated_terms
+                .iter()
+                .any(|term| text.contains(&term.to_lowercase())),
+            Operator::Filetype => self.filetype.as_ref().is_none_or(|filetype| {
+                let path = url_path(&result.url).to_lowercase();
+                path.ends_with(&format!(".{filetype}"))
+            }),
+            Operator::InTitle => {
+                let title = result.title.to_lowercase();
+                self.title_terms
+                    .iter()
+                    .all(|term| title.contains(&term.to_lowercase()))
+            }
+            // `OR` can't be emulated, so it never filters anything out.
+            Operator::Or => true,
+        }
+    }
+}
+
+/// Rewrites the operators in a raw query which an engine's provider writes differently, keeping
+/// the order of everything the user typed. Operators the provider doesn't support are left out,
+/// and the query is sent unchanged if none of its operators need rewriting.
+pub(crate) fn render_for_url(query: &str, syntax: &QuerySyntax) -> String {
+    let tokens = tokenize(query);
+    let rendered_tokens = tokens
+        .iter()
+        .map(|this| render_token(this, syntax))
+        .collect::<Vec<_>>();
+
+    if tokens
+        .iter()
+        .zip(&rendered_tokens)
+        .all(|(token, rendered_token)| rendered_token.as_deref() == Some(token.as_str()))
+    {
+        return query.to_string();
+    }
+
+    rendered_tokens
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Renders a single token of a raw query into an engine's syntax, or `None` if it has to be
+/// left out.
+fn render_token(token: &str, syntax: &QuerySyntax) -> Option<String> {
+    let with_prefix =
+        |prefix: Option<&str>, value: &str| prefix.map(|this| format!("{this}{value}"));
+
+    if is_or(token) {
+        // Without `OR` the best we can do is search for every alternative.
+        syntax.or.map(str::to_string)
+    } else if let Some(site) = token.strip_prefix("-site:") {
+        with_prefix(syntax.excluded_site, site)
+    } else if let Some(site) = token.strip_prefix("site:") {
+        with_prefix(syntax.site, site)
+    } else if let Some(filetype) = token
+        .strip_prefix("filetype:")
+        .or_else(|| token.strip_prefix("ext:"))
+    {
+        with_prefix(syntax.filetype, filetype)
+    } else if let Some(term) = token.strip_prefix("intitle:") {
+        with_prefix(syntax.title, term).or_else(|| Some(term.to_string()))
+    } else if token.len() > 1 && token.starts_with('-') {
+        syntax.negation.then(|| token.to_string())
+    } else if token.len() > 1 && token.starts_with('"') && !syntax.phrase {
+        Some(unquote(token).to_string())
+    } else {
+        Some(token.to_string())
+    }
+}
+
+/// Whether a url belongs to a site, where the site may include a path (e.g. `docs.rs/tokio`).
+pub(crate) fn url_is_on_site(url: &str, site: &str) -> bool {
+    let host_and_path = url
+        .split_once("://")
+        .map_or(url, |(_, rest)| rest)
+        .to_lowercase();
+    let host_and_path = host_and_path.strip_prefix("www.").unwrap_or(&host_and_path);
+
+    let site = site.to_lowercase();
+    let site = site.strip_prefix("www.").unwrap_or(&site);
+
+    let (host, path) = host_and_path.split_once('/').unwrap_or((host_and_path, ""));
+    let (site_host, site_path) = site.split_once('/').unwrap_or((site, ""));
+
+    let is_on_host = host == site_host || host.ends_with(&format!(".{site_host}"));
+    is_on_host && path.starts_with(site_path)
+}
+
+fn url_path(url: &str) -> &str {
+    let without_scheme = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let path = without_scheme
+        .find('/')
+        .map_or("", |idx| &without_scheme[idx..]);
+
+    path.split(['?', '#']).next().unwrap_or_default()
+}
+
+/// Splits a query on whitespace, keeping quoted phrases together.
+fn tokenize(query: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut in_quotes = false;
+
+    for char in query.chars() {
+        match char {
+            '"' => {
+                in_quotes = !in_quotes;
+                token.push(char);
+            }
+            char if char.is_whitespace() && !in_quotes => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+            }
+            char => token.push(char),
+        }
+    }
+
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+
+    tokens
+}
+
+fn is_or(token: &str) -> bool {
+    token == "OR" || token == "|"
+}
+
+fn is_operator(token: &str) -> bool {
+    token.starts_with('-') || OPERATOR_PREFIXES.iter().any(|this| token.starts_with(this))
+}
+
+fn unquote(token: &str) -> &str {
+    token.trim_matches('"')
+}
+
+fn quote(term: &str) -> String {
+    if term.contains(char::is_whitespace) {
+        format!("\"{term}\"")
+    } else {
+        term.to_string()
+    }
+}
+
+const OPERATOR_PREFIXES: [&str; 4] = ["site:", "filetype:", "ext:", "intitle:"];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(title: &str, url: &str) -> RichSearchResult {
+        RichSearchResult::new(title.to_string(), url.to_string(), String::new())
+    }
+
+    #[test]
+    fn parses_operators() {
+        let query = ParsedQuery::parse(
+            r#"rust "borrow checker" -java site:docs.rs -site:pinterest.com filetype:.PDF intitle:guide async OR await"#,
+        );
+
+        assert_eq!(
+            query,
+            ParsedQuery {
+                terms: vec!["rust".to_string()],
+                phrases: vec!["borrow checker".to_string()],
+                negated_terms: vec!["java".to_string()],
+                sites: vec!["docs.rs".to_string()],
+                excluded_sites: vec!["pinterest.com".to_string()],
+                filetype: Some("pdf".to_string()),
+                title_terms: vec!["guide".to_string()],
+                alternatives: vec![vec!["async".to_string(), "await".to_string()]],
+            }
+        );
+    }
+
+    #[test]
+    fn renders_supported_operators() {
+        let rendered = ParsedQuery::parse(r#"rust "borrow checker" site:docs.rs site:crates.io"#)
+            .render(&QuerySyntax::COMMON);
+
+        assert_eq!(
+            rendered.query,
+            r#"rust "borrow checker" (site:docs.rs OR site:crates.io)"#
+        );
+        assert!(rendered.emulated.is_empty());
+        assert!(rendered.unsupported.is_empty());
+    }
+
+    #[test]
+    fn reports_operators_which_are_emulated_or_unsupported() {
+        let syntax = QuerySyntax {
+            excluded_site: None,
+            title: None,
+            or: None,
+            ..QuerySyntax::COMMON
+        };
+        let rendered = ParsedQuery::parse("rust -site:pinterest.com intitle:guide async OR await")
+            .render(&syntax);
+
+        assert_eq!(rendered.query, "rust guide async await");
+        assert_eq!(
+            rendered.emulated,
+            [Operator::ExcludedSite, Operator::InTitle]
+        );
+        assert_eq!(rendered.unsupported, [Operator::Or]);
+    }
+
+    #[test]
+    fn filters_results_for_emulated_operators() {
+        let query = ParsedQuery::parse("rust -site:pinterest.com filetype:pdf");
+        let mut results = vec![
+            result("Rust", "https://www.rust-lang.org/learn.pdf"),
+            result("Rust", "https://www.pinterest.com/rust.pdf"),
+            result("Rust", "https://www.rust-lang.org/learn"),
+        ];
+
+        query.retain_matching(&mut results, &[Operator::ExcludedSite, Operator::Filetype]);
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].url, "https://www.rust-lang.org/learn.pdf");
+    }
+
+    #[test]
+    fn sends_queries_without_operators_to_rewrite_unchanged() {
+        let query = r#""new york"  pizza -pineapple site:nyc.com OR site:ny.com"#;
+
+        assert_eq!(render_for_url(query, &QuerySyntax::COMMON), query);
+    }
+
+    #[test]
+    fn keeps_the_order_of_the_query_when_rewriting_operators() {
+        let syntax = QuerySyntax {
+            excluded_site: None,
+            title: None,
+            or: None,
+            ..QuerySyntax::COMMON
+        };
+
+        assert_eq!(
+            render_for_url(
+                r#""new york" pizza OR pasta -site:pinterest.com intitle:menu cheap"#,
+                &syntax
+            ),
+            r#""new york" pizza pasta menu cheap"#
+        );
+    }
+
+    #[test]
+    fn rewrites_operators_with_the_providers_prefixes() {
+        let syntax = QuerySyntax {
+            site: Some("host:"),
+            phrase: false,
+            or: Some("|"),
+            ..QuerySyntax::COMMON
+        };
+
+        assert_eq!(
+            render_for_url(r#""new york" site:nyc.com OR site:ny.com"#, &syntax),
+            "new york host:nyc.com | host:ny.com"
+        );
+    }
+
+    #[test]
+    fn matches_urls_on_sites() {
+        assert!(url_is_on_site("https://docs.rs/tokio/latest", "docs.rs"));
+        assert!(url_is_on_site("https://www.docs.rs/tokio", "docs.rs/tokio"));
+        assert!(url_is_on_site(
+            "https://blog.rust-lang.org/",
+            "rust-lang.org"
+        ));
+        assert!(!url_is_on_site("https://docs.rs/serde", "docs.rs/tokio"));
+        assert!(!url_is_on_site("https://notdocs.rs/", "docs.rs"));
+    }
+}
@@ -0,0 +1,431 @@
+//! Richer search results which carry data that doesn't fit into a plain [`SearchResult`].
+
+use chrono::{DateTime, Utc};
+use quaero_shared::models::{
+    sanitized_url::SanitizedUrl,
+    search::{DateTimeRange, SearchResult},
+};
+
+use crate::{config::AdHandling, health::ParseHealth, query::Operator};
+
+/// The results parsed from a single results page.
+#[derive(Debug, Clone, Default)]
+pub struct ParsedPage {
+    /// The results on the page, in the order they were shown.
+    pub results: Vec<RichSearchResult>,
+    /// The number of sponsored results (ads) which were removed from the page.
+    pub dropped_ads: usize,
+    /// The total number of results the provider claims to have for the query.
+    pub estimated_total: Option<u64>,
+    /// Whether the provider offers another page of results.
+    /// This is `None` if the engine couldn't tell.
+    pub has_next_page: Option<bool>,
+    /// The query operators which the engine couldn't honour for this page.
+    pub unsupported_operators: Vec<Operator>,
+    /// How well the engine's selectors matched the page.
+    pub health: ParseHealth,
+}
+
+impl ParsedPage {
+    /// Creates a new page from its organic results.
+    pub fn new(results: Vec<RichSearchResult>) -> Self {
+        Self {
+            results,
+            dropped_ads: 0,
+            estimated_total: None,
+            has_next_page: None,
+            unsupported_operators: Vec::new(),
+            health: ParseHealth::default(),
+        }
+    }
+
+    /// Adds the sponsored results (ads) found on the page, either labelling or dropping them
+    /// depending on `handling`. Each ad comes with the number of organic results shown before it,
+    /// so labelled ads keep their place on the page. Organic results are left alone, even if an
+    /// ad links to the same page.
+    pub(crate) fn add_ads(
+        &mut self,
+        ads: impl IntoIterator<Item = (usize, RichSearchResult)>,
+        handling: AdHandling,
+    ) {
+        let mut ads = ads.into_iter().collect::<Vec<_>>();
+
+        match handling {
+            AdHandling::Drop => self.dropped_ads += ads.len(),
+            AdHandling::Label => {
+                ads.sort_by_key(|(position, _)| *position);
+
+                // Inserting the last ad first leaves the positions of the earlier ones unchanged.
+                for (position, mut ad) in ads.into_iter().rev() {
+                    ad.sponsored = true;
+                    self.results.insert(position.min(self.results.len()), ad);
+                }
+            }
+        }
+    }
+
+    /// Converts the organic results into the keyed [`SearchResult`]s which quaero expects.
+    /// Sponsored results are left out as [`SearchResult`] has no way to label them.
+    pub fn into_pairs(self) -> Vec<(String, SearchResult)> {
+        self.results
+            .into_iter()
+            .filter(|this| !this.sponsored)
+            .map(RichSearchResult::into_pair)
+            .collect()
+    }
+}
+
+/// A search result along with any extra data an engine was able to extract for it.
+#[derive(Debug, Clone)]
+pub struct RichSearchResult {
+    /// The title of the result.
+    pub title: String,
+    /// The url of the result, as it appeared on the results page.
+    pub url: String,
+    /// The summary of the result.
+    pub summary: String,
+    /// The summary split into plain and highlighted spans, which join back into `summary`.
+    pub summary_spans: Vec<TextSpan>,
+    /// What kind of result this is.
+    pub kind: ResultKind,
+    /// Sub-links which were shown under the result (e.g. docs, pricing and login pages).
+    pub sitelinks: Vec<Sitelink>,
+    /// When the result was published or last updated, if the provider showed it.
+    pub date: Option<DateTime<Utc>>,
+    /// Where the result ranked and which engine and page it came from.
+    /// This is only filled in by [`RichEngine::parse_page`](crate::engine::RichEngine::parse_page).
+    pub provenance: Provenance,
+    /// Whether the result is a sponsored result (an ad).
+    pub sponsored: bool,
+
+    pub(crate) key: String,
+    pub(crate) result: SearchResult,
+}
+
+impl RichSearchResult {
+    /// Creates a new organic result.
+    pub fn new(title: String, url: String, summary: String) -> Self {
+        let (key, result) = SearchResult::new(title.clone(), url.clone(), summary.clone());
+        Self::from_parts(title, url, summary, key, result)
+    }
+
+    /// Creates a new organic result whose url has any query parameters matching `filter` removed.
+    pub fn new_with_url_filter(
+        title: String,
+        url: String,
+        summary: String,
+        filter: fn(&str, &str) -> bool,
+    ) -> Self {
+        let sanitized_url = SanitizedUrl::new(&url, filter);
+        let (key, result) =
+            SearchResult::new_from_sanitized_url(title.clone(), sanitized_url, summary.clone());
+        Self::from_parts(title, url, summary, key, result)
+    }
+
+    /// Creates a result from quaero's keyed [`SearchResult`], for engines which can't parse rich
+    /// results. Only its url is known, which is taken from its key.
+    pub(crate) fn from_pair((key, result): (String, SearchResult)) -> Self {
+        Self::from_parts(String::new(), key.clone(), String::new(), key, result)
+    }
+
+    fn from_parts(
+        title: String,
+        url: String,
+        summary: String,
+        key: String,
+        result: SearchResult,
+    ) -> Self {
+        Self {
+            title,
+            url,
+            summary_spans: TextSpan::plain_spans(&summary),
+            summary,
+            kind: ResultKind::Organic,
+            sitelinks: Vec::new(),
+            date: None,
+            provenance: Provenance::default(),
+            sponsored: false,
+            key,
+            result,
+        }
+    }
+
+    /// Whether the result's date falls within `range`.
+    /// Returns `None` if the result doesn't have a date.
+    pub fn is_within(&self, range: &DateTimeRange) -> Option<bool> {
+        self.date
+            .map(|date| range.start <= date && date <= range.end)
+    }
+
+    /// The key quaero uses to identify the result when merging results from different engines.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Converts the result into the keyed [`SearchResult`] which quaero expects.
+    pub fn into_pair(self) -> (String, SearchResult) {
+        (self.key, self.result)
+    }
+}
+
+/// Where a result came from.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Provenance {
+    /// The id of the engine which produced the result.
+    pub engine: String,
+    /// The page number the result was on.
+    pub page: usize,
+    /// The position of the result on its page, starting from `1`.
+    /// Sponsored results aren't ranked, so keep a rank of `0`.
+    pub rank: usize,
+}
+
+/// A run of summary text which is either plain or highlighted by the provider as matching the query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextSpan {
+    /// The text of the span.
+    pub text: String,
+    /// Whether the provider highlighted the span (e.g. with `<b>`, `<strong>` or `<mark>`).
+    pub highlighted: bool,
+}
+
+impl TextSpan {
+    /// Creates the spans for text which has no highlighting.
+    pub fn plain_spans(text: &str) -> Vec<Self> {
+        if text.is_empty() {
+            return Vec::new();
+        }
+
+        vec![Self {
+            text: text.to_string(),
+            highlighted: false,
+        }]
+    }
+}
+
+/// A sub-link which was shown under a result.
+#[derive(Debug, Clone)]
+pub struct Sitelink {
+    /// The title of the sitelink.
+    pub title: String,
+    /// The url of the sitelink.
+    pub url: SanitizedUrl,
+    /// A short description of the page the sitelink points to.
+    pub description: Option<String>,
+}
+
+impl Sitelink {
+    /// Creates a new sitelink whose url has any query parameters matching `filter` removed.
+    pub fn new(
+        title: String,
+        url: &str,
+        description: Option<String>,
+        filter: fn(&str, &str) -> bool,
+    ) -> Self {
+        Self {
+            title,
+            url: SanitizedUrl::new(url, filter),
+            description,
+        }
+    }
+}
+
+/// Filters out the tracking parameters most providers append to urls.
+pub(crate) fn filter_tracking_params_in_url(key: &str, _value: &str) -> bool {
+    key.starts_with("utm")
+}
+
+/// The number of organic results shown before each ad container, given the result parsed from
+/// each organic container (if any), and whether each container holds ads, in the order they're
+/// shown.
+pub(crate) fn ad_positions(
+    results: &[Option<RichSearchResult>],
+    containers: impl IntoIterator<Item = bool>,
+) -> Vec<usize> {
+    let mut results = results.iter();
+    let mut shown = 0;
+
+    containers
+        .into_iter()
+        .filter_map(|is_ad| {
+            if is_ad {
+                return Some(shown);
+            }
+
+            if results.next().is_some_and(Option::is_some) {
+                shown += 1;
+            }
+
+            None
+        })
+        .collect()
+}
+
+/// The kind of content a [`RichSearchResult`] represents.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResultKind {
+    /// A standard web result.
+    Organic,
+    /// A thread from a forum or discussion site.
+    Discussion(Discussion),
+    /// A frequently asked question, where the title is the question and the summary is the answer.
+    Faq,
+    /// A product listing.
+    Product(Product),
+    /// A recipe.
+    Recipe(Recipe),
+}
+
+/// Extra data for a discussion result.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Discussion {
+    /// The name of the forum the discussion was posted in (e.g. `r/rust`).
+    pub forum: Option<String>,
+    /// The number of replies to the discussion.
+    pub replies: Option<u64>,
+    /// The number of votes the discussion received.
+    pub votes: Option<u64>,
+}
+
+/// Extra data for a product result.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Product {
+    /// The price of the product, including its currency symbol.
+    pub price: Option<String>,
+    /// The average rating of the product, usually out of 5.
+    pub rating: Option<f32>,
+    /// The number of reviews the product has.
+    pub review_count: Option<u64>,
+    /// The store selling the product.
+    pub merchant: Option<String>,
+}
+
+/// Extra data for a recipe result.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Recipe {
+    /// The average rating of the recipe, usually out of 5.
+    pub rating: Option<f32>,
+    /// The number of reviews the recipe has.
+    pub review_count: Option<u64>,
+    /// How long the recipe takes to make (e.g. `45 min`).
+    pub total_time: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(title: &str, url: &str) -> RichSearchResult {
+        RichSearchResult::new(title.to_string(), url.to_string(), String::new())
+    }
+
+    fn titles(page: &ParsedPage) -> Vec<&str> {
+        page.results
+            .iter()
+            .map(|this| this.title.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn drops_ads() {
+        let mut page = ParsedPage::new(vec![result("Rust", "https://rust-lang.org")]);
+        page.add_ads(
+            [
+                (0, result("Ad", "https://ads.test")),
+                (1, result("Another ad", "https://more-ads.test")),
+            ],
+            AdHandling::Drop,
+        );
+
+        assert_eq!(titles(&page), ["Rust"]);
+        assert_eq!(page.dropped_ads, 2);
+    }
+
+    #[test]
+    fn labels_ads() {
+        let mut page = ParsedPage::new(vec![result("Rust", "https://rust-lang.org")]);
+        page.add_ads([(1, result("Ad", "https://ads.test"))], AdHandling::Label);
+
+        assert_eq!(titles(&page), ["Rust", "Ad"]);
+        assert!(!page.results[0].sponsored);
+        assert!(page.results[1].sponsored);
+        assert_eq!(page.dropped_ads, 0);
+    }
+
+    #[test]
+    fn keeps_organic_results_which_share_a_url_with_an_ad() {
+        for handling in [AdHandling::Drop, AdHandling::Label] {
+            let mut page = ParsedPage::new(vec![
+                result("Rust", "https://rust-lang.org"),
+                result("Docs", "https://doc.rust-lang.org"),
+            ]);
+            page.add_ads(
+                [(0, result("Learn Rust", "https://rust-lang.org"))],
+                handling,
+            );
+
+            let organic = page
+                .results
+                .iter()
+                .filter(|this| !this.sponsored)
+                .map(|this| this.title.as_str())
+                .collect::<Vec<_>>();
+            assert_eq!(organic, ["Rust", "Docs"], "{handling:?}");
+        }
+    }
+
+    #[test]
+    fn leaves_ads_out_of_quaero_results() {
+        let mut page = ParsedPage::new(vec![result("Rust", "https://rust-lang.org")]);
+        page.add_ads([(1, result("Ad", "https://ads.test"))], AdHandling::Label);
+
+        let pairs = page.into_pairs();
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(pairs[0].0, "https://rust-lang.org");
+    }
+
+    #[test]
+    fn keeps_labelled_ads_where_they_were_shown() {
+        let mut page = ParsedPage::new(vec![
+            result("Rust", "https://rust-lang.org"),
+            result("Docs", "https://doc.rust-lang.org"),
+        ]);
+        page.add_ads(
+            [
+                (2, result("Bottom ad", "https://bottom.test")),
+                (0, result("Top ad", "https://top.test")),
+                (1, result("Middle ad", "https://middle.test")),
+                (0, result("Second top ad", "https://top-2.test")),
+                (5, result("Past the end", "https://end.test")),
+            ],
+            AdHandling::Label,
+        );
+
+        assert_eq!(
+            titles(&page),
+            [
+                "Top ad",
+                "Second top ad",
+                "Rust",
+                "Middle ad",
+                "Docs",
+                "Bottom ad",
+                "Past the end"
+            ]
+        );
+    }
+
+    #[test]
+    fn counts_the_organic_results_shown_before_each_ad() {
+        let results = [
+            Some(result("Rust", "https://rust-lang.org")),
+            None,
+            Some(result("Docs", "https://doc.rust-lang.org")),
+        ];
+
+        // An ad, a result, a container which couldn't be parsed, two ads, a result, an ad.
+        let containers = [true, false, false, true, true, false, true];
+
+        assert_eq!(ad_positions(&results, containers), [0, 1, 1, 2]);
+    }
+}
@@ -0,0 +1,150 @@
+//! Helpers for turning provider summary markup into [`TextSpan`]s.
+
+use crate::results::TextSpan;
+
+/// Builds text spans from the children of a summary node, given each child's tag name (or `None`
+/// for text nodes) and text. Children wrapped in highlighting tags become highlighted spans.
+pub(crate) fn collect_spans<Tag, Text>(
+    children: impl IntoIterator<Item = (Option<Tag>, Option<Text>)>,
+) -> Vec<TextSpan>
+where
+    Tag: AsRef<str>,
+    Text: AsRef<str>,
+{
+    let mut spans: Vec<TextSpan> = Vec::new();
+
+    for (tag, text) in children {
+        let Some(text) = text else {
+            continue;
+        };
+        let text = text.as_ref();
+        if text.is_empty() {
+            continue;
+        }
+
+        let highlighted = tag.is_some_and(|tag| {
+            HIGHLIGHT_TAGS
+                .iter()
+                .any(|this| tag.as_ref().eq_ignore_ascii_case(this))
+        });
+
+        // Merges neighbouring spans of the same kind (e.g. text split by a `<span>`).
+        match spans.last_mut() {
+            Some(last) if last.highlighted == highlighted => last.text.push_str(text),
+            _ => spans.push(TextSpan {
+                text: text.to_string(),
+                highlighted,
+            }),
+        }
+    }
+
+    spans
+}
+
+/// Trims spans so that their text is exactly `summary`, which may have had prefixes (e.g. dates)
+/// stripped from it. Falls back to a single plain span if the spans don't contain the summary.
+pub(crate) fn align_spans(spans: Vec<TextSpan>, summary: &str) -> Vec<TextSpan> {
+    let text = spans
+        .iter()
+        .map(|this| this.text.as_str())
+        .collect::<String>();
+
+    let Some(start_idx) = text.find(summary) else {
+        return TextSpan::plain_spans(summary);
+    };
+    let end_idx = start_idx + summary.len();
+
+    let mut span_start_idx = 0;
+    spans
+        .into_iter()
+        .filter_map(|this| {
+            let span_end_idx = span_start_idx + this.text.len();
+
+            let trim_start = start_idx.clamp(span_start_idx, span_end_idx) - span_start_idx;
+            let trim_end = end_idx.clamp(span_start_idx, span_end_idx) - span_start_idx;
+            span_start_idx = span_end_idx;
+
+            if trim_start == trim_end {
+                return None;
+            }
+
+            Some(TextSpan {
+                text: this.text[trim_start..trim_end].to_string(),
+                highlighted: this.highlighted,
+            })
+        })
+        .collect()
+}
+
+const HIGHLIGHT_TAGS: [&str; 4] = ["b", "strong", "mark", "em"];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(text: &str, highlighted: bool) -> TextSpan {
+        TextSpan {
+            text: text.to_string(),
+            highlighted,
+        }
+    }
+
+    #[test]
+    fn highlights_text_in_highlighting_tags() {
+        let spans = collect_spans([
+            (None, Some("Learn ")),
+            (Some("STRONG"), Some("Rust")),
+            (Some("span"), Some(" today")),
+        ]);
+
+        assert_eq!(
+            spans,
+            [
+                span("Learn ", false),
+                span("Rust", true),
+                span(" today", false)
+            ]
+        );
+    }
+
+    #[test]
+    fn merges_neighbouring_spans_of_the_same_kind() {
+        let spans = collect_spans([
+            (None, Some("The ")),
+            (Some("span"), Some("Rust")),
+            (Some("b"), Some("")),
+            (Some("em"), None),
+            (None::<&str>, Some(" book")),
+        ]);
+
+        assert_eq!(spans, [span("The Rust book", false)]);
+    }
+
+    #[test]
+    fn trims_spans_to_the_summary() {
+        let spans = vec![
+            span("3 days ago — The ", false),
+            span("Rust", true),
+            span(" book", false),
+        ];
+
+        assert_eq!(
+            align_spans(spans, "The Rust book"),
+            [
+                span("The ", false),
+                span("Rust", true),
+                span(" book", false)
+            ]
+        );
+    }
+
+    #[test]
+    fn falls_back_to_a_plain_span_for_a_summary_the_spans_do_not_contain() {
+        let spans = vec![span("Rust", true)];
+
+        assert_eq!(
+            align_spans(spans, "The Rust book"),
+            [span("The Rust book", false)]
+        );
+    }
+}
@@ -0,0 +1,420 @@
+//! Helpers shared by the unit tests: an engine with a trivial page format, a fetcher which
+//! answers requests with canned responses, and a logger which captures what's logged.
+
+use std::{
+    collections::VecDeque,
+    fs,
+    sync::{
+        Arc, Mutex, Once,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use anyhttp::Response;
+use http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
+use log::{LevelFilter, Log, Metadata, Record};
+use quaero_shared::models::{
+    engine::Engine,
+    search::{SafeSearch, SearchError, SearchOptions, SearchResult},
+};
+
+use crate::{
+    config::EngineConfig,
+    engine::{RichEngine, parse_for_quaero},
+    error::{EngineError, ParseDiagnostics, ParseError},
+    fetch::{FetchRequest, FetchedResponse, Fetcher},
+    overrides::SelectorOverrides,
+    results::{ParsedPage, RichSearchResult},
+};
+
+/// Search options for the first page, without safe search or a date range.
+pub(crate) fn options() -> SearchOptions {
+    SearchOptions {
+        page_num: 0,
+        safe_search: SafeSearch::Off,
+        date_time_range: None,
+    }
+}
+
+/// A response from `url` with `headers`.
+pub(crate) fn response(url: &str, headers: &[(&str, &str)]) -> Response {
+    let headers = headers
+        .iter()
+        .map(|(name, value)| {
+            (
+                HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                HeaderValue::from_str(value).unwrap(),
+            )
+        })
+        .collect::<HeaderMap>();
+
+    Response::new(StatusCode::OK, url.parse().unwrap(), headers)
+}
+
+/// Selector overrides read from a file with `contents`.
+pub(crate) fn selector_overrides(contents: &str) -> Arc<SelectorOverrides> {
+    static FILES: AtomicUsize = AtomicUsize::new(0);
+
+    let path = std::env::temp_dir().join(format!(
+        "quaero_engines_overrides_{}_{}.txt",
+        std::process::id(),
+        FILES.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::write(&path, contents).unwrap();
+
+    let overrides = SelectorOverrides::load(&path).unwrap();
+    let _ = fs::remove_file(path);
+
+    Arc::new(overrides)
+}
+
+/// An engine whose results pages have a result on each line, written as `<url> <title>`, or
+/// `ad <url> <title>` for an ad. A page without any lines has no results, and a page starting
+/// with `<html>` is treated as having an unexpected layout.
+#[derive(Default)]
+pub(crate) struct TestEngine {
+    pub(crate) config: EngineConfig,
+}
+
+#[async_trait::async_trait]
+impl Engine for TestEngine {
+    fn homepage(&self) -> &'static str {
+        "http://search.test/"
+    }
+
+    fn url(&self, query: &str, options: &SearchOptions) -> Result<String, SearchError> {
+        Ok(format!(
+            "http://search.test/search?q={query}&page={}",
+            options.page_num
+        ))
+    }
+
+    fn validate_response(&self, response: &Response) -> Result<(), SearchError> {
+        self.config.store_cookies(response);
+
+        if response.url().path() == "/captcha" {
+            Err(SearchError::Captcha)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn parse<'a>(&self, response_text: String) -> Result<Vec<(String, SearchResult)>, SearchError> {
+        parse_for_quaero(self, response_text)
+    }
+}
+
+impl RichEngine for TestEngine {
+    fn id(&self) -> &str {
+        "test"
+    }
+
+    fn config(&self) -> &EngineConfig {
+        &self.config
+    }
+
+    fn results_per_page(&self) -> usize {
+        2
+    }
+
+    fn parse_rich(&self, response_text: String) -> Result<ParsedPage, ParseError> {
+        if response_text.starts_with("<html>") {
+            return Err(ParseError::SelectorMissing(ParseDiagnostics::new(
+                self.id(),
+                ".results",
+                &response_text,
+            )));
+        }
+
+        let mut results = Vec::new();
+        let mut ads = Vec::new();
+
+        for line in response_text.lines() {
+            let (is_ad, line) = match line.strip_prefix("ad ") {
+                Some(line) => (true, line),
+                None => (false, line),
+            };
+            let Some((url, title)) = line.split_once(' ') else {
+                continue;
+            };
+
+            let result = RichSearchResult::new(title.to_string(), url.to_string(), String::new());
+            if is_ad {
+                ads.push((results.len(), result));
+            } else {
+                results.push(result);
+            }
+        }
+        if results.is_empty() && ads.is_empty() {
+            return Err(SearchError::NoResultsFound.into());
+        }
+
+        let mut page = ParsedPage::new(results);
+        page.add_ads(ads, self.config.ads);
+
+        Ok(page)
+    }
+}
+
+/// A fetcher which answers each request with the next of its canned replies, and records the
+/// requests and sleeps it was asked for.
+#[derive(Default)]
+pub(crate) struct FakeFetcher {
+    replies: Mutex<VecDeque<Result<Reply, EngineError>>>,
+    requests: Mutex<Vec<FetchRequest>>,
+    sleeps: Mutex<Vec<Duration>>,
+}
+
+struct Reply {
+    url: Option<String>,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl FakeFetcher {
+    /// Answers the next request with `body`.
+    pub(crate) fn with_body(self, body: &str) -> Self {
+        self.with_response(None, &[], body)
+    }
+
+    /// Answers the next request with `body`, as if it was redirected to `url` (or served from the
+    /// requested url) with `headers`.
+    pub(crate) fn with_response(
+        self,
+        url: Option<&str>,
+        headers: &[(&str, &str)],
+        body: &str,
+    ) -> Self {
+        self.push(Ok(Reply {
+            url: url.map(str::to_string),
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            body: body.to_string(),
+        }))
+    }
+
+    /// Answers the next request with `error`.
+    pub(crate) fn with_error(self, error: EngineError) -> Self {
+        self.push(Err(error))
+    }
+
+    fn push(self, reply: Result<Reply, EngineError>) -> Self {
+        self.replies.lock().unwrap().push_back(reply);
+        self
+    }
+
+    /// The requests which were sent, in order.
+    pub(crate) fn requests(&self) -> Vec<FetchRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// The sleeps which were asked for, in order.
+    pub(crate) fn sleeps(&self) -> Vec<Duration> {
+        self.sleeps.lock().unwrap().clone()
+    }
+}
+
+#[async_trait::async_trait]
+impl Fetcher for FakeFetcher {
+    async fn fetch(&self, request: FetchRequest) -> Result<FetchedResponse, EngineError> {
+        let reply = self
+            .replies
+            .lock()
+            .unwrap()
+            .pop_front()
+            .unwrap_or_else(|| panic!("unexpected request for {}", request.url));
+        let url = request.url.clone();
+        self.requests.lock().unwrap().push(request);
+
+        let Reply {
+            url: final_url,
+            headers,
+            body,
+        } = reply?;
+        let headers = headers
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect::<Vec<_>>();
+
+        Ok(FetchedResponse {
+            response: response(final_url.as_deref().unwrap_or(&url), &headers),
+            body,
+        })
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        self.sleeps.lock().unwrap().push(duration);
+    }
+}
+
+/// The messages logged so far by every test, with their levels.
+pub(crate) fn logs() -> Vec<String> {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        log::set_logger(&CapturingLogger).unwrap();
+        log::set_max_level(LevelFilter::Trace);
+    });
+
+    LOGS.lock().unwrap().clone()
+}
+
+static LOGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+struct CapturingLogger;
+
+impl Log for CapturingLogger {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        LOGS.lock()
+            .unwrap()
+            .push(format!("{} {}", record.level(), record.args()));
+    }
+
+    fn flush(&self) {}
+}
+
+/// Replaying the cassettes in `tests/fixtures/synthetic_cassettes` through a
+/// [`StubServer`](crate::cassette::StubServer). They're written by hand in the recording format
+/// rather than recorded from the providers, so they check the requests each engine builds and how
+/// it parses a known page, not what the providers currently serve.
+pub(crate) mod cassettes {
+    use std::io::{self, Read, Write};
+
+    use http::Uri;
+
+    use super::*;
+    use crate::{
+        cassette::{Cassette, ReplayReport, replay_page},
+        egress::Egress,
+        profiles::BrowserProfile,
+    };
+
+    /// The config the cassettes were written for. The browser is fixed, so the same headers
+    /// are sent every time.
+    pub(crate) fn cassette_config() -> EngineConfig {
+        EngineConfig {
+            browser_profile: Some(BrowserProfile::Lynx),
+            ..EngineConfig::default()
+        }
+    }
+
+    /// Reads the cassette `name` from `tests/fixtures/synthetic_cassettes`.
+    pub(crate) fn load_cassette(name: &str) -> Cassette {
+        Cassette::load(format!(
+            "{}/tests/fixtures/synthetic_cassettes/{name}.json",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap()
+    }
+
+    /// Replays `cassette` with `engine`, searching for `rust`.
+    pub(crate) async fn replay(engine: &impl RichEngine, cassette: &Cassette) -> ReplayReport {
+        replay_page(engine, &TcpFetcher, "rust", &options(), cassette)
+            .await
+            .unwrap()
+    }
+
+    /// Replays the cassette `name` with `engine`, and checks that the engine sent the
+    /// cassette's requests and parsed its results.
+    pub(crate) async fn replay_cassette(engine: &impl RichEngine, name: &str) -> ParsedPage {
+        let report = replay(engine, &load_cassette(name)).await;
+        assert!(report.mismatches.is_empty(), "{:#?}", report.mismatches);
+        assert!(!report.page.results.is_empty());
+
+        report.page
+    }
+
+    /// Sends plain http requests over the connections from [`Egress::connect`], following
+    /// redirects.
+    struct TcpFetcher;
+
+    #[async_trait::async_trait]
+    impl Fetcher for TcpFetcher {
+        async fn fetch(&self, request: FetchRequest) -> Result<FetchedResponse, EngineError> {
+            let mut url = request.url.clone();
+
+            for _ in 0..MAX_REDIRECTS {
+                // Connecting blocks, so it's kept off the executor.
+                let (egress, request_url, request_headers) =
+                    (request.egress.clone(), url.clone(), request.headers.clone());
+                let (status, headers, body) = tokio::task::spawn_blocking(move || {
+                    get(&egress, &request_url, &request_headers)
+                })
+                .await
+                .unwrap()
+                .map_err(|error| EngineError::Transport(error.into()))?;
+
+                match headers.get(http::header::LOCATION) {
+                    Some(location) if status.is_redirection() => {
+                        let uri = url.parse::<Uri>().unwrap();
+                        url = format!(
+                            "http://{}{}",
+                            uri.authority().unwrap(),
+                            location.to_str().unwrap()
+                        );
+                    }
+                    _ => {
+                        return Ok(FetchedResponse {
+                            response: Response::new(status, url.parse().unwrap(), headers),
+                            body,
+                        });
+                    }
+                }
+            }
+
+            panic!("too many redirects for {}", request.url)
+        }
+
+        async fn sleep(&self, _duration: Duration) {}
+    }
+
+    fn get(
+        egress: &Egress,
+        url: &str,
+        headers: &HeaderMap,
+    ) -> io::Result<(StatusCode, HeaderMap, String)> {
+        let uri = url.parse::<Uri>().unwrap();
+        let mut stream = egress.connect(uri.host().unwrap(), uri.port_u16().unwrap_or(80))?;
+
+        write!(
+            stream,
+            "GET {} HTTP/1.1\r\nHost: {}\r\n",
+            uri.path_and_query().unwrap(),
+            uri.authority().unwrap()
+        )?;
+        for (name, value) in headers {
+            write!(stream, "{name}: {}\r\n", value.to_str().unwrap())?;
+        }
+        write!(stream, "Connection: close\r\n\r\n")?;
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        let (head, body) = response.split_once("\r\n\r\n").unwrap_or((&response, ""));
+
+        let mut lines = head.lines();
+        let status = lines
+            .next()
+            .and_then(|this| this.split_whitespace().nth(1))
+            .and_then(|this| this.parse::<StatusCode>().ok())
+            .unwrap_or(StatusCode::BAD_GATEWAY);
+        let headers = lines
+            .filter_map(|this| this.split_once(':'))
+            .map(|(name, value)| {
+                (
+                    HeaderName::from_bytes(name.trim().as_bytes()).unwrap(),
+                    HeaderValue::from_str(value.trim()).unwrap(),
+                )
+            })
+            .collect();
+
+        Ok((status, headers, body.to_string()))
+    }
+
+    const MAX_REDIRECTS: usize = 5;
+}
@@ -0,0 +1,235 @@
+//! Warming up a session by visiting an engine's homepage before searching, like a browser would,
+//! to collect the cookies and tokens its provider expects search requests to carry.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use quaero_shared::models::search::{SearchError, SearchOptions};
+
+use crate::{
+    engine::RichEngine,
+    error::EngineError,
+    fetch::{FetchedResponse, Fetcher, send},
+};
+
+/// The warm-up session of a single engine. Cloning it shares the session.
+///
+/// The homepage is fetched before the first search, and again once the session is older than its
+/// time to live or the provider served a captcha.
+#[derive(Debug, Clone)]
+pub struct WarmUp {
+    ttl: Duration,
+    session: Arc<Mutex<Option<Session>>>,
+}
+
+#[derive(Debug)]
+struct Session {
+    tokens: HashMap<String, String>,
+    started_at: Instant,
+}
+
+impl WarmUp {
+    /// Creates a warm-up whose sessions are refreshed after `ttl`.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            session: Arc::default(),
+        }
+    }
+
+    /// The token `name` collected from the homepage, if the session has one.
+    pub fn token(&self, name: &str) -> Option<String> {
+        self.lock()
+            .as_ref()
+            .and_then(|this| this.tokens.get(name).cloned())
+    }
+
+    /// Ends the session, so the homepage is fetched again before the next search.
+    pub fn invalidate(&self) {
+        *self.lock() = None;
+    }
+
+    /// Whether there's no session, or it has expired.
+    pub fn needs_refresh(&self) -> bool {
+        self.lock()
+            .as_ref()
+            .is_none_or(|this| this.started_at.elapsed() >= self.ttl)
+    }
+
+    fn start_session(&self, tokens: HashMap<String, String>) {
+        *self.lock() = Some(Session {
+            tokens,
+            started_at: Instant::now(),
+        });
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<Session>> {
+        self.session.lock().unwrap_or_else(|this| this.into_inner())
+    }
+}
+
+/// Fetches `engine`'s homepage if its warm-up session needs refreshing, keeping the cookies the
+/// provider sets and the tokens the engine finds on the page.
+/// Does nothing for engines without a [`WarmUp`].
+pub(crate) async fn warm_up<E, F>(
+    engine: &E,
+    fetcher: &F,
+    options: &SearchOptions,
+) -> Result<(), EngineError>
+where
+    E: RichEngine + ?Sized,
+    F: Fetcher + ?Sized,
+{
+    let config = engine.config();
+    let Some(warm_up) = config.warm_up.as_ref().filter(|this| this.needs_refresh()) else {
+        return Ok(());
+    };
+
+    let FetchedResponse { response, body } =
+        send(engine, fetcher, engine.homepage().to_string(), options).await?;
+    engine.validate_response(&response)?;
+
+    warm_up.start_session(engine.warm_up_tokens(&body).into_iter().collect());
+
+    Ok(())
+}
+
+/// Ends `engine`'s warm-up session if `error` shows the provider is blocking it, along with the
+/// cookies which got it blocked.
+pub(crate) fn end_blocked_session<E: RichEngine + ?Sized>(engine: &E, error: &SearchError) {
+    let config = engine.config();
+    let Some(warm_up) = &config.warm_up else {
+        return;
+    };
+
+    if matches!(error, SearchError::Captcha) {
+        warm_up.invalidate();
+
+        if let Some(cookie_jar) = &config.cookie_jar {
+            cookie_jar.clear();
+        }
+    }
+}
+
+/// The value of the `<input>` named `name` in `html`, e.g. a hidden form field.
+pub(crate) fn input_value(html: &str, name: &str) -> Option<String> {
+    let name_attribute = format!("name=\"{name}\"");
+
+    html.split("<input")
+        .skip(1)
+        .filter_map(|this| this.split_once('>').map(|(attributes, _)| attributes))
+        .find(|this| this.contains(&name_attribute))
+        .and_then(|this| this.split_once("value=\""))
+        .and_then(|(_, value)| value.split_once('"'))
+        .map(|(value, _)| html_escape::decode_html_entities(value).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        config::EngineConfig,
+        cookies::CookieJar,
+        fetch::fetch_page,
+        testing::{FakeFetcher, TestEngine, options},
+    };
+
+    fn warmed_up_engine(ttl: Duration) -> TestEngine {
+        TestEngine {
+            config: EngineConfig {
+                cookie_jar: Some(CookieJar::new()),
+                warm_up: Some(WarmUp::new(ttl)),
+                ..EngineConfig::default()
+            },
+        }
+    }
+
+    fn urls(fetcher: &FakeFetcher) -> Vec<String> {
+        fetcher
+            .requests()
+            .into_iter()
+            .map(|this| this.url)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn fetches_the_homepage_before_the_first_search() {
+        let engine = warmed_up_engine(Duration::from_secs(60));
+        let fetcher = FakeFetcher::default()
+            .with_response(None, &[("set-cookie", "session=1")], "")
+            .with_body("https://a.test A")
+            .with_body("https://b.test B");
+
+        fetch_page(&engine, &fetcher, "rust", &options())
+            .await
+            .unwrap();
+        fetch_page(&engine, &fetcher, "rust", &options())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            urls(&fetcher),
+            [
+                "http://search.test/",
+                "http://search.test/search?q=rust&page=0",
+                "http://search.test/search?q=rust&page=0"
+            ]
+        );
+        let cookie_jar = engine.config.cookie_jar.as_ref().unwrap();
+        assert_eq!(
+            cookie_jar.get("http://search.test/", "session").as_deref(),
+            Some("1")
+        );
+    }
+
+    #[tokio::test]
+    async fn fetches_the_homepage_again_once_the_session_expires() {
+        let engine = warmed_up_engine(Duration::ZERO);
+        let fetcher = FakeFetcher::default()
+            .with_body("")
+            .with_body("https://a.test A")
+            .with_body("")
+            .with_body("https://b.test B");
+
+        fetch_page(&engine, &fetcher, "rust", &options())
+            .await
+            .unwrap();
+        fetch_page(&engine, &fetcher, "rust", &options())
+            .await
+            .unwrap();
+
+        assert_eq!(fetcher.requests().len(), 4);
+        assert_eq!(fetcher.requests()[2].url, "http://search.test/");
+    }
+
+    #[tokio::test]
+    async fn ends_the_session_and_clears_its_cookies_when_blocked() {
+        let engine = warmed_up_engine(Duration::from_secs(60));
+        let fetcher = FakeFetcher::default()
+            .with_response(None, &[("set-cookie", "session=1")], "")
+            .with_response(Some("http://search.test/captcha"), &[], "");
+
+        let error = fetch_page(&engine, &fetcher, "rust", &options())
+            .await
+            .unwrap_err();
+
+        assert!(error.is_blocked());
+        assert!(engine.config.warm_up.as_ref().unwrap().needs_refresh());
+        let cookie_jar = engine.config.cookie_jar.as_ref().unwrap();
+        assert_eq!(cookie_jar.get("http://search.test/", "session"), None);
+    }
+
+    #[test]
+    fn reads_the_value_of_a_named_input() {
+        let html = r#"<form><input type="text" name="q" value="rust">
+            <input type="hidden" name="form" value="a&amp;b"></form>"#;
+
+        assert_eq!(input_value(html, "form").as_deref(), Some("a&b"));
+        assert_eq!(input_value(html, "q").as_deref(), Some("rust"));
+        assert_eq!(input_value(html, "missing"), None);
+    }
+}
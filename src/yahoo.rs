@@ -1,26 +1,46 @@
 use std::borrow::Cow;
 
+use anyhttp::Response;
 use chrono::Duration;
 use html_hybrid_parser::{ClassName, Node, Query, QueryClassNames, class_names_any};
-use http::{
-    HeaderMap, HeaderValue,
-    header::{ACCEPT, REFERER, USER_AGENT},
-};
+use http::HeaderMap;
 
 use quaero_shared::models::{
     engine::{Engine, TaggedEngine},
     search::{SafeSearch, SearchError, SearchOptions, SearchResult},
-    user_agent::UserAgent,
 };
 use query_parameters::query_params;
 
+use crate::{
+    config::EngineConfig,
+    counts::parse_total,
+    engine::{RichEngine, parse_for_quaero},
+    error::{ParseDiagnostics, ParseError},
+    health::{Field, ParseHealth, SelectorHits, Selectors, first_match},
+    overrides::AnyOf,
+    pacing::RequestBudget,
+    profiles::BrowserProfile,
+    results::{
+        ParsedPage, RichSearchResult, Sitelink, ad_positions, filter_tracking_params_in_url,
+    },
+    spans::{align_spans, collect_spans},
+};
+
 /// An engine which parses search results from Yahoo.
-pub struct YahooEngine;
+#[derive(Default)]
+pub struct YahooEngine {
+    config: EngineConfig,
+}
 
 impl YahooEngine {
     /// Creates a new Yahoo engine.
     pub fn new() -> TaggedEngine {
-        TaggedEngine::new(Self {})
+        Self::with_config(EngineConfig::default())
+    }
+
+    /// Creates a new Yahoo engine with a custom config.
+    pub fn with_config(config: EngineConfig) -> TaggedEngine {
+        TaggedEngine::new(Self { config })
     }
 }
 
@@ -40,9 +60,11 @@ impl Engine for YahooEngine {
             ..
         }: &SearchOptions,
     ) -> Result<String, SearchError> {
+        let query = self.render_query(query);
+
         // Turns the page number into the index of the first result.
         // Page 0 is `1`, Page 1 is `11`, Page 2 is `21`, etc...
-        let results_per_page = 10;
+        let results_per_page = self.results_per_page();
         let page_start_idx = results_per_page * page_num + 1;
 
         let safe_search_param = match safe_search {
@@ -73,55 +95,232 @@ impl Engine for YahooEngine {
     }
 
     fn headers(&self, headers: &mut HeaderMap, _options: &SearchOptions) {
-        headers.insert(USER_AGENT, UserAgent::random_no_js().into());
-        headers.insert(
-            ACCEPT,
-            HeaderValue::from_static("application/x-www-form-urlencoded"),
-        );
-        headers.append(REFERER, HeaderValue::from_static("https://google.com/"));
+        self.config
+            .browser_profile
+            .unwrap_or_else(BrowserProfile::random_no_js)
+            .apply(headers, self.homepage(), Some("https://google.com/"));
+        self.config.apply_cookies(headers, self.homepage(), "");
+    }
+
+    fn validate_response(&self, response: &Response) -> Result<(), SearchError> {
+        self.config.store_cookies(response);
+        Ok(())
     }
 
     fn parse<'a>(&self, response_text: String) -> Result<Vec<(String, SearchResult)>, SearchError> {
+        parse_for_quaero(self, response_text)
+    }
+}
+
+impl RichEngine for YahooEngine {
+    fn id(&self) -> &str {
+        "yahoo"
+    }
+
+    fn config(&self) -> &EngineConfig {
+        &self.config
+    }
+
+    fn request_budget(&self) -> RequestBudget {
+        REQUEST_BUDGET
+    }
+
+    fn parse_rich(&self, response_text: String) -> Result<ParsedPage, ParseError> {
         let dom = html_hybrid_parser::Parser::fast_but_constrained(&response_text);
         let parser = dom.parser();
 
+        let overrides = self.config.selectors(self.id());
+        let search_results_wrapper_classes = overrides.classes(
+            "SEARCH_RESULTS_WRAPPER_CLASSES",
+            &SEARCH_RESULTS_WRAPPER_CLASSES,
+        );
+        let search_result_classes =
+            overrides.classes("SEARCH_RESULT_CLASSES", &SEARCH_RESULT_CLASSES);
+        let title_classes = overrides.classes("TITLE_CLASSES", &TITLE_CLASSES);
+        let summary_classes = overrides.classes("SUMMARY_CLASSES", &SUMMARY_CLASSES);
+
         let Some(results) =
-            dom.get_first_node_with_classes(&SEARCH_RESULTS_WRAPPER_CLASSES, parser)
+            dom.get_first_node_with_classes(&search_results_wrapper_classes, parser)
         else {
-            return Err(SearchError::NoResultsFound);
+            return Err(ParseError::SelectorMissing(ParseDiagnostics::new(
+                self.id(),
+                search_results_wrapper_classes.selector(".searchCenterMiddle"),
+                &response_text,
+            )));
         };
 
-        let nodes = results
-            .get_nodes_with_classes(&SEARCH_RESULT_CLASSES, parser)
-            // Removes any nodes which:
-            // - Have the `AlsoTry_M` class (search suggestions).
-            .filter(|this| !SEARCH_RESULT_BLOCKLISTED_CLASSES.matches(this.class()));
+        // Removes any nodes which have the `AlsoTry_M` class (search suggestions).
+        let containers_in_wrapper = results
+            .get_nodes_with_classes(&AnyOf(&search_result_classes, &AD_CLASSES), parser)
+            .filter(|this| !SEARCH_RESULT_BLOCKLISTED_CLASSES.matches(this.class()))
+            .map(|this| (AD_CLASSES.matches(this.class()), this))
+            .collect::<Vec<_>>();
 
-        Ok(nodes
-            .filter_map(|this| {
-                let Some(title_node) = this.get_first_node_with_classes(&TITLE_CLASSES, parser)
+        // Ads are handled separately below.
+        let nodes = containers_in_wrapper
+            .iter()
+            .filter(|(is_ad, _)| !is_ad)
+            .map(|(_, this)| *this)
+            .collect::<Vec<_>>();
+        let containers = nodes.len();
+        let mut selector_hits = SelectorHits::default();
+
+        let results = nodes
+            .into_iter()
+            .map(|this| {
+                let Some(title_node) = this.get_first_node_with_classes(&title_classes, parser)
                 else {
                     return None;
                 };
 
-                let title = title_node
-                    .children_raw_text(parser)
-                    .map(|this| this.to_string())
+                let title = first_match!(selector_hits, Field::Title, {
+                    "TITLE_CLASSES" => title_node
+                        .children_raw_text(parser)
+                        .map(|this| this.to_string()),
+                })
+                .unwrap_or_default();
+
+                let url = first_match!(selector_hits, Field::Url, {
+                    "TITLE_CLASSES" => title_node
+                        .get_href()
+                        .map(|this| clean_url(this.to_string())),
+                })
+                .unwrap_or_default();
+
+                let summary_spans = this
+                    .get_first_node_with_classes(&summary_classes, parser)
+                    .map(|this| {
+                        collect_spans(
+                            this.get_child_nodes(parser)
+                                .map(|this| (this.tag_name(), this.text(parser))),
+                        )
+                    })
                     .unwrap_or_default();
 
-                let url = title_node
-                    .get_href()
-                    .map(|this| clean_url(this.to_string()))
+                let summary = first_match!(selector_hits, Field::Summary, {
+                    "SUMMARY_CLASSES" => this
+                        .get_first_node_with_classes(&summary_classes, parser)
+                        .and_then(|this| this.text(parser).map(|this| this.to_string())),
+                })
+                .unwrap_or_default();
+
+                // Deep links are shown as a list underneath the main result.
+                let sitelinks = this
+                    .get_first_node_with_classes(&SITELINKS_WRAPPER_CLASSES, parser)
+                    .and_then(|this| this.get_first_node_with_tag("ul", parser))
+                    .map(|this| {
+                        this.get_child_nodes(parser)
+                            .filter_map(|this| {
+                                let link = this.get_first_node_with_tag("a", parser)?;
+
+                                let title = link.text(parser)?.trim().to_string();
+                                let url = clean_url(link.get_href()?.to_string());
+
+                                let description = this
+                                    .get_first_node_with_classes(&summary_classes, parser)
+                                    .and_then(|this| {
+                                        this.text(parser).map(|this| this.to_string())
+                                    });
+
+                                Some(Sitelink::new(
+                                    title,
+                                    &url,
+                                    description,
+                                    filter_tracking_params_in_url,
+                                ))
+                            })
+                            .collect()
+                    })
                     .unwrap_or_default();
 
+                let summary_spans = align_spans(summary_spans, &summary);
+
+                Some(RichSearchResult {
+                    summary_spans,
+                    sitelinks,
+                    ..RichSearchResult::new(title, url, summary)
+                })
+            })
+            .collect::<Vec<_>>();
+
+        // Ads outside of the results wrapper are shown above or below all of the results.
+        let mut ad_positions_in_wrapper = ad_positions(
+            &results,
+            containers_in_wrapper.iter().map(|(is_ad, _)| *is_ad),
+        )
+        .into_iter();
+        let shown = results.iter().flatten().count();
+        let mut past_wrapper = false;
+        let ad_positions = dom
+            .get_nodes_with_classes(&AnyOf(&search_results_wrapper_classes, &AD_CLASSES), parser)
+            .filter_map(|this| {
+                if !AD_CLASSES.matches(this.class()) {
+                    past_wrapper = true;
+                    return None;
+                }
+
+                Some(if past_wrapper {
+                    ad_positions_in_wrapper.next().unwrap_or(shown)
+                } else {
+                    0
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut page = ParsedPage::new(results.into_iter().flatten().collect());
+        page.health = ParseHealth::new(&SELECTORS, &overrides, containers, selector_hits);
+
+        // Ads are shown in lists above and below the results as well as amongst them.
+        // A list holds each ad as an item, while an ad amongst the results stands alone.
+        let ads = dom
+            .get_nodes_with_classes(&AD_CLASSES, parser)
+            .zip(ad_positions)
+            .flat_map(|(this, position)| {
+                let is_list = |tag: Option<Cow<'_, str>>| {
+                    tag.is_some_and(|this| this == "ol" || this == "ul")
+                };
+
+                Some(this)
+                    .filter(|this| is_list(this.tag_name()))
+                    .or_else(|| {
+                        ["ol", "ul"]
+                            .into_iter()
+                            .find_map(|tag| this.get_first_child_node_with_tag(tag, parser))
+                    })
+                    .map(|this| this.get_child_nodes(parser).collect::<Vec<_>>())
+                    .unwrap_or_else(|| vec![this])
+                    .into_iter()
+                    .map(move |this| (position, this))
+            })
+            .filter_map(|(position, this)| {
+                let link = this.get_first_node_with_tag("a", parser)?;
+
+                let title = link.text(parser)?.trim().to_string();
+                let url = clean_url(link.get_href()?.to_string());
+
                 let summary = this
-                    .get_first_node_with_classes(&SUMMARY_CLASSES, parser)
+                    .get_first_node_with_classes(&summary_classes, parser)
                     .and_then(|this| this.text(parser).map(|this| this.to_string()))
                     .unwrap_or_default();
 
-                Some(SearchResult::new(title, url, summary))
-            })
-            .collect())
+                Some((position, RichSearchResult::new(title, url, summary)))
+            });
+        page.add_ads(ads, self.config.ads);
+
+        // The pagination ends with the number of results (e.g. `About 1,230,000 search results`).
+        let pagination = dom.get_first_node_with_classes(&PAGINATION_CLASSES, parser);
+
+        page.estimated_total = pagination
+            .as_ref()
+            .and_then(|this| this.get_first_node_with_tag("span", parser))
+            .and_then(|this| this.text(parser).and_then(|this| parse_total(&this)));
+
+        page.has_next_page = Some(pagination.is_some_and(|this| {
+            this.get_first_node_with_classes(&NEXT_PAGE_CLASSES, parser)
+                .is_some()
+        }));
+
+        Ok(page)
     }
 }
 
@@ -138,15 +337,197 @@ fn clean_url(input_url: String) -> String {
 
 const SEARCH_RESULTS_WRAPPER_CLASSES: ClassName = class_names_any! { "searchCenterMiddle" };
 
+const REQUEST_BUDGET: RequestBudget = RequestBudget::per_minute(20);
+
+pub(crate) const SELECTORS: Selectors = Selectors {
+    container: "{SEARCH_RESULTS_WRAPPER_CLASSES} {SEARCH_RESULT_CLASSES}",
+    title: "{TITLE_CLASSES}",
+    url: "{TITLE_CLASSES} a",
+    summary: "{SUMMARY_CLASSES}",
+    classes: &[
+        ("SEARCH_RESULTS_WRAPPER_CLASSES", ".searchCenterMiddle"),
+        ("SEARCH_RESULT_CLASSES", ".dd"),
+        ("TITLE_CLASSES", ".s-title"),
+        ("SUMMARY_CLASSES", ".s-desc"),
+    ],
+};
+
 const SEARCH_RESULT_CLASSES: ClassName = class_names_any! { "dd" };
 const SEARCH_RESULT_BLOCKLISTED_CLASSES: ClassName = class_names_any! { "AlsoTry_M" };
 
+const AD_CLASSES: ClassName = class_names_any! { "ads" };
+
 const TITLE_CLASSES: ClassName = class_names_any! { "s-title" };
 
 const SUMMARY_CLASSES: ClassName = class_names_any! { "s-desc" };
 
+const SITELINKS_WRAPPER_CLASSES: ClassName = class_names_any! { "compDlink" };
+
+const PAGINATION_CLASSES: ClassName = class_names_any! { "compPagination" };
+const NEXT_PAGE_CLASSES: ClassName = class_names_any! { "next" };
+
 const DATE_TIME_PRESETS: [(Duration, &'static str); 3] = [
     (Duration::hours(24), "d"),
     (Duration::weeks(1), "w"),
     (Duration::days(30), "m"),
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        config::AdHandling,
+        testing::{logs, options, selector_overrides},
+    };
+
+    const CONSENT_PAGE: &str =
+        "<html><head><title>Yahoo</title></head><body><p>Before you continue</p></body></html>";
+
+    fn diagnostics(engine: &YahooEngine) -> ParseDiagnostics {
+        match engine.parse_rich(CONSENT_PAGE.to_string()) {
+            Err(ParseError::SelectorMissing(diagnostics)) => diagnostics,
+            other => panic!("expected a missing selector, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn parses_deep_links_under_a_result() {
+        let page = YahooEngine::default()
+            .parse_rich(
+                r#"<html><body><div class="searchCenterMiddle">
+                <div class="dd algo"><h3 class="s-title"><a href="https://www.rust-lang.org/">Rust</a></h3><p class="s-desc">A language empowering everyone.</p>
+                    <div class="compDlink"><ul>
+                        <li><a href="https://www.rust-lang.org/learn">Learn</a><p class="s-desc">Get started with Rust.</p></li>
+                        <li><a href="https://www.rust-lang.org/tools/install">Install</a></li>
+                    </ul></div></div>
+                </div></body></html>"#
+                    .to_string(),
+            )
+            .unwrap();
+
+        let result = &page.results[0];
+        assert_eq!(result.summary, "A language empowering everyone.");
+        let sitelinks = result
+            .sitelinks
+            .iter()
+            .map(|this| (this.title.as_str(), this.description.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sitelinks,
+            [("Learn", Some("Get started with Rust.")), ("Install", None)]
+        );
+    }
+
+    #[test]
+    fn reports_the_missing_results_wrapper() {
+        let diagnostics = diagnostics(&YahooEngine::default());
+
+        assert_eq!(diagnostics.engine, "yahoo");
+        assert_eq!(diagnostics.selector, ".searchCenterMiddle");
+        assert_eq!(diagnostics.page_title.as_deref(), Some("Yahoo"));
+        assert_eq!(diagnostics.excerpt, "Before you continue");
+    }
+
+    #[test]
+    fn reports_the_overridden_results_wrapper() {
+        let engine = YahooEngine {
+            config: EngineConfig {
+                selector_overrides: Some(selector_overrides(
+                    "yahoo.SEARCH_RESULTS_WRAPPER_CLASSES = any results-v2 serp",
+                )),
+                ..EngineConfig::default()
+            },
+        };
+
+        assert_eq!(diagnostics(&engine).selector, ".results-v2, .serp");
+    }
+
+    #[test]
+    fn logs_the_missing_results_wrapper_when_parsing_for_quaero() {
+        logs();
+
+        let error = Engine::parse(&YahooEngine::default(), CONSENT_PAGE.to_string());
+
+        assert!(matches!(error, Err(SearchError::NoResultsFound)));
+        assert!(logs().contains(&format!("WARN {}", diagnostics(&YahooEngine::default()))));
+    }
+
+    fn safe_search_url(safe_search: SafeSearch) -> String {
+        let options = SearchOptions {
+            safe_search,
+            ..options()
+        };
+
+        YahooEngine::default().url("rust", &options).unwrap()
+    }
+
+    #[test]
+    fn sends_the_safe_search_level() {
+        assert!(safe_search_url(SafeSearch::Off).contains("&v=1"));
+        assert!(safe_search_url(SafeSearch::Moderate).contains("&vm=p"));
+        assert!(safe_search_url(SafeSearch::Strict).contains("&vm=r"));
+    }
+
+    #[test]
+    fn supports_every_safe_search_level() {
+        for safe_search in [SafeSearch::Off, SafeSearch::Moderate, SafeSearch::Strict] {
+            assert!(YahooEngine::default().supports_safe_search(&safe_search));
+        }
+    }
+
+    fn ads(handling: AdHandling) -> ParsedPage {
+        let engine = YahooEngine {
+            config: EngineConfig {
+                ads: handling,
+                ..EngineConfig::default()
+            },
+        };
+
+        engine
+            .parse_rich(include_str!("../tests/fixtures/yahoo/ads.html").to_string())
+            .unwrap()
+    }
+
+    #[test]
+    fn labels_each_ad_in_a_list_of_ads_where_it_was_shown() {
+        let page = ads(AdHandling::Label);
+
+        let titles = page
+            .results
+            .iter()
+            .map(|this| (this.title.as_str(), this.sponsored))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            titles,
+            [
+                ("Rust Training", true),
+                ("Rust Jobs", true),
+                ("Rust Programming Language", false),
+                ("Rust Books", true),
+                ("The Rust Programming Language - The Rust Book", false),
+                ("Rust Courses", true),
+            ]
+        );
+    }
+
+    #[test]
+    fn counts_each_ad_in_a_list_of_ads() {
+        let page = ads(AdHandling::Drop);
+
+        assert_eq!(page.dropped_ads, 4);
+        assert_eq!(page.results.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn replays_its_cassette() {
+        use crate::testing::cassettes::{cassette_config, replay_cassette};
+
+        let engine = YahooEngine {
+            config: cassette_config(),
+        };
+
+        let page = replay_cassette(&engine, "yahoo").await;
+        assert_eq!(page.results[0].title, "Rust Programming Language");
+    }
+}
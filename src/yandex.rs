@@ -3,25 +3,41 @@ use std::borrow::Cow;
 use anyhttp::Response;
 use chrono::Datelike;
 use html_hybrid_parser::{ClassName, Node, Query, class_names_any};
-use http::{
-    HeaderMap, HeaderValue,
-    header::{ACCEPT, REFERER, USER_AGENT},
-};
+use http::HeaderMap;
 
 use quaero_shared::models::{
     engine::{Engine, TaggedEngine},
-    search::{DateTimeRange, SearchError, SearchOptions, SearchResult},
-    user_agent::UserAgent,
+    search::{DateTimeRange, SafeSearch, SearchError, SearchOptions, SearchResult},
 };
 use query_parameters::query_params;
 
+use crate::{
+    config::EngineConfig,
+    engine::{RichEngine, parse_for_quaero},
+    error::{ParseDiagnostics, ParseError},
+    health::{Field, ParseHealth, SelectorHits, Selectors, first_match},
+    pacing::RequestBudget,
+    profiles::BrowserProfile,
+    query::QuerySyntax,
+    results::{ParsedPage, RichSearchResult},
+    spans::{align_spans, collect_spans},
+};
+
 /// An engine which parses search results from Yandex.
-pub struct YandexEngine;
+#[derive(Default)]
+pub struct YandexEngine {
+    config: EngineConfig,
+}
 
 impl YandexEngine {
     /// Creates a new Yandex engine.
     pub fn new() -> TaggedEngine {
-        TaggedEngine::new(Self {})
+        Self::with_config(EngineConfig::default())
+    }
+
+    /// Creates a new Yandex engine with a custom config.
+    pub fn with_config(config: EngineConfig) -> TaggedEngine {
+        TaggedEngine::new(Self { config })
     }
 }
 
@@ -41,15 +57,20 @@ impl Engine for YandexEngine {
             ..
         }: &SearchOptions,
     ) -> Result<String, SearchError> {
-        if safe_search.as_incrementing_usize() == 2 {
-            return Err(SearchError::SafeSearchRestriction);
-        }
+        let query = self.render_query(query);
+
+        // Strict safe search uses Yandex's family search mode,
+        // otherwise the default (moderate) filtering is used.
+        let safe_search_param = match safe_search {
+            SafeSearch::Off | SafeSearch::Moderate => "",
+            SafeSearch::Strict => "&fyandex=1",
+        };
 
         let date_time_range_params = if let Some(date_time_range) = date_time_range {
             let DateTimeRange { start, end } = date_time_range;
 
             Cow::Owned(format!(
-                "constraintid=0&within=777&from_day={}&from_month={}&from_year={}&to_day={}&to_month={}&to_year={}",
+                "&constraintid=0&within=777&from_day={}&from_month={}&from_year={}&to_day={}&to_month={}&to_year={}",
                 start.day(),
                 start.month(),
                 start.year(),
@@ -61,6 +82,12 @@ impl Engine for YandexEngine {
             Cow::Borrowed("")
         };
 
+        let results_per_page_param = self.config.results_per_page_param(
+            "numdoc",
+            DEFAULT_RESULTS_PER_PAGE,
+            MAX_RESULTS_PER_PAGE,
+        );
+
         let query_params = query_params! {
             "text" => query,
             "p" => page_num,
@@ -71,11 +98,13 @@ impl Engine for YandexEngine {
         };
 
         Ok(format!(
-            "https://yandex.com/search/site/?text={query_params}{date_time_range_params}"
+            "https://yandex.com/search/site/?{query_params}{results_per_page_param}{safe_search_param}{date_time_range_params}"
         ))
     }
 
     fn validate_response(&self, response: &Response) -> Result<(), SearchError> {
+        self.config.store_cookies(response);
+
         if response.url().path().starts_with("/showcaptcha") {
             Err(SearchError::Captcha)
         } else {
@@ -84,59 +113,262 @@ impl Engine for YandexEngine {
     }
 
     fn headers(&self, headers: &mut HeaderMap, _options: &SearchOptions) {
-        headers.insert(USER_AGENT, UserAgent::random().into());
-        headers.insert(
-            ACCEPT,
-            HeaderValue::from_static("application/x-www-form-urlencoded"),
-        );
-        headers.append(REFERER, HeaderValue::from_static("https://google.com/"));
+        self.config
+            .browser_profile
+            .unwrap_or_else(BrowserProfile::random)
+            .apply(headers, self.homepage(), Some("https://google.com/"));
+        self.config.apply_cookies(headers, self.homepage(), "");
     }
 
     fn parse<'a>(&self, response_text: String) -> Result<Vec<(String, SearchResult)>, SearchError> {
+        parse_for_quaero(self, response_text)
+    }
+}
+
+impl RichEngine for YandexEngine {
+    fn id(&self) -> &str {
+        "yandex"
+    }
+
+    fn config(&self) -> &EngineConfig {
+        &self.config
+    }
+
+    fn request_budget(&self) -> RequestBudget {
+        REQUEST_BUDGET
+    }
+
+    // Yandex always filters results, so searching without safe search uses its default
+    // (moderate) filtering.
+    fn supports_safe_search(&self, safe_search: &SafeSearch) -> bool {
+        !matches!(safe_search, SafeSearch::Off)
+    }
+
+    fn query_syntax(&self) -> QuerySyntax {
+        QuerySyntax {
+            filetype: Some("mime:"),
+            title: Some("title:"),
+            or: Some("|"),
+            ..QuerySyntax::COMMON
+        }
+    }
+
+    fn results_per_page(&self) -> usize {
+        self.config
+            .results_per_page(DEFAULT_RESULTS_PER_PAGE, MAX_RESULTS_PER_PAGE)
+    }
+
+    fn parse_rich(&self, response_text: String) -> Result<ParsedPage, ParseError> {
         let dom = html_hybrid_parser::Parser::fast_but_constrained(&response_text);
         let parser = dom.parser();
 
+        let overrides = self.config.selectors(self.id());
+        let search_results_wrapper_classes = overrides.classes(
+            "SEARCH_RESULTS_WRAPPER_CLASSES",
+            &SEARCH_RESULTS_WRAPPER_CLASSES,
+        );
+        let search_result_classes =
+            overrides.classes("SEARCH_RESULT_CLASSES", &SEARCH_RESULT_CLASSES);
+        let title_classes = overrides.classes("TITLE_CLASSES", &TITLE_CLASSES);
+        let summary_classes = overrides.classes("SUMMARY_CLASSES", &SUMMARY_CLASSES);
+
         let Some(results) =
-            dom.get_first_node_with_classes(&SEARCH_RESULTS_WRAPPER_CLASSES, parser)
+            dom.get_first_node_with_classes(&search_results_wrapper_classes, parser)
         else {
-            return Err(SearchError::NoResultsFound);
+            return Err(ParseError::SelectorMissing(ParseDiagnostics::new(
+                self.id(),
+                search_results_wrapper_classes.selector(".b-serp-list"),
+                &response_text,
+            )));
         };
 
-        Ok(results
-            .get_nodes_with_classes(&SEARCH_RESULT_CLASSES, parser)
+        let nodes = results
+            .get_nodes_with_classes(&search_result_classes, parser)
+            .collect::<Vec<_>>();
+        let containers = nodes.len();
+        let mut selector_hits = SelectorHits::default();
+
+        let results = nodes
+            .into_iter()
             .filter_map(|this| {
-                let Some(title_node) = this.get_first_node_with_classes(&TITLE_CLASSES, parser)
+                let Some(title_node) = this.get_first_node_with_classes(&title_classes, parser)
                 else {
                     return None;
                 };
 
-                let title = title_node
-                    .text(parser)
-                    .map(|this| this.to_string())
-                    .unwrap_or_default();
+                let title = first_match!(selector_hits, Field::Title, {
+                    "TITLE_CLASSES" => title_node.text(parser).map(|this| this.to_string()),
+                })
+                .unwrap_or_default();
 
-                let url = title_node
-                    .get_href()
-                    .map(|this| this.to_string())
-                    .unwrap_or_default();
+                let url = first_match!(selector_hits, Field::Url, {
+                    "TITLE_CLASSES" => title_node.get_href().map(|this| this.to_string()),
+                })
+                .unwrap_or_default();
 
-                let summary = this
-                    .get_first_node_with_classes(&SUMMARY_CLASSES, parser)
-                    .and_then(|this| this.text(parser).map(|this| this.to_string()))
+                let summary_spans = this
+                    .get_first_node_with_classes(&summary_classes, parser)
+                    .map(|this| {
+                        collect_spans(
+                            this.get_child_nodes(parser)
+                                .map(|this| (this.tag_name(), this.text(parser))),
+                        )
+                    })
                     .unwrap_or_default();
 
-                Some(SearchResult::new(title, url, summary))
+                let summary = first_match!(selector_hits, Field::Summary, {
+                    "SUMMARY_CLASSES" => this
+                        .get_first_node_with_classes(&summary_classes, parser)
+                        .and_then(|this| this.text(parser).map(|this| this.to_string())),
+                })
+                .unwrap_or_default();
+
+                let summary_spans = align_spans(summary_spans, &summary);
+
+                Some(RichSearchResult {
+                    summary_spans,
+                    ..RichSearchResult::new(title, url, summary)
+                })
             })
-            .collect())
+            .collect();
+
+        let mut page = ParsedPage::new(results);
+        page.health = ParseHealth::new(&SELECTORS, &overrides, containers, selector_hits);
+
+        page.has_next_page = Some(
+            dom.get_first_node_with_classes(&NEXT_PAGE_CLASSES, parser)
+                .is_some(),
+        );
+
+        Ok(page)
     }
 }
 
 // This is the search id from searxng and 4get.
 const SEARCH_ID: &str = "3131712";
 
+const DEFAULT_RESULTS_PER_PAGE: usize = 10;
+const MAX_RESULTS_PER_PAGE: usize = 50;
+
 const SEARCH_RESULTS_WRAPPER_CLASSES: ClassName = class_names_any! { "b-serp-list" };
+const REQUEST_BUDGET: RequestBudget = RequestBudget::per_minute(10);
+
+pub(crate) const SELECTORS: Selectors = Selectors {
+    container: "{SEARCH_RESULTS_WRAPPER_CLASSES} {SEARCH_RESULT_CLASSES}",
+    title: "{TITLE_CLASSES}",
+    url: "{TITLE_CLASSES}[href]",
+    summary: "{SUMMARY_CLASSES}",
+    classes: &[
+        ("SEARCH_RESULTS_WRAPPER_CLASSES", ".b-serp-list"),
+        ("SEARCH_RESULT_CLASSES", ".b-serp-item"),
+        ("TITLE_CLASSES", ".b-serp-item__title-link"),
+        ("SUMMARY_CLASSES", ".b-serp-item__text"),
+    ],
+};
+
 const SEARCH_RESULT_CLASSES: ClassName = class_names_any! { "b-serp-item" };
 
 const TITLE_CLASSES: ClassName = class_names_any! { "b-serp-item__title-link" };
 
 const SUMMARY_CLASSES: ClassName = class_names_any! { "b-serp-item__text" };
+
+const NEXT_PAGE_CLASSES: ClassName = class_names_any! { "b-pager__next" };
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use quaero_shared::models::search::SafeSearch;
+
+    use super::*;
+
+    use crate::testing::options;
+
+    fn url(date_time_range: Option<DateTimeRange>) -> String {
+        let engine = YandexEngine {
+            config: EngineConfig::default(),
+        };
+        let options = SearchOptions {
+            page_num: 1,
+            safe_search: SafeSearch::Off,
+            date_time_range,
+        };
+
+        engine.url("rust", &options).unwrap()
+    }
+
+    #[test]
+    fn sends_the_query_once() {
+        let url = url(None);
+
+        assert!(url.starts_with("https://yandex.com/search/site/?text=rust&p=1&"));
+        assert_eq!(url.matches("text=").count(), 1);
+        assert!(url.ends_with("&searchid=3131712"));
+    }
+
+    #[test]
+    fn requests_the_configured_number_of_results_per_page() {
+        let url = |results_per_page| {
+            let engine = YandexEngine {
+                config: EngineConfig {
+                    results_per_page,
+                    ..EngineConfig::default()
+                },
+            };
+
+            engine.url("rust", &options()).unwrap()
+        };
+
+        assert!(url(Some(0)).contains("&numdoc=1"));
+        assert!(!url(None).contains("numdoc="));
+    }
+
+    #[test]
+    fn separates_the_date_range_params() {
+        let url = url(Some(DateTimeRange {
+            start: Utc.with_ymd_and_hms(2024, 2, 1, 0, 0, 0).unwrap(),
+            end: Utc.with_ymd_and_hms(2024, 3, 5, 0, 0, 0).unwrap(),
+        }));
+
+        assert!(url.ends_with(
+            "&searchid=3131712&constraintid=0&within=777\
+            &from_day=1&from_month=2&from_year=2024&to_day=5&to_month=3&to_year=2024"
+        ));
+    }
+
+    fn safe_search_url(safe_search: SafeSearch) -> String {
+        let options = SearchOptions {
+            safe_search,
+            ..options()
+        };
+
+        YandexEngine::default().url("rust", &options).unwrap()
+    }
+
+    #[test]
+    fn sends_family_search_for_strict_safe_search() {
+        assert!(!safe_search_url(SafeSearch::Off).contains("fyandex"));
+        assert!(!safe_search_url(SafeSearch::Moderate).contains("fyandex"));
+        assert!(safe_search_url(SafeSearch::Strict).contains("&fyandex=1"));
+    }
+
+    #[test]
+    fn does_not_support_turning_safe_search_off() {
+        let engine = YandexEngine::default();
+
+        assert!(!engine.supports_safe_search(&SafeSearch::Off));
+        assert!(engine.supports_safe_search(&SafeSearch::Moderate));
+        assert!(engine.supports_safe_search(&SafeSearch::Strict));
+    }
+
+    #[tokio::test]
+    async fn replays_its_cassette() {
+        use crate::testing::cassettes::{cassette_config, replay_cassette};
+
+        let engine = YandexEngine {
+            config: cassette_config(),
+        };
+
+        let page = replay_cassette(&engine, "yandex").await;
+        assert_eq!(page.results[0].title, "Rust Programming Language");
+    }
+}